@@ -1,8 +1,11 @@
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use std::time::Duration;
 
-use crate::ai::deepseek::{ChatMessage, StreamResponse};
+use crate::ai::deepseek::ChatMessage;
+use crate::ai::error::ProviderError;
+use crate::ai::traffic::TrafficLog;
+use crate::ai::{ChatEndpoint, Completion, DEFAULT_TEMPERATURE, Provider};
 
 #[derive(Debug, Clone, PartialEq, Copy, Eq, Hash)]
 pub enum AliYunModelType {
@@ -126,70 +129,20 @@ pub struct AliYunChatRequest {
     pub stream: bool,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct AliYunChatResponse {
-    pub id: String,
-    pub object: String,
-    pub created: i64,
-    pub model: String,
-    pub choices: Vec<Choice>,
-    pub usage: Usage,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Choice {
-    pub index: i32,
-    pub message: MessageResponse,
-    pub finish_reason: String,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct MessageResponse {
-    pub role: String,
-    pub content: String,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct Usage {
-    pub prompt_tokens: i32,
-    pub completion_tokens: i32,
-    pub total_tokens: i32,
-}
-
-#[derive(Debug)]
-pub enum AliYunError {
-    RequestError(String),
-    ParseError(String),
-    ApiError(String),
-    Timeout(String),
-    ConfigError(String),
-}
-
-impl std::fmt::Display for AliYunError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            AliYunError::RequestError(msg) => write!(f, "Request error: {}", msg),
-            AliYunError::ParseError(msg) => write!(f, "Parse error: {}", msg),
-            AliYunError::ApiError(msg) => write!(f, "API error: {}", msg),
-            AliYunError::Timeout(msg) => write!(f, "Timeout: {}", msg),
-            AliYunError::ConfigError(msg) => write!(f, "Config error: {}", msg),
-        }
-    }
-}
-
-impl std::error::Error for AliYunError {}
-
 #[derive(Debug, Clone)]
 pub struct AliYunClient {
     config: AliYunConfig,
     client: Client,
+    traffic: Option<TrafficLog>,
 }
 
 impl AliYunClient {
-    pub fn new(config: AliYunConfig) -> Result<Self, AliYunError> {
+    pub fn new(config: AliYunConfig) -> Result<Self, ProviderError> {
         if config.api_key.is_empty() {
-            return Err(AliYunError::ConfigError(
-                "API key cannot be empty".to_string(),
+            return Err(ProviderError::auth(
+                Provider::AliYun,
+                "empty_api_key",
+                "API key cannot be empty",
             ));
         }
 
@@ -197,13 +150,20 @@ impl AliYunClient {
             .timeout(Duration::from_secs(config.timeout_seconds))
             .build()
             .map_err(|e| {
-                AliYunError::RequestError(format!("Failed to build HTTP client: {}", e))
+                ProviderError::network(
+                    Provider::AliYun,
+                    format!("Failed to build HTTP client: {}", e),
+                )
             })?;
 
-        Ok(Self { config, client })
+        Ok(Self {
+            config,
+            client,
+            traffic: None,
+        })
     }
 
-    pub fn with_api_key(api_key: &str) -> Result<Self, AliYunError> {
+    pub fn with_api_key(api_key: &str) -> Result<Self, ProviderError> {
         let config = AliYunConfig {
             api_key: api_key.to_string(),
             ..Default::default()
@@ -214,7 +174,7 @@ impl AliYunClient {
     pub fn with_api_key_and_model(
         api_key: &str,
         model_type: AliYunModelType,
-    ) -> Result<Self, AliYunError> {
+    ) -> Result<Self, ProviderError> {
         let config = AliYunConfig {
             api_key: api_key.to_string(),
             model_type,
@@ -223,7 +183,12 @@ impl AliYunClient {
         Self::new(config)
     }
 
-    pub async fn chat(&self, messages: Vec<ChatMessage>) -> Result<String, AliYunError> {
+    pub fn with_traffic_log(mut self, traffic: TrafficLog) -> Self {
+        self.traffic = Some(traffic);
+        self
+    }
+
+    pub async fn chat(&self, messages: Vec<ChatMessage>) -> Result<String, ProviderError> {
         self.chat_with_options(messages, None, None, false).await
     }
 
@@ -233,128 +198,67 @@ impl AliYunClient {
         temperature: Option<f32>,
         max_tokens: Option<i32>,
         stream: bool,
-    ) -> Result<String, AliYunError> {
+    ) -> Result<String, ProviderError> {
         let request = AliYunChatRequest {
             model: self.config.model_type.name().to_string(),
             messages,
-            temperature: temperature.unwrap_or(0.7),
+            temperature: temperature.unwrap_or(DEFAULT_TEMPERATURE),
             max_tokens,
             stream,
         };
-        let endpoint = format!(
-            "{}/compatible-mode/v1/chat/completions",
-            self.config.base_url
-        );
         if stream {
-            return self.chat_stream(&endpoint, &request).await;
-        }
-        let response = self
-            .client
-            .post(&endpoint)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| {
-                if e.is_timeout() {
-                    AliYunError::Timeout(format!(
-                        "Request timeout after {} seconds",
-                        self.config.timeout_seconds
-                    ))
-                } else {
-                    AliYunError::RequestError(format!("Failed to send request: {}", e))
-                }
-            })?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(AliYunError::ApiError(format!(
-                "HTTP {}: {}",
-                status, error_text
-            )));
+            return self.endpoint().stream(&request, |_| {}).await;
         }
-        let chat_response: AliYunChatResponse = response
-            .json()
+        self.endpoint()
+            .complete(&request)
             .await
-            .map_err(|e| AliYunError::ParseError(format!("Failed to parse response: {}", e)))?;
-        if let Some(choice) = chat_response.choices.first() {
-            Ok(choice.message.content.clone())
-        } else {
-            Err(AliYunError::ParseError(
-                "No choices in response".to_string(),
-            ))
+            .map(|completion| completion.content)
+    }
+
+    fn endpoint(&self) -> ChatEndpoint<'_> {
+        ChatEndpoint {
+            provider: Provider::AliYun,
+            client: &self.client,
+            url: format!(
+                "{}/compatible-mode/v1/chat/completions",
+                self.config.base_url
+            ),
+            api_key: &self.config.api_key,
+            timeout_seconds: self.config.timeout_seconds,
+            traffic: self.traffic.as_ref(),
         }
     }
 
-    async fn chat_stream(
-        &self,
-        endpoint: &str,
-        request: &AliYunChatRequest,
-    ) -> Result<String, AliYunError> {
-        use futures::StreamExt;
-        let response = self
-            .client
-            .post(endpoint)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .header("Accept", "text/event-stream")
-            .json(request)
-            .send()
-            .await
-            .map_err(|e| {
-                AliYunError::RequestError(format!("Failed to send stream request: {}", e))
-            })?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(AliYunError::ApiError(format!(
-                "HTTP {}: {}",
-                status, error_text
-            )));
+    /// Puts the language instruction, if any, after the system messages at the start.
+    fn insert_language_prompt(
+        messages: &mut Vec<ChatMessage>,
+        language: Option<crate::i18n::Language>,
+    ) {
+        if let Some(language) = language {
+            let system_count = messages
+                .iter()
+                .take_while(|message| message.role == "system")
+                .count();
+            messages.insert(system_count, Self::language_prompt(language));
         }
-        let mut full_response = String::new();
-        let mut stream = response.bytes_stream();
-        while let Some(item) = stream.next().await {
-            let chunk =
-                item.map_err(|e| AliYunError::RequestError(format!("Stream error: {}", e)))?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..];
-                    if data == "[DONE]" {
-                        break;
-                    }
-                    match serde_json::from_str::<StreamResponse>(data) {
-                        Ok(stream_response) => {
-                            if let Some(choice) = stream_response.choices.first() {
-                                if let Some(content) = &choice.delta.content {
-                                    full_response.push_str(content);
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            continue;
-                        }
-                    }
-                }
-            }
+    }
+
+    fn language_prompt(language: crate::i18n::Language) -> ChatMessage {
+        let language_prompt = match language {
+            crate::i18n::Language::Chinese => "请使用中文回答。",
+            crate::i18n::Language::English => "Please respond in English only.",
+        };
+        ChatMessage {
+            role: "system".to_string(),
+            content: language_prompt.to_string(),
         }
-        Ok(full_response)
     }
 
-    pub async fn simple_chat(
-        &self,
+    fn simple_messages(
         user_message: &str,
         system_prompt: Option<&str>,
         language: crate::i18n::Language,
-    ) -> Result<String, AliYunError> {
+    ) -> Vec<ChatMessage> {
         let mut messages = Vec::new();
         if let Some(prompt) = system_prompt {
             messages.push(ChatMessage {
@@ -362,22 +266,88 @@ impl AliYunClient {
                 content: prompt.to_string(),
             });
         }
-        let language_prompt = match language {
-            crate::i18n::Language::Chinese => "请使用中文回答。",
-            crate::i18n::Language::English => "Please respond in English only.",
-        };
-        messages.push(ChatMessage {
-            role: "system".to_string(),
-            content: language_prompt.to_string(),
-        });
+        messages.push(Self::language_prompt(language));
         messages.push(ChatMessage {
             role: "user".to_string(),
             content: user_message.to_string(),
         });
-        self.chat(messages).await
+        messages
+    }
+
+    pub async fn simple_chat(
+        &self,
+        user_message: &str,
+        system_prompt: Option<&str>,
+        language: crate::i18n::Language,
+    ) -> Result<String, ProviderError> {
+        self.chat(Self::simple_messages(user_message, system_prompt, language))
+            .await
+    }
+
+    pub async fn simple_chat_stream<F>(
+        &self,
+        user_message: &str,
+        system_prompt: Option<&str>,
+        language: crate::i18n::Language,
+        on_chunk: F,
+    ) -> Result<String, ProviderError>
+    where
+        F: FnMut(String) + Send + 'static,
+    {
+        let request = AliYunChatRequest {
+            model: self.config.model_type.name().to_string(),
+            messages: Self::simple_messages(user_message, system_prompt, language),
+            temperature: DEFAULT_TEMPERATURE,
+            max_tokens: None,
+            stream: true,
+        };
+        self.endpoint().stream(&request, on_chunk).await
+    }
+
+    /// Streams a reply to a whole conversation, oldest message first. The language
+    /// instruction, when there is one, goes after any system messages at the start.
+    pub async fn chat_stream_with_options<F>(
+        &self,
+        mut messages: Vec<ChatMessage>,
+        temperature: Option<f32>,
+        language: Option<crate::i18n::Language>,
+        on_chunk: F,
+    ) -> Result<String, ProviderError>
+    where
+        F: FnMut(String) + Send + 'static,
+    {
+        Self::insert_language_prompt(&mut messages, language);
+        let request = AliYunChatRequest {
+            model: self.config.model_type.name().to_string(),
+            messages,
+            temperature: temperature.unwrap_or(DEFAULT_TEMPERATURE),
+            max_tokens: None,
+            stream: true,
+        };
+        self.endpoint().stream(&request, on_chunk).await
+    }
+
+    /// Asks for a whole reply to a conversation at once, with the token usage. The
+    /// language instruction goes in as for `chat_stream_with_options`.
+    pub async fn complete(
+        &self,
+        mut messages: Vec<ChatMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<i32>,
+        language: Option<crate::i18n::Language>,
+    ) -> Result<Completion, ProviderError> {
+        Self::insert_language_prompt(&mut messages, language);
+        let request = AliYunChatRequest {
+            model: self.config.model_type.name().to_string(),
+            messages,
+            temperature: temperature.unwrap_or(DEFAULT_TEMPERATURE),
+            max_tokens,
+            stream: false,
+        };
+        self.endpoint().complete(&request).await
     }
 
-    pub async fn test_connection(&self) -> Result<bool, AliYunError> {
+    pub async fn test_connection(&self) -> Result<bool, ProviderError> {
         let test_message = ChatMessage {
             role: "user".to_string(),
             content: "Hello, respond with 'OK' if you can hear me.".to_string(),
@@ -0,0 +1,455 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+#[cfg(any(test, feature = "mock"))]
+use crate::ai::mock::{MockBody, MockReply, MockServer, SseChunk};
+use crate::ai::wire::{
+    self, RecordedRequest, finish_sse, write_json, write_sse_chunk, write_sse_head,
+};
+use crate::ai::{Provider, Providers};
+#[cfg(any(test, feature = "mock"))]
+use std::time::Duration;
+
+pub const RECORD_ENV_VAR: &str = "AICHAT_RECORD_CASSETTE";
+pub const REPLAY_ENV_VAR: &str = "AICHAT_REPLAY_CASSETTE";
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Provider traffic captured to disk. Interactions are replayed in recording order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub provider: String,
+    pub request: CassetteRequest,
+    pub response: CassetteResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteResponse {
+    pub status: u16,
+    /// Time from sending the request to the first response byte.
+    pub delay_ms: u64,
+    #[serde(flatten)]
+    pub body: CassetteBody,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CassetteBody {
+    Json { body: String },
+    Sse { chunks: Vec<CassetteChunk> },
+}
+
+/// One network chunk of an SSE stream, with the gap since the previous chunk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteChunk {
+    pub delay_ms: u64,
+    pub data: String,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, text)
+    }
+
+    #[cfg(any(test, feature = "mock"))]
+    pub fn replies(&self) -> Vec<MockReply> {
+        self.interactions
+            .iter()
+            .map(|interaction| interaction.response.to_reply())
+            .collect()
+    }
+
+    /// Starts a mock server that serves the recorded responses in order.
+    #[cfg(any(test, feature = "mock"))]
+    pub async fn replay(&self) -> io::Result<MockServer> {
+        let server = MockServer::start().await?;
+        for reply in self.replies() {
+            server.enqueue(reply);
+        }
+        Ok(server)
+    }
+}
+
+impl CassetteRequest {
+    pub fn from_recorded(request: &RecordedRequest) -> Self {
+        Self {
+            method: request.method.clone(),
+            path: request.path.clone(),
+            headers: redact_headers(&request.headers),
+            body: request.body.clone(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl CassetteResponse {
+    pub fn to_reply(&self) -> MockReply {
+        let body = match &self.body {
+            CassetteBody::Json { body } => MockBody::Json(body.clone()),
+            CassetteBody::Sse { chunks } => MockBody::Sse(
+                chunks
+                    .iter()
+                    .map(|chunk| SseChunk {
+                        delay: Duration::from_millis(chunk.delay_ms),
+                        data: chunk.data.clone(),
+                    })
+                    .collect(),
+            ),
+        };
+        MockReply {
+            status: self.status,
+            delay: Duration::from_millis(self.delay_ms),
+            body,
+        }
+    }
+}
+
+/// Keeps only the headers that matter for replay and masks anything credential-like.
+pub fn redact_headers(headers: &[(String, String)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.to_ascii_lowercase();
+            name == "authorization"
+                || name == "content-type"
+                || name == "accept"
+                || name.contains("key")
+                || name.contains("token")
+        })
+        .map(|(name, value)| {
+            let name_lower = name.to_ascii_lowercase();
+            if name_lower == "authorization" {
+                let scheme = value.split_whitespace().next().unwrap_or_default();
+                (name.clone(), format!("{} {}", scheme, REDACTED))
+            } else if name_lower.contains("key") || name_lower.contains("token") {
+                (name.clone(), REDACTED.to_string())
+            } else {
+                (name.clone(), value.clone())
+            }
+        })
+        .collect()
+}
+
+/// Local proxies that forward provider traffic upstream and append every exchange to a
+/// cassette file.
+pub struct Recorder {
+    cassette: Arc<Mutex<Cassette>>,
+    path: PathBuf,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Recorder {
+    /// Starts one proxy per provider and returns settings that route through them.
+    pub async fn start(path: &Path, upstream: &Providers) -> io::Result<(Self, Providers)> {
+        let cassette = Arc::new(Mutex::new(Cassette::default()));
+        let path = path.to_path_buf();
+        let mut proxied = upstream.clone();
+        let mut handles = Vec::new();
+        for provider in [Provider::DeepSeek, Provider::AliYun] {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let proxy_url = format!("http://{}", listener.local_addr()?);
+            let target = upstream
+                .get(provider)
+                .base_url
+                .trim_end_matches('/')
+                .to_string();
+            let cassette = Arc::clone(&cassette);
+            let path = path.clone();
+            handles.push(tokio::spawn(async move {
+                let client = reqwest::Client::new();
+                while let Ok((stream, _)) = listener.accept().await {
+                    let proxy = ProxyTarget {
+                        provider,
+                        target: target.clone(),
+                        client: client.clone(),
+                        cassette: Arc::clone(&cassette),
+                        path: path.clone(),
+                    };
+                    tokio::spawn(async move {
+                        let _ = proxy.handle(stream).await;
+                    });
+                }
+            }));
+            match provider {
+                Provider::DeepSeek => proxied.deepseek.base_url = proxy_url,
+                Provider::AliYun => proxied.aliyun.base_url = proxy_url,
+            }
+        }
+        Ok((
+            Self {
+                cassette,
+                path,
+                handles,
+            },
+            proxied,
+        ))
+    }
+
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+struct ProxyTarget {
+    provider: Provider,
+    target: String,
+    client: reqwest::Client,
+    cassette: Arc<Mutex<Cassette>>,
+    path: PathBuf,
+}
+
+impl ProxyTarget {
+    async fn handle(self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let request = wire::read_request(&mut reader).await?;
+        let mut stream = reader.into_inner();
+        let mut builder = self
+            .client
+            .request(
+                request.method.parse().unwrap_or(reqwest::Method::POST),
+                format!("{}{}", self.target, request.path),
+            )
+            .body(request.body.clone());
+        for name in ["authorization", "content-type", "accept"] {
+            if let Some(value) = request.header(name) {
+                builder = builder.header(name, value);
+            }
+        }
+        let started = Instant::now();
+        let response = match builder.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                let body = serde_json::json!({ "error": { "message": e.to_string() } });
+                return write_json(&mut stream, 502, &body.to_string()).await;
+            }
+        };
+        let status = response.status().as_u16();
+        let delay_ms = started.elapsed().as_millis() as u64;
+        let is_sse = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        let body = if is_sse {
+            write_sse_head(&mut stream, status).await?;
+            let mut chunks = Vec::new();
+            let mut carry = Vec::new();
+            let mut last = Instant::now();
+            let mut upstream = response.bytes_stream();
+            while let Some(Ok(bytes)) = upstream.next().await {
+                carry.extend_from_slice(&bytes);
+                let data = take_complete_utf8(&mut carry);
+                write_sse_chunk(&mut stream, &data).await?;
+                chunks.push(CassetteChunk {
+                    delay_ms: last.elapsed().as_millis() as u64,
+                    data,
+                });
+                last = Instant::now();
+            }
+            finish_sse(&mut stream).await?;
+            CassetteBody::Sse { chunks }
+        } else {
+            let body = response.text().await.unwrap_or_default();
+            write_json(&mut stream, status, &body).await?;
+            CassetteBody::Json { body }
+        };
+        let interaction = Interaction {
+            provider: self.provider.name().to_string(),
+            request: CassetteRequest::from_recorded(&request),
+            response: CassetteResponse {
+                status,
+                delay_ms,
+                body,
+            },
+        };
+        let snapshot = {
+            let mut cassette = self.cassette.lock().unwrap();
+            cassette.interactions.push(interaction);
+            cassette.clone()
+        };
+        snapshot.save(&self.path)
+    }
+}
+
+/// Drains the longest valid UTF-8 prefix, leaving a split trailing character for the
+/// next chunk so cassette text stays lossless.
+fn take_complete_utf8(buffer: &mut Vec<u8>) -> String {
+    let valid = match std::str::from_utf8(buffer) {
+        Ok(_) => buffer.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => buffer.len(),
+    };
+    let taken: Vec<u8> = buffer.drain(..valid).collect();
+    String::from_utf8_lossy(&taken).into_owned()
+}
+
+/// Keeps a recording proxy or replay server alive for the lifetime of the app.
+pub enum CassetteSession {
+    Recording(Recorder),
+    #[cfg(any(test, feature = "mock"))]
+    Replaying(MockServer),
+}
+
+/// Reroutes `providers` through a recorder or a replay server when the matching
+/// environment variable names a cassette file.
+pub async fn attach_from_env(providers: &mut Providers) -> io::Result<Option<CassetteSession>> {
+    #[cfg(any(test, feature = "mock"))]
+    if let Ok(path) = std::env::var(REPLAY_ENV_VAR) {
+        let server = Cassette::load(Path::new(&path))?.replay().await?;
+        *providers = server.providers();
+        return Ok(Some(CassetteSession::Replaying(server)));
+    }
+    #[cfg(not(any(test, feature = "mock")))]
+    if std::env::var_os(REPLAY_ENV_VAR).is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} needs a build with the `mock` feature", REPLAY_ENV_VAR),
+        ));
+    }
+    if let Ok(path) = std::env::var(RECORD_ENV_VAR) {
+        let (recorder, proxied) = Recorder::start(Path::new(&path), providers).await?;
+        *providers = proxied;
+        return Ok(Some(CassetteSession::Recording(recorder)));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::deepseek::{DeepSeekClient, DeepSeekConfig, create_user_message};
+    use crate::ai::mock::stream_event;
+    use crate::testing::temp_path;
+
+    fn client(base_url: &str) -> DeepSeekClient {
+        DeepSeekClient::new(DeepSeekConfig {
+            api_key: "sk-secret-key".to_string(),
+            base_url: base_url.to_string(),
+            timeout_seconds: 5,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let upstream = MockServer::start().await.unwrap();
+        upstream.enqueue(MockReply::completion("recorded answer"));
+        upstream
+            .enqueue(MockReply::stream(&["stre", "amed"]).chunk_delay(Duration::from_millis(20)));
+        let dir = temp_path("cassette");
+        let path = dir.join("record.json");
+        let (recorder, proxied) = Recorder::start(&path, &upstream.providers()).await.unwrap();
+        let recording_client = client(&proxied.deepseek.base_url);
+        let first = recording_client.simple_chat("Hi", None).await.unwrap();
+        let second = recording_client
+            .chat_with_options(vec![create_user_message("Stream")], None, None, true)
+            .await
+            .unwrap();
+        assert_eq!(first, "recorded answer");
+        assert_eq!(second, "streamed");
+        drop(recorder);
+
+        let cassette = Cassette::load(&path).unwrap();
+        assert_eq!(cassette.interactions.len(), 2);
+        let request = &cassette.interactions[0].request;
+        assert_eq!(request.path, "/v1/chat/completions");
+        assert!(
+            !std::fs::read_to_string(&path)
+                .unwrap()
+                .contains("sk-secret-key")
+        );
+        assert!(
+            request
+                .headers
+                .iter()
+                .any(|(name, value)| name.eq_ignore_ascii_case("authorization")
+                    && value == "Bearer [REDACTED]")
+        );
+        match &cassette.interactions[1].response.body {
+            CassetteBody::Sse { chunks } => assert!(chunks.len() >= 2),
+            CassetteBody::Json { .. } => panic!("expected an SSE recording"),
+        }
+
+        let replay = cassette.replay().await.unwrap();
+        let replay_client = client(&replay.url());
+        assert_eq!(
+            replay_client.simple_chat("Hi", None).await.unwrap(),
+            "recorded answer"
+        );
+        let replayed = replay_client
+            .chat_with_options(vec![create_user_message("Stream")], None, None, true)
+            .await
+            .unwrap();
+        assert_eq!(replayed, "streamed");
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_regression_event_split_across_chunks() {
+        let cassette: Cassette =
+            serde_json::from_str(include_str!("../../tests/cassettes/split_sse_event.json"))
+                .unwrap();
+        let server = cassette.replay().await.unwrap();
+        let reply = client(&server.url())
+            .chat_with_options(vec![create_user_message("你好")], None, None, true)
+            .await
+            .unwrap();
+        assert_eq!(reply, "你好，世界");
+    }
+
+    #[test]
+    fn test_split_utf8_is_carried_over() {
+        let event = format!("data: {}\n\n", stream_event("界"));
+        let bytes = event.as_bytes();
+        let split = event.find('界').unwrap() + 1;
+        let mut buffer = bytes[..split].to_vec();
+        let head = take_complete_utf8(&mut buffer);
+        assert_eq!(buffer.len(), 1);
+        buffer.extend_from_slice(&bytes[split..]);
+        assert_eq!(head + &take_complete_utf8(&mut buffer), event);
+    }
+}
@@ -1,8 +1,11 @@
-use futures::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+use crate::ai::error::ProviderError;
+use crate::ai::traffic::TrafficLog;
+use crate::ai::{ChatEndpoint, Completion, DEFAULT_TEMPERATURE, Provider};
+
 #[derive(Debug, Clone)]
 pub struct DeepSeekConfig {
     pub api_key: String,
@@ -60,7 +63,7 @@ pub struct MessageResponse {
     pub content: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Usage {
     pub prompt_tokens: i32,
     pub completion_tokens: i32,
@@ -89,52 +92,81 @@ pub struct StreamDelta {
     pub content: Option<String>,
 }
 
-#[derive(Debug)]
-pub enum DeepSeekError {
-    RequestError(String),
-    ParseError(String),
-    ApiError(String),
-    Timeout(String),
-    ConfigError(String),
+/// Reassembles SSE lines from network chunks. An event, or a multi-byte character
+/// inside it, can be split across chunks, so bytes are held until the newline arrives.
+#[derive(Debug, Default)]
+pub struct SseLineBuffer {
+    pending: Vec<u8>,
 }
 
-impl std::fmt::Display for DeepSeekError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            DeepSeekError::RequestError(msg) => write!(f, "Request error: {}", msg),
-            DeepSeekError::ParseError(msg) => write!(f, "Parse error: {}", msg),
-            DeepSeekError::ApiError(msg) => write!(f, "API error: {}", msg),
-            DeepSeekError::Timeout(msg) => write!(f, "Timeout: {}", msg),
-            DeepSeekError::ConfigError(msg) => write!(f, "Config error: {}", msg),
+impl SseLineBuffer {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            lines.push(line.trim_end_matches(['\r', '\n']).to_string());
         }
+        lines
     }
 }
 
-impl std::error::Error for DeepSeekError {}
+#[derive(Debug, Clone, PartialEq)]
+pub enum SseEvent {
+    Delta(String),
+    Done,
+}
+
+/// Parses one SSE line of an OpenAI-compatible stream. Comments, keep-alives and
+/// malformed events yield `None`.
+pub fn parse_sse_line(line: &str) -> Option<SseEvent> {
+    let data = line.strip_prefix("data:")?.trim_start();
+    if data == "[DONE]" {
+        return Some(SseEvent::Done);
+    }
+    let stream_response = serde_json::from_str::<StreamResponse>(data).ok()?;
+    stream_response
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.delta.content)
+        .map(SseEvent::Delta)
+}
 
 #[derive(Debug, Clone)]
 pub struct DeepSeekClient {
     config: DeepSeekConfig,
     client: Client,
+    traffic: Option<TrafficLog>,
 }
 
 impl DeepSeekClient {
-    pub fn new(config: DeepSeekConfig) -> Result<Self, DeepSeekError> {
+    pub fn new(config: DeepSeekConfig) -> Result<Self, ProviderError> {
         if config.api_key.is_empty() {
-            return Err(DeepSeekError::ConfigError(
-                "API key cannot be empty".to_string(),
+            return Err(ProviderError::auth(
+                Provider::DeepSeek,
+                "empty_api_key",
+                "API key cannot be empty",
             ));
         }
         let client = Client::builder()
             .timeout(Duration::from_secs(config.timeout_seconds))
             .build()
             .map_err(|e| {
-                DeepSeekError::RequestError(format!("Failed to build HTTP client: {}", e))
+                ProviderError::network(
+                    Provider::DeepSeek,
+                    format!("Failed to build HTTP client: {}", e),
+                )
             })?;
-        Ok(Self { config, client })
+        Ok(Self {
+            config,
+            client,
+            traffic: None,
+        })
     }
 
-    pub fn with_api_key(api_key: &str) -> Result<Self, DeepSeekError> {
+    pub fn with_api_key(api_key: &str) -> Result<Self, ProviderError> {
         let config = DeepSeekConfig {
             api_key: api_key.to_string(),
             ..Default::default()
@@ -142,7 +174,12 @@ impl DeepSeekClient {
         Self::new(config)
     }
 
-    pub async fn chat(&self, messages: Vec<ChatMessage>) -> Result<String, DeepSeekError> {
+    pub fn with_traffic_log(mut self, traffic: TrafficLog) -> Self {
+        self.traffic = Some(traffic);
+        self
+    }
+
+    pub async fn chat(&self, messages: Vec<ChatMessage>) -> Result<String, ProviderError> {
         self.chat_with_options(messages, None, None, false).await
     }
 
@@ -152,123 +189,39 @@ impl DeepSeekClient {
         temperature: Option<f32>,
         max_tokens: Option<i32>,
         stream: bool,
-    ) -> Result<String, DeepSeekError> {
+    ) -> Result<String, ProviderError> {
         let request = ChatRequest {
             model: self.config.model.clone(),
             messages,
-            temperature: temperature.unwrap_or(0.7),
+            temperature: temperature.unwrap_or(DEFAULT_TEMPERATURE),
             max_tokens,
             stream,
         };
-        let endpoint = format!("{}/v1/chat/completions", self.config.base_url);
         if stream {
-            return self.chat_stream(&endpoint, &request).await;
-        }
-        let response = self
-            .client
-            .post(&endpoint)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| {
-                if e.is_timeout() {
-                    DeepSeekError::Timeout(format!(
-                        "Request timeout after {} seconds",
-                        self.config.timeout_seconds
-                    ))
-                } else {
-                    DeepSeekError::RequestError(format!("Failed to send request: {}", e))
-                }
-            })?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(DeepSeekError::ApiError(format!(
-                "HTTP {}: {}",
-                status, error_text
-            )));
+            return self.endpoint().stream(&request, |_| {}).await;
         }
-        let chat_response: ChatResponse = response
-            .json()
+        self.endpoint()
+            .complete(&request)
             .await
-            .map_err(|e| DeepSeekError::ParseError(format!("Failed to parse response: {}", e)))?;
-        if let Some(choice) = chat_response.choices.first() {
-            Ok(choice.message.content.clone())
-        } else {
-            Err(DeepSeekError::ParseError(
-                "No choices in response".to_string(),
-            ))
-        }
+            .map(|completion| completion.content)
     }
 
-    async fn chat_stream(
-        &self,
-        endpoint: &str,
-        request: &ChatRequest,
-    ) -> Result<String, DeepSeekError> {
-        let response = self
-            .client
-            .post(endpoint)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .header("Accept", "text/event-stream")
-            .json(request)
-            .send()
-            .await
-            .map_err(|e| {
-                DeepSeekError::RequestError(format!("Failed to send stream request: {}", e))
-            })?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(DeepSeekError::ApiError(format!(
-                "HTTP {}: {}",
-                status, error_text
-            )));
+    fn endpoint(&self) -> ChatEndpoint<'_> {
+        ChatEndpoint {
+            provider: Provider::DeepSeek,
+            client: &self.client,
+            url: format!("{}/v1/chat/completions", self.config.base_url),
+            api_key: &self.config.api_key,
+            timeout_seconds: self.config.timeout_seconds,
+            traffic: self.traffic.as_ref(),
         }
-        let mut full_response = String::new();
-        let mut stream = response.bytes_stream();
-        while let Some(item) = stream.next().await {
-            let chunk =
-                item.map_err(|e| DeepSeekError::RequestError(format!("Stream error: {}", e)))?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..];
-                    if data == "[DONE]" {
-                        break;
-                    }
-                    match serde_json::from_str::<StreamResponse>(data) {
-                        Ok(stream_response) => {
-                            if let Some(choice) = stream_response.choices.first() {
-                                if let Some(content) = &choice.delta.content {
-                                    full_response.push_str(content);
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        Ok(full_response)
     }
 
     pub async fn simple_chat(
         &self,
         user_message: &str,
         system_prompt: Option<&str>,
-    ) -> Result<String, DeepSeekError> {
+    ) -> Result<String, ProviderError> {
         let mut messages = Vec::new();
         if let Some(prompt) = system_prompt {
             messages.push(ChatMessage {
@@ -283,7 +236,7 @@ impl DeepSeekClient {
         self.chat(messages).await
     }
 
-    pub async fn test_connection(&self) -> Result<bool, DeepSeekError> {
+    pub async fn test_connection(&self) -> Result<bool, ProviderError> {
         let test_message = ChatMessage {
             role: "user".to_string(),
             content: "Hello, respond with 'OK' if you can hear me.".to_string(),
@@ -306,8 +259,8 @@ impl DeepSeekClient {
         &self,
         user_message: &str,
         system_prompt: Option<&str>,
-        mut on_chunk: F,
-    ) -> Result<String, DeepSeekError>
+        on_chunk: F,
+    ) -> Result<String, ProviderError>
     where
         F: FnMut(String) + Send + 'static,
     {
@@ -322,69 +275,44 @@ impl DeepSeekClient {
             role: "user".to_string(),
             content: user_message.to_string(),
         });
+        self.chat_stream_with_options(messages, None, on_chunk)
+            .await
+    }
+
+    /// Streams a reply to a whole conversation, oldest message first.
+    pub async fn chat_stream_with_options<F>(
+        &self,
+        messages: Vec<ChatMessage>,
+        temperature: Option<f32>,
+        on_chunk: F,
+    ) -> Result<String, ProviderError>
+    where
+        F: FnMut(String) + Send + 'static,
+    {
         let request = ChatRequest {
             model: self.config.model.clone(),
             messages,
-            temperature: 0.7,
+            temperature: temperature.unwrap_or(DEFAULT_TEMPERATURE),
             max_tokens: None,
             stream: true,
         };
-        let endpoint = format!("{}/v1/chat/completions", self.config.base_url);
-        let response = self
-            .client
-            .post(&endpoint)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .header("Accept", "text/event-stream")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| DeepSeekError::RequestError(format!("Failed to send request: {}", e)))?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(DeepSeekError::ApiError(format!(
-                "HTTP {}: {}",
-                status, error_text
-            )));
-        }
-        let mut full_response = String::new();
-        let mut stream = response.bytes_stream();
-        while let Some(item) = stream.next().await {
-            let chunk =
-                item.map_err(|e| DeepSeekError::RequestError(format!("Stream error: {}", e)))?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..];
-                    if data == "[DONE]" {
-                        return Ok(full_response);
-                    }
-                    if data.trim().is_empty() {
-                        continue;
-                    }
-                    match serde_json::from_str::<StreamResponse>(data) {
-                        Ok(stream_response) => {
-                            if let Some(choice) = stream_response.choices.first() {
-                                if let Some(content) = &choice.delta.content {
-                                    full_response.push_str(content);
-                                    on_chunk(content.clone());
-                                }
-                            }
-                        }
-                        Err(_e) => {
-                            if !data.contains(":") {
-                                continue;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        Ok(full_response)
+        self.endpoint().stream(&request, on_chunk).await
+    }
+    /// Asks for a whole reply to a conversation at once, with the token usage.
+    pub async fn complete(
+        &self,
+        messages: Vec<ChatMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<i32>,
+    ) -> Result<Completion, ProviderError> {
+        let request = ChatRequest {
+            model: self.config.model.clone(),
+            messages,
+            temperature: temperature.unwrap_or(DEFAULT_TEMPERATURE),
+            max_tokens,
+            stream: false,
+        };
+        self.endpoint().complete(&request).await
     }
 }
 
@@ -0,0 +1,319 @@
+use crate::ai::Provider;
+use crate::i18n::Translations;
+
+/// What the provider told us, kept alongside the classification for display and logs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorDetail {
+    pub provider: Provider,
+    pub status: Option<u16>,
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// A provider failure, classified by what the user can do about it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProviderError {
+    AuthFailed(ErrorDetail),
+    RateLimited(ErrorDetail),
+    QuotaExhausted(ErrorDetail),
+    ContextTooLong(ErrorDetail),
+    ContentFiltered(ErrorDetail),
+    Network(ErrorDetail),
+    BadResponse(ErrorDetail),
+}
+
+impl ErrorDetail {
+    pub fn new(provider: Provider, message: impl Into<String>) -> Self {
+        Self {
+            provider,
+            status: None,
+            code: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+}
+
+impl ProviderError {
+    pub fn auth(provider: Provider, code: &str, message: impl Into<String>) -> Self {
+        ProviderError::AuthFailed(ErrorDetail::new(provider, message).with_code(code))
+    }
+
+    pub fn network(provider: Provider, message: impl Into<String>) -> Self {
+        ProviderError::Network(ErrorDetail::new(provider, message))
+    }
+
+    pub fn bad_response(provider: Provider, message: impl Into<String>) -> Self {
+        ProviderError::BadResponse(ErrorDetail::new(provider, message))
+    }
+
+    /// Classifies a non-success HTTP response. Both the OpenAI-style
+    /// `{"error": {"code", "type", "message"}}` body used by DeepSeek and DashScope's
+    /// compatible mode and DashScope's native `{"code", "message"}` body are understood.
+    /// The status decides; the body only tells apart the causes of a 400 or a 429.
+    pub fn from_response(provider: Provider, status: u16, body: &str) -> Self {
+        let (code, message) = parse_error_body(body);
+        let detail = ErrorDetail {
+            provider,
+            status: Some(status),
+            code,
+            message: message.unwrap_or_else(|| body.trim().to_string()),
+        };
+        let text = format!(
+            "{} {}",
+            detail.code.as_deref().unwrap_or_default(),
+            detail.message
+        )
+        .to_lowercase();
+        let mentions = |needles: &[&str]| needles.iter().any(|needle| text.contains(needle));
+        let quota = || {
+            mentions(&[
+                "insufficient",
+                "arrearage",
+                "allocationquota",
+                "balance",
+                "billing",
+            ])
+        };
+        match status {
+            401 | 403 => ProviderError::AuthFailed(detail),
+            402 => ProviderError::QuotaExhausted(detail),
+            413 => ProviderError::ContextTooLong(detail),
+            // Providers answer 429 both for going too fast and for running out of credit.
+            429 if quota() => ProviderError::QuotaExhausted(detail),
+            429 => ProviderError::RateLimited(detail),
+            // A 400 covers most of what can be wrong with a request; the body says what.
+            400 if mentions(&[
+                "data_inspection",
+                "datainspection",
+                "content_filter",
+                "content_policy",
+                "inappropriate content",
+            ]) =>
+            {
+                ProviderError::ContentFiltered(detail)
+            }
+            400 if mentions(&[
+                "context_length",
+                "context length",
+                "maximum context",
+                "range of input length",
+                "too long",
+            ]) =>
+            {
+                ProviderError::ContextTooLong(detail)
+            }
+            400 if quota() => ProviderError::QuotaExhausted(detail),
+            400 if mentions(&["rate limit", "rate_limit", "throttling"]) => {
+                ProviderError::RateLimited(detail)
+            }
+            400 if mentions(&["invalid_api_key", "invalidapikey", "api key"]) => {
+                ProviderError::AuthFailed(detail)
+            }
+            _ => ProviderError::BadResponse(detail),
+        }
+    }
+
+    pub fn detail(&self) -> &ErrorDetail {
+        match self {
+            ProviderError::AuthFailed(detail)
+            | ProviderError::RateLimited(detail)
+            | ProviderError::QuotaExhausted(detail)
+            | ProviderError::ContextTooLong(detail)
+            | ProviderError::ContentFiltered(detail)
+            | ProviderError::Network(detail)
+            | ProviderError::BadResponse(detail) => detail,
+        }
+    }
+
+    /// Translation key suffix shared by the title and hint strings.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProviderError::AuthFailed(_) => "auth_failed",
+            ProviderError::RateLimited(_) => "rate_limited",
+            ProviderError::QuotaExhausted(_) => "quota_exhausted",
+            ProviderError::ContextTooLong(_) => "context_too_long",
+            ProviderError::ContentFiltered(_) => "content_filtered",
+            ProviderError::Network(_) => "network",
+            ProviderError::BadResponse(_) => "bad_response",
+        }
+    }
+
+    /// Title, provider detail and an actionable hint, for display in the chat.
+    pub fn localized(&self, translations: &Translations) -> String {
+        let detail = self.detail();
+        let title = translations.get(&format!("error_{}", self.kind()));
+        let hint = translations
+            .get(&format!("error_hint_{}", self.kind()))
+            .replace("{env}", detail.provider.api_key_env_var());
+        format!(
+            "{} · {}\n{}\n💡 {}",
+            title,
+            detail.provider.name(),
+            self.describe_detail(),
+            hint
+        )
+    }
+
+    fn describe_detail(&self) -> String {
+        let detail = self.detail();
+        let mut prefix = Vec::new();
+        if let Some(status) = detail.status {
+            prefix.push(format!("HTTP {}", status));
+        }
+        if let Some(code) = &detail.code {
+            prefix.push(format!("({})", code));
+        }
+        if prefix.is_empty() {
+            detail.message.clone()
+        } else {
+            format!("{}: {}", prefix.join(" "), detail.message)
+        }
+    }
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            ProviderError::AuthFailed(_) => "Authentication failed",
+            ProviderError::RateLimited(_) => "Rate limited",
+            ProviderError::QuotaExhausted(_) => "Quota exhausted",
+            ProviderError::ContextTooLong(_) => "Context too long",
+            ProviderError::ContentFiltered(_) => "Content filtered",
+            ProviderError::Network(_) => "Network error",
+            ProviderError::BadResponse(_) => "Bad response",
+        };
+        write!(
+            f,
+            "{} [{}] {}",
+            label,
+            self.detail().provider.name(),
+            self.describe_detail()
+        )
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+fn parse_error_body(body: &str) -> (Option<String>, Option<String>) {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(body) else {
+        return (None, None);
+    };
+    let source = value
+        .get("error")
+        .filter(|e| e.is_object())
+        .unwrap_or(&value);
+    let text = |key: &str| {
+        source.get(key).and_then(|v| match v {
+            serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        })
+    };
+    (text("code").or_else(|| text("type")), text("message"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Language;
+
+    fn classify(status: u16, body: &str) -> ProviderError {
+        ProviderError::from_response(Provider::AliYun, status, body)
+    }
+
+    #[test]
+    fn test_openai_style_errors_are_classified() {
+        let error = classify(
+            401,
+            r#"{"error":{"message":"Authentication Fails (no such user)","type":"authentication_error","code":"invalid_request_error"}}"#,
+        );
+        assert!(matches!(error, ProviderError::AuthFailed(_)));
+        assert_eq!(error.detail().status, Some(401));
+        assert_eq!(
+            error.detail().message,
+            "Authentication Fails (no such user)"
+        );
+        assert!(matches!(
+            classify(402, r#"{"error":{"message":"Insufficient Balance"}}"#),
+            ProviderError::QuotaExhausted(_)
+        ));
+        assert!(matches!(
+            classify(
+                400,
+                r#"{"error":{"message":"This model's maximum context length is 65536 tokens","code":"invalid_request_error"}}"#
+            ),
+            ProviderError::ContextTooLong(_)
+        ));
+    }
+
+    #[test]
+    fn test_dashscope_errors_are_classified() {
+        assert!(matches!(
+            classify(
+                429,
+                r#"{"error":{"code":"Throttling.RateQuota","message":"Requests rate limit exceeded"}}"#
+            ),
+            ProviderError::RateLimited(_)
+        ));
+        assert!(matches!(
+            classify(
+                400,
+                r#"{"code":"DataInspectionFailed","message":"Input data may contain inappropriate content.","request_id":"x"}"#
+            ),
+            ProviderError::ContentFiltered(_)
+        ));
+        assert!(matches!(
+            classify(
+                400,
+                r#"{"code":"Arrearage","message":"Access denied, please make sure your account is in good standing."}"#
+            ),
+            ProviderError::QuotaExhausted(_)
+        ));
+        assert!(matches!(
+            classify(503, "upstream unavailable"),
+            ProviderError::BadResponse(_)
+        ));
+    }
+
+    #[test]
+    fn test_status_wins_over_body_text() {
+        assert!(matches!(
+            classify(
+                401,
+                r#"{"error":{"message":"Prompt too long for this key's rate limit tier"}}"#
+            ),
+            ProviderError::AuthFailed(_)
+        ));
+        assert!(matches!(
+            classify(403, r#"{"code":"Arrearage","message":"Access denied"}"#),
+            ProviderError::AuthFailed(_)
+        ));
+        assert!(matches!(
+            classify(
+                500,
+                r#"{"error":{"message":"billing service unavailable"}}"#
+            ),
+            ProviderError::BadResponse(_)
+        ));
+        assert!(matches!(
+            classify(
+                429,
+                r#"{"error":{"message":"You exceeded your quota, check your billing","code":"insufficient_quota"}}"#
+            ),
+            ProviderError::QuotaExhausted(_)
+        ));
+    }
+
+    #[test]
+    fn test_localized_message_has_hint() {
+        let error = ProviderError::auth(Provider::DeepSeek, "missing_api_key", "API key not found");
+        let text = error.localized(&Translations::new(Language::English));
+        assert!(text.starts_with("🔑 Authentication failed · deepseek"));
+        assert!(text.contains("DEEPSEEK_API_KEY"));
+    }
+}
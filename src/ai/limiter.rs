@@ -0,0 +1,286 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+use crate::config::ProviderConfig;
+
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+
+/// Client-side limits for one provider. `None` means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+    pub max_in_flight: Option<usize>,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_in_flight: Some(DEFAULT_MAX_IN_FLIGHT),
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn unlimited() -> Self {
+        Self {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_in_flight: None,
+        }
+    }
+
+    /// Defaults overridden by the `rpm`, `tpm` and `max_in_flight` settings, which
+    /// also come from `AICHAT_<PROVIDER>_RPM`, `_TPM` and `_MAX_IN_FLIGHT`. A value of
+    /// `0` removes that limit.
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        let mut limits = Self::default();
+        if let Some(rpm) = config.rpm {
+            limits.requests_per_minute = (rpm > 0).then_some(rpm);
+        }
+        if let Some(tpm) = config.tpm {
+            limits.tokens_per_minute = (tpm > 0).then_some(tpm);
+        }
+        if let Some(max) = config.max_in_flight {
+            limits.max_in_flight = (max > 0).then_some(max as usize);
+        }
+        limits
+    }
+}
+
+/// Rough token count used to charge the tokens/minute bucket before a request is sent:
+/// about four ASCII characters per token and one token per CJK character.
+pub fn estimate_tokens(text: &str) -> u32 {
+    let (ascii, other) = text.chars().fold((0u32, 0u32), |(ascii, other), c| {
+        if c.is_ascii() {
+            (ascii + 1, other)
+        } else {
+            (ascii, other + 1)
+        }
+    });
+    ascii.div_ceil(4) + other
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    per_second: f64,
+}
+
+impl TokenBucket {
+    fn per_minute(limit: u32) -> Self {
+        Self {
+            capacity: limit as f64,
+            available: limit as f64,
+            per_second: limit as f64 / 60.0,
+        }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.available =
+            (self.available + elapsed.as_secs_f64() * self.per_second).min(self.capacity);
+    }
+
+    /// How long until `amount` is available; zero when it already is.
+    fn wait_for(&self, amount: f64) -> Duration {
+        let amount = amount.min(self.capacity);
+        if self.available >= amount {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((amount - self.available) / self.per_second)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Option<TokenBucket>,
+    tokens: Option<TokenBucket>,
+    last_refill: Instant,
+}
+
+impl Buckets {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last_refill;
+        self.last_refill = now;
+        for bucket in [&mut self.requests, &mut self.tokens].into_iter().flatten() {
+            bucket.refill(elapsed);
+        }
+    }
+
+    /// Takes one request and `tokens` tokens, or says how long to wait before retrying.
+    fn try_take(&mut self, tokens: u32) -> Result<(), Duration> {
+        self.refill();
+        let wait = [(&self.requests, 1.0), (&self.tokens, tokens as f64)]
+            .into_iter()
+            .filter_map(|(bucket, amount)| bucket.as_ref().map(|b| b.wait_for(amount)))
+            .max()
+            .unwrap_or(Duration::ZERO);
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        if let Some(bucket) = &mut self.requests {
+            bucket.available -= 1.0;
+        }
+        if let Some(bucket) = &mut self.tokens {
+            bucket.available -= (tokens as f64).min(bucket.capacity);
+        }
+        Ok(())
+    }
+}
+
+/// Shared per-provider limiter; clones share the same buckets and in-flight slots.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Arc<Mutex<Buckets>>,
+    in_flight: Option<Arc<Semaphore>>,
+}
+
+/// Held for the duration of a request; dropping it frees the in-flight slot.
+#[derive(Debug)]
+pub struct Permit {
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(RateLimits::default())
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            buckets: Arc::new(Mutex::new(Buckets {
+                requests: limits.requests_per_minute.map(TokenBucket::per_minute),
+                tokens: limits.tokens_per_minute.map(TokenBucket::per_minute),
+                last_refill: Instant::now(),
+            })),
+            in_flight: limits
+                .max_in_flight
+                .map(|max| Arc::new(Semaphore::new(max.max(1)))),
+        }
+    }
+
+    pub fn limits(&self) -> RateLimits {
+        self.limits
+    }
+
+    /// Waits for an in-flight slot and for room in both buckets. `on_wait` runs once,
+    /// the first time the request has to queue.
+    pub async fn acquire<W: FnOnce()>(&self, tokens: u32, on_wait: W) -> Permit {
+        let mut on_wait = Some(on_wait);
+        let mut notify = || {
+            if let Some(on_wait) = on_wait.take() {
+                on_wait();
+            }
+        };
+        let slot = match &self.in_flight {
+            Some(semaphore) => Some(match Arc::clone(semaphore).try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    notify();
+                    Arc::clone(semaphore)
+                        .acquire_owned()
+                        .await
+                        .expect("rate limiter semaphore is never closed")
+                }
+            }),
+            None => None,
+        };
+        loop {
+            let result = self.buckets.lock().unwrap().try_take(tokens);
+            match result {
+                Ok(()) => return Permit { _slot: slot },
+                Err(wait) => {
+                    notify();
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("hello world"), 3);
+        assert_eq!(estimate_tokens("你好"), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_per_minute_queues_excess() {
+        let limiter = RateLimiter::new(RateLimits {
+            requests_per_minute: Some(2),
+            ..RateLimits::unlimited()
+        });
+        let waits = AtomicUsize::new(0);
+        let start = tokio::time::Instant::now();
+        for _ in 0..3 {
+            limiter
+                .acquire(0, || {
+                    waits.fetch_add(1, Ordering::SeqCst);
+                })
+                .await;
+        }
+        assert_eq!(waits.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() >= Duration::from_secs(29));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tokens_per_minute_clamps_oversized_requests() {
+        let limiter = RateLimiter::new(RateLimits {
+            tokens_per_minute: Some(100),
+            ..RateLimits::unlimited()
+        });
+        limiter
+            .acquire(500, || panic!("a full bucket should not wait"))
+            .await;
+        let waited = AtomicUsize::new(0);
+        limiter
+            .acquire(50, || {
+                waited.fetch_add(1, Ordering::SeqCst);
+            })
+            .await;
+        assert_eq!(waited.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_max_in_flight_holds_until_permit_dropped() {
+        let limiter = RateLimiter::new(RateLimits {
+            max_in_flight: Some(1),
+            ..RateLimits::unlimited()
+        });
+        let first = limiter.acquire(0, || {}).await;
+        let second = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                let waited = AtomicUsize::new(0);
+                let _permit = limiter
+                    .acquire(0, || {
+                        waited.fetch_add(1, Ordering::SeqCst);
+                    })
+                    .await;
+                waited.load(Ordering::SeqCst)
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!second.is_finished());
+        drop(first);
+        assert_eq!(second.await.unwrap(), 1);
+    }
+}
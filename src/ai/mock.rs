@@ -0,0 +1,406 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::ai::limiter::{RateLimiter, RateLimits};
+use crate::ai::wire::{
+    RecordedRequest, finish_sse, read_request, write_json, write_sse_chunk, write_sse_head,
+};
+use crate::ai::{ProviderSettings, Providers};
+
+/// A local stand-in for the OpenAI-compatible chat endpoints used by DeepSeek and
+/// DashScope. Replies are scripted up front and served in order, one per request.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct MockState {
+    replies: VecDeque<MockReply>,
+    fallback: Option<MockReply>,
+    requests: Vec<RecordedRequest>,
+}
+
+#[derive(Debug, Clone)]
+pub enum MockBody {
+    Json(String),
+    Sse(Vec<SseChunk>),
+}
+
+/// One write on an SSE response. `data` is sent verbatim, so it may hold several
+/// events, half an event, or garbage.
+#[derive(Debug, Clone)]
+pub struct SseChunk {
+    pub delay: Duration,
+    pub data: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockReply {
+    pub status: u16,
+    pub delay: Duration,
+    pub body: MockBody,
+}
+
+impl MockReply {
+    pub fn completion(content: &str) -> Self {
+        let body = serde_json::json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "mock",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": content },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2 }
+        });
+        Self::json(200, &body.to_string())
+    }
+
+    pub fn stream(parts: &[&str]) -> Self {
+        let mut chunks: Vec<SseChunk> = parts
+            .iter()
+            .map(|part| SseChunk {
+                delay: Duration::ZERO,
+                data: format!("data: {}\n\n", stream_event(part)),
+            })
+            .collect();
+        chunks.push(SseChunk {
+            delay: Duration::ZERO,
+            data: "data: [DONE]\n\n".to_string(),
+        });
+        Self::sse(chunks)
+    }
+
+    pub fn raw_sse(chunks: &[&str]) -> Self {
+        Self::sse(
+            chunks
+                .iter()
+                .map(|data| SseChunk {
+                    delay: Duration::ZERO,
+                    data: data.to_string(),
+                })
+                .collect(),
+        )
+    }
+
+    pub fn error(status: u16, code: &str, message: &str) -> Self {
+        let body = serde_json::json!({
+            "error": { "message": message, "type": code, "code": code }
+        });
+        Self::json(status, &body.to_string())
+    }
+
+    pub fn json(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            delay: Duration::ZERO,
+            body: MockBody::Json(body.to_string()),
+        }
+    }
+
+    pub fn sse(chunks: Vec<SseChunk>) -> Self {
+        Self {
+            status: 200,
+            delay: Duration::ZERO,
+            body: MockBody::Sse(chunks),
+        }
+    }
+
+    /// Waits before sending the status line.
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Waits before every SSE chunk.
+    pub fn chunk_delay(mut self, delay: Duration) -> Self {
+        if let MockBody::Sse(chunks) = &mut self.body {
+            for chunk in chunks.iter_mut() {
+                chunk.delay = delay;
+            }
+        }
+        self
+    }
+}
+
+pub fn stream_event(content: &str) -> String {
+    serde_json::json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": "mock",
+        "choices": [{
+            "index": 0,
+            "delta": { "content": content },
+            "finish_reason": null
+        }]
+    })
+    .to_string()
+}
+
+impl MockServer {
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let server_state = Arc::clone(&state);
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = Arc::clone(&server_state);
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, state).await;
+                });
+            }
+        });
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Provider settings that send every provider to this server with a dummy key and
+    /// no client-side rate limits.
+    pub fn providers(&self) -> Providers {
+        let settings = || ProviderSettings {
+            base_url: self.url(),
+            timeout_seconds: 5,
+            api_key: Some("sk-mock".to_string()),
+            api_key_env: String::new(),
+            limiter: RateLimiter::new(RateLimits::unlimited()),
+        };
+        Providers {
+            deepseek: settings(),
+            aliyun: settings(),
+        }
+    }
+
+    pub fn enqueue(&self, reply: MockReply) {
+        self.state.lock().unwrap().replies.push_back(reply);
+    }
+
+    /// Served whenever the scripted queue is empty.
+    pub fn set_fallback(&self, reply: MockReply) {
+        self.state.lock().unwrap().fallback = Some(reply);
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_connection(stream: TcpStream, state: Arc<Mutex<MockState>>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let request = read_request(&mut reader).await?;
+    let is_chat = request.method == "POST" && request.path.ends_with("/chat/completions");
+    let reply = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request);
+        if is_chat {
+            state
+                .replies
+                .pop_front()
+                .or_else(|| state.fallback.clone())
+                .unwrap_or_else(|| {
+                    MockReply::error(500, "mock_exhausted", "No scripted reply left")
+                })
+        } else {
+            MockReply::error(404, "not_found", "Unknown endpoint")
+        }
+    };
+    let mut stream = reader.into_inner();
+    tokio::time::sleep(reply.delay).await;
+    match reply.body {
+        MockBody::Json(body) => write_json(&mut stream, reply.status, &body).await?,
+        MockBody::Sse(chunks) => {
+            write_sse_head(&mut stream, reply.status).await?;
+            for chunk in chunks {
+                tokio::time::sleep(chunk.delay).await;
+                write_sse_chunk(&mut stream, &chunk.data).await?;
+            }
+            finish_sse(&mut stream).await?;
+        }
+    }
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::aliyun::{AliYunClient, AliYunConfig, AliYunModelType};
+    use crate::ai::deepseek::{DeepSeekClient, DeepSeekConfig, create_user_message};
+    use crate::ai::error::ProviderError;
+    use crate::ai::{Prompt, call_real_aliyun_api, call_real_deepseek_api};
+    use crate::i18n::Language;
+
+    fn deepseek_client(server: &MockServer, timeout_seconds: u64) -> DeepSeekClient {
+        DeepSeekClient::new(DeepSeekConfig {
+            api_key: "sk-mock".to_string(),
+            base_url: server.url(),
+            timeout_seconds,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn aliyun_client(server: &MockServer) -> AliYunClient {
+        AliYunClient::new(AliYunConfig {
+            api_key: "sk-mock".to_string(),
+            model_type: AliYunModelType::QwenPlus,
+            timeout_seconds: 5,
+            base_url: server.url(),
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_deepseek_completion() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::completion("Hello from mock"));
+        let reply = deepseek_client(&server, 5)
+            .simple_chat("Hi", Some("Be brief"))
+            .await
+            .unwrap();
+        assert_eq!(reply, "Hello from mock");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/v1/chat/completions");
+        assert_eq!(requests[0].header("authorization"), Some("Bearer sk-mock"));
+        let body = requests[0].json();
+        assert_eq!(body["model"], "deepseek-chat");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "Hi");
+    }
+
+    #[tokio::test]
+    async fn test_aliyun_completion_uses_compatible_mode() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::completion("你好"));
+        let reply = aliyun_client(&server)
+            .simple_chat("Hi", None, Language::Chinese)
+            .await
+            .unwrap();
+        assert_eq!(reply, "你好");
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/compatible-mode/v1/chat/completions");
+        assert_eq!(request.json()["model"], "qwen-plus");
+        assert_eq!(request.json()["messages"][0]["content"], "请使用中文回答。");
+    }
+
+    #[tokio::test]
+    async fn test_stream_collects_deltas() {
+        let server = MockServer::start().await.unwrap();
+        server
+            .enqueue(MockReply::stream(&["Hel", "lo", "!"]).chunk_delay(Duration::from_millis(5)));
+        let reply = deepseek_client(&server, 5)
+            .chat_with_options(vec![create_user_message("Hi")], None, None, true)
+            .await
+            .unwrap();
+        assert_eq!(reply, "Hello!");
+        assert_eq!(server.requests()[0].json()["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn test_stream_skips_malformed_events() {
+        let server = MockServer::start().await.unwrap();
+        let good = format!("data: {}\n\n", stream_event("ok"));
+        server.enqueue(MockReply::raw_sse(&[
+            ": keep-alive\n\n",
+            "data: {not json}\n\n",
+            &good,
+            "data: [DONE]\n\n",
+        ]));
+        let reply = aliyun_client(&server)
+            .chat_with_options(vec![create_user_message("Hi")], None, None, true)
+            .await
+            .unwrap();
+        assert_eq!(reply, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_error_status_is_reported() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::error(
+            401,
+            "invalid_api_key",
+            "Incorrect API key",
+        ));
+        let error = deepseek_client(&server, 5)
+            .simple_chat("Hi", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ProviderError::AuthFailed(_)));
+        assert_eq!(error.detail().status, Some(401));
+        assert_eq!(error.detail().code.as_deref(), Some("invalid_api_key"));
+        assert_eq!(error.detail().message, "Incorrect API key");
+    }
+
+    #[tokio::test]
+    async fn test_slow_reply_times_out() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::completion("late").delayed(Duration::from_secs(3)));
+        let error = deepseek_client(&server, 1)
+            .simple_chat("Hi", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ProviderError::Network(_)));
+        assert!(error.detail().message.contains("timeout"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_endpoint_is_not_found() {
+        let server = MockServer::start().await.unwrap();
+        let response = reqwest::get(format!("{}/v1/models", server.url()))
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_call_real_api_helpers_use_provider_settings() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["from ", "deepseek"]));
+        server.enqueue(MockReply::stream(&["from ", "qwen"]));
+        let providers = server.providers();
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&chunks);
+        let prompt = Prompt::user("Hi");
+        let deepseek = call_real_deepseek_api(&prompt, &providers.deepseek, None, move |chunk| {
+            sink.lock().unwrap().push(chunk)
+        })
+        .await;
+        let aliyun = call_real_aliyun_api(
+            &prompt,
+            Some(Language::English),
+            AliYunModelType::QwenMax,
+            &providers.aliyun,
+            None,
+            |_| {},
+        )
+        .await;
+        assert_eq!(deepseek.unwrap(), "from deepseek");
+        assert_eq!(*chunks.lock().unwrap(), vec!["from ", "deepseek"]);
+        assert_eq!(aliyun.unwrap(), "from qwen");
+    }
+}
@@ -1,113 +1,560 @@
-use crate::ai::deepseek::DeepSeekClient;
-use crate::i18n::Language;
+use crate::ai::aliyun::AliYunConfig;
+use crate::ai::deepseek::{
+    ChatMessage, ChatResponse, DeepSeekClient, DeepSeekConfig, SseEvent, SseLineBuffer,
+    parse_sse_line,
+};
+use crate::ai::error::ProviderError;
+use crate::ai::limiter::{RateLimiter, RateLimits};
+use crate::ai::traffic::{ExchangeHandle, TrafficLog};
+use crate::ai_models::AIModel;
+use crate::config::{ProviderConfig, ProvidersConfig};
+use crate::i18n::{Language, Translations};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
 
 pub mod aliyun;
+pub mod cassette;
 pub mod deepseek;
+pub mod error;
+pub mod limiter;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod traffic;
+pub mod wire;
 
-pub async fn call_real_deepseek_api(user_input: &str, language: Language) -> String {
-    dotenv::dotenv().ok();
-    let api_key = match std::env::var("DEEPSEEK_API_KEY") {
-        Ok(key) if !key.trim().is_empty() => key,
-        Ok(_) => match language {
-            Language::Chinese => {
-                return "⚠️ DeepSeek API 密钥为空。请检查配置：\n\
-                           1. .env 文件中的 DEEPSEEK_API_KEY 不能为空\n\
-                           2. 或系统环境变量中的 DEEPSEEK_API_KEY 不能为空"
-                    .to_string();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    DeepSeek,
+    AliYun,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 2] = [Provider::DeepSeek, Provider::AliYun];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::DeepSeek => "deepseek",
+            Provider::AliYun => "aliyun",
+        }
+    }
+
+    pub fn api_key_env_var(&self) -> &'static str {
+        match self {
+            Provider::DeepSeek => "DEEPSEEK_API_KEY",
+            Provider::AliYun => "ALIYUN_API_KEY",
+        }
+    }
+
+    /// What this provider's keys look like: DeepSeek issues `sk-` and 32 hex digits,
+    /// DashScope `sk-` and at least 32 letters and digits.
+    fn key_format(&self) -> KeyFormat {
+        match self {
+            Provider::DeepSeek => KeyFormat {
+                prefix: "sk-",
+                len: 32,
+                exact: true,
+                charset: "key_charset_hex",
+                allowed: |c| c.is_ascii_hexdigit(),
+            },
+            Provider::AliYun => KeyFormat {
+                prefix: "sk-",
+                len: 32,
+                exact: false,
+                charset: "key_charset_alphanumeric",
+                allowed: |c| c.is_ascii_alphanumeric(),
+            },
+        }
+    }
+
+    /// Checks that `key` looks like one of this provider's keys, so a paste that
+    /// picked up too much or too little shows before the first request fails.
+    pub fn check_key(&self, key: &str) -> Result<(), KeyProblem> {
+        if key.is_empty() {
+            return Err(KeyProblem::Empty);
+        }
+        if key.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(KeyProblem::Whitespace);
+        }
+        let format = self.key_format();
+        let Some(body) = key.strip_prefix(format.prefix) else {
+            return Err(KeyProblem::Prefix(*self));
+        };
+        let len = body.chars().count();
+        if (format.exact && len != format.len) || len < format.len {
+            return Err(KeyProblem::Length(*self, len));
+        }
+        if !body.chars().all(format.allowed) {
+            return Err(KeyProblem::Charset(*self));
+        }
+        Ok(())
+    }
+}
+
+/// The shape of a provider's API keys, for `Provider::check_key`.
+struct KeyFormat {
+    prefix: &'static str,
+    /// Characters after the prefix: exactly this many, or at least this many.
+    len: usize,
+    exact: bool,
+    /// Translation key naming the characters allowed after the prefix.
+    charset: &'static str,
+    allowed: fn(char) -> bool,
+}
+
+/// Why a key does not look like one of its provider's, from `Provider::check_key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyProblem {
+    Empty,
+    Whitespace,
+    Prefix(Provider),
+    /// The number of characters found after the prefix.
+    Length(Provider, usize),
+    Charset(Provider),
+}
+
+impl KeyProblem {
+    /// What is wrong and what the provider's keys look like instead, for the key screen.
+    pub fn localized(&self, translations: &Translations) -> String {
+        let (key, provider, found) = match self {
+            KeyProblem::Empty => return translations.get("key_problem_empty"),
+            KeyProblem::Whitespace => return translations.get("key_problem_whitespace"),
+            KeyProblem::Prefix(provider) => ("key_problem_prefix", provider, 0),
+            KeyProblem::Length(provider, found) if provider.key_format().exact => {
+                ("key_problem_length_exact", provider, *found)
             }
-            Language::English => {
-                return "⚠️ DeepSeek API key is empty. Please check configuration:\n\
-                           1. DEEPSEEK_API_KEY in .env file must not be empty\n\
-                           2. Or DEEPSEEK_API_KEY in system environment variables must not be empty"
-                    .to_string();
+            KeyProblem::Length(provider, found) => ("key_problem_length_min", provider, *found),
+            KeyProblem::Charset(provider) => ("key_problem_charset", provider, 0),
+        };
+        let format = provider.key_format();
+        translations
+            .get(key)
+            .replace("{provider}", provider.name())
+            .replace("{prefix}", format.prefix)
+            .replace("{len}", &format.len.to_string())
+            .replace("{found}", &found.to_string())
+            .replace("{charset}", &translations.get(format.charset))
+    }
+}
+
+/// What to send for one reply: the conversation so far, oldest first and ending with
+/// the user's turn, and the sampling temperature (`None` for the provider default).
+#[derive(Debug, Clone, Default)]
+pub struct Prompt {
+    pub messages: Vec<ChatMessage>,
+    pub temperature: Option<f32>,
+}
+
+impl Prompt {
+    /// A single user message without history.
+    pub fn user(content: &str) -> Self {
+        Self {
+            messages: vec![deepseek::create_user_message(content)],
+            temperature: None,
+        }
+    }
+
+    /// The text of every message, for estimating its size.
+    pub fn text(&self) -> String {
+        self.messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// What the providers use when a session has not picked a temperature.
+pub const DEFAULT_TEMPERATURE: f32 = 0.7;
+
+/// A whole reply with the token counts the provider reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub content: String,
+    pub usage: deepseek::Usage,
+}
+
+/// An OpenAI-compatible chat completions endpoint, as both the DeepSeek and the
+/// DashScope clients talk to it: sends a request body, logs the exchange to the
+/// inspector and classifies failures as errors of `provider`.
+pub(crate) struct ChatEndpoint<'a> {
+    pub provider: Provider,
+    pub client: &'a reqwest::Client,
+    pub url: String,
+    pub api_key: &'a str,
+    pub timeout_seconds: u64,
+    pub traffic: Option<&'a TrafficLog>,
+}
+
+impl ChatEndpoint<'_> {
+    fn headers(&self, stream: bool) -> Vec<(String, String)> {
+        let mut headers = vec![
+            (
+                "Authorization".to_string(),
+                format!("Bearer {}", self.api_key),
+            ),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ];
+        if stream {
+            headers.push(("Accept".to_string(), "text/event-stream".to_string()));
+        }
+        headers
+    }
+
+    async fn send<T: Serialize>(
+        &self,
+        body: &T,
+        stream: bool,
+    ) -> Result<(reqwest::Response, ExchangeHandle), ProviderError> {
+        let headers = self.headers(stream);
+        let exchange = ExchangeHandle::begin(
+            self.traffic,
+            self.provider.name(),
+            &self.url,
+            &headers,
+            body,
+        );
+        let mut builder = self.client.post(&self.url).json(body);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        let response = builder.send().await.map_err(|e| {
+            let error = if e.is_timeout() {
+                ProviderError::network(
+                    self.provider,
+                    format!("Request timeout after {} seconds", self.timeout_seconds),
+                )
+            } else {
+                ProviderError::network(self.provider, format!("Failed to send request: {}", e))
+            };
+            exchange.fail(&error.to_string());
+            error
+        })?;
+        exchange.status(response.status().as_u16());
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            exchange.finish_with_body(&error_text);
+            return Err(ProviderError::from_response(
+                self.provider,
+                status.as_u16(),
+                &error_text,
+            ));
+        }
+        Ok((response, exchange))
+    }
+
+    /// Sends `body`, which asks for a whole reply, and returns it with the usage.
+    pub async fn complete<T: Serialize>(&self, body: &T) -> Result<Completion, ProviderError> {
+        let (response, exchange) = self.send(body, false).await?;
+        let text = response.text().await.map_err(|e| {
+            exchange.fail(&e.to_string());
+            ProviderError::bad_response(self.provider, format!("Failed to read response: {}", e))
+        })?;
+        exchange.finish_with_body(&text);
+        let chat_response: ChatResponse = serde_json::from_str(&text).map_err(|e| {
+            ProviderError::bad_response(self.provider, format!("Failed to parse response: {}", e))
+        })?;
+        match chat_response.choices.into_iter().next() {
+            Some(choice) => Ok(Completion {
+                content: choice.message.content,
+                usage: chat_response.usage,
+            }),
+            None => Err(ProviderError::bad_response(
+                self.provider,
+                "No choices in response".to_string(),
+            )),
+        }
+    }
+
+    /// Sends `body`, which asks for a streamed reply, passes each delta to `on_chunk`
+    /// and returns the full text.
+    pub async fn stream<T, F>(&self, body: &T, mut on_chunk: F) -> Result<String, ProviderError>
+    where
+        T: Serialize,
+        F: FnMut(String) + Send,
+    {
+        let (response, exchange) = self.send(body, true).await?;
+        let mut full_response = String::new();
+        let mut lines = SseLineBuffer::default();
+        let mut stream = response.bytes_stream();
+        while let Some(item) = stream.next().await {
+            let chunk = item.map_err(|e| {
+                exchange.fail(&e.to_string());
+                ProviderError::network(self.provider, format!("Stream error: {}", e))
+            })?;
+            for line in lines.push(&chunk) {
+                exchange.sse_line(&line);
+                match parse_sse_line(&line) {
+                    Some(SseEvent::Delta(content)) => {
+                        full_response.push_str(&content);
+                        on_chunk(content);
+                    }
+                    Some(SseEvent::Done) => {
+                        exchange.finish();
+                        return Ok(full_response);
+                    }
+                    None => {}
+                }
             }
-        },
-        Err(_) => match language {
-            Language::Chinese => {
-                return "⚠️ 未找到 DeepSeek API 密钥。请按以下方式配置：\n\
-                           1. 在项目根目录创建 .env 文件，内容为：\n\
-                              DEEPSEEK_API_KEY=your_api_key_here\n\
-                           2. 或在系统环境变量中设置：\n\
-                              export DEEPSEEK_API_KEY=your_api_key_here"
-                    .to_string();
+        }
+        exchange.finish();
+        Ok(full_response)
+    }
+}
+
+/// Connection settings for one provider. `api_key`, from the key store, overrides the
+/// lookup of the `api_key_env` variable. Clones share the same `limiter`, so every request to a
+/// provider draws from one budget.
+#[derive(Debug, Clone)]
+pub struct ProviderSettings {
+    pub base_url: String,
+    pub timeout_seconds: u64,
+    pub api_key: Option<String>,
+    pub api_key_env: String,
+    pub limiter: RateLimiter,
+}
+
+impl ProviderSettings {
+    /// The built-in defaults for `provider` with what `config` sets on top.
+    pub fn from_config(provider: Provider, config: &ProviderConfig) -> Self {
+        let (base_url, timeout_seconds) = match provider {
+            Provider::DeepSeek => {
+                let defaults = DeepSeekConfig::default();
+                (defaults.base_url, defaults.timeout_seconds)
             }
-            Language::English => {
-                return "⚠️ DeepSeek API key not found. Please configure as follows:\n\
-                           1. Create .env file in project root with content:\n\
-                              DEEPSEEK_API_KEY=your_api_key_here\n\
-                           2. Or set in system environment variables:\n\
-                              export DEEPSEEK_API_KEY=your_api_key_here"
-                    .to_string();
+            Provider::AliYun => {
+                let defaults = AliYunConfig::default();
+                (defaults.base_url, defaults.timeout_seconds)
             }
-        },
-    };
-    match DeepSeekClient::with_api_key(&api_key) {
-        Ok(client) => match client.simple_chat(user_input, None).await {
-            Ok(response) => response,
-            Err(e) => match language {
-                Language::Chinese => format!("⚠️ API调用失败: {}", e),
-                Language::English => format!("⚠️ API call failed: {}", e),
-            },
-        },
-        Err(e) => match language {
-            Language::Chinese => format!("⚠️ 客户端创建失败: {}", e),
-            Language::English => format!("⚠️ Client creation failed: {}", e),
-        },
+        };
+        Self {
+            base_url: config.base_url.clone().unwrap_or(base_url),
+            timeout_seconds: config.timeout_seconds.unwrap_or(timeout_seconds),
+            api_key: None,
+            api_key_env: config
+                .api_key_env
+                .clone()
+                .unwrap_or_else(|| provider.api_key_env_var().to_string()),
+            limiter: RateLimiter::new(RateLimits::from_config(config)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Providers {
+    pub deepseek: ProviderSettings,
+    pub aliyun: ProviderSettings,
+}
+
+impl Default for Providers {
+    fn default() -> Self {
+        Self::from_config(&ProvidersConfig::default())
+    }
+}
+
+impl Providers {
+    pub fn from_config(config: &ProvidersConfig) -> Self {
+        Self {
+            deepseek: ProviderSettings::from_config(Provider::DeepSeek, &config.deepseek),
+            aliyun: ProviderSettings::from_config(Provider::AliYun, &config.aliyun),
+        }
+    }
+
+    pub fn get(&self, provider: Provider) -> &ProviderSettings {
+        match provider {
+            Provider::DeepSeek => &self.deepseek,
+            Provider::AliYun => &self.aliyun,
+        }
+    }
+
+    pub fn get_mut(&mut self, provider: Provider) -> &mut ProviderSettings {
+        match provider {
+            Provider::DeepSeek => &mut self.deepseek,
+            Provider::AliYun => &mut self.aliyun,
+        }
+    }
+
+    /// The settings of the provider serving `model`, if it has a real API.
+    pub fn for_model(&self, model: &AIModel) -> Option<&ProviderSettings> {
+        match model {
+            AIModel::DeepSeek => Some(&self.deepseek),
+            AIModel::AliYun(_) => Some(&self.aliyun),
+            _ => None,
+        }
+    }
+}
+
+fn with_traffic<C>(client: C, traffic: Option<&TrafficLog>, attach: fn(C, TrafficLog) -> C) -> C {
+    match traffic {
+        Some(traffic) => attach(client, traffic.clone()),
+        None => client,
+    }
+}
+
+fn lookup_api_key(
+    settings: &ProviderSettings,
+    provider: Provider,
+) -> Result<String, ProviderError> {
+    if let Some(key) = &settings.api_key {
+        return Ok(key.clone());
+    }
+    match std::env::var(&settings.api_key_env) {
+        Ok(key) if !key.trim().is_empty() => Ok(key),
+        Ok(_) => Err(ProviderError::auth(
+            provider,
+            "empty_api_key",
+            format!("{} is empty", settings.api_key_env),
+        )),
+        Err(_) => Err(ProviderError::auth(
+            provider,
+            "missing_api_key",
+            format!("{} is not set", settings.api_key_env),
+        )),
     }
 }
 
-pub async fn call_real_aliyun_api(
-    user_input: &str,
-    language: Language,
+fn deepseek_client(
+    settings: &ProviderSettings,
+    traffic: Option<&TrafficLog>,
+) -> Result<DeepSeekClient, ProviderError> {
+    let config = DeepSeekConfig {
+        api_key: lookup_api_key(settings, Provider::DeepSeek)?,
+        base_url: settings.base_url.clone(),
+        timeout_seconds: settings.timeout_seconds,
+        ..Default::default()
+    };
+    Ok(with_traffic(
+        DeepSeekClient::new(config)?,
+        traffic,
+        DeepSeekClient::with_traffic_log,
+    ))
+}
+
+fn aliyun_client(
     model_type: aliyun::AliYunModelType,
-) -> String {
-    dotenv::dotenv().ok();
-    let api_key = match std::env::var("ALIYUN_API_KEY") {
-        Ok(key) if !key.trim().is_empty() => key,
-        Ok(_) => match language {
-            Language::Chinese => {
-                return "⚠️ 阿里云API密钥为空。请检查配置：\n\
-                       1. .env 文件中的 ALIYUN_API_KEY 不能为空\n\
-                       2. 或系统环境变量中的 ALIYUN_API_KEY 不能为空"
-                    .to_string();
-            }
-            Language::English => {
-                return "⚠️ Aliyun API key is empty. Please check configuration:\n\
-                       1. ALIYUN_API_KEY in .env file must not be empty\n\
-                       2. Or ALIYUN_API_KEY in system environment variables must not be empty"
-                    .to_string();
-            }
-        },
-        Err(_) => match language {
-            Language::Chinese => {
-                return "⚠️ 未找到阿里云API密钥。请按以下方式配置：\n\
-                       1. 在项目根目录创建 .env 文件，内容为：\n\
-                          ALIYUN_API_KEY=your_api_key_here\n\
-                       2. 或在系统环境变量中设置：\n\
-                          export ALIYUN_API_KEY=your_api_key_here"
-                    .to_string();
+    settings: &ProviderSettings,
+    traffic: Option<&TrafficLog>,
+) -> Result<aliyun::AliYunClient, ProviderError> {
+    let config = AliYunConfig {
+        api_key: lookup_api_key(settings, Provider::AliYun)?,
+        model_type,
+        base_url: settings.base_url.clone(),
+        timeout_seconds: settings.timeout_seconds,
+    };
+    Ok(with_traffic(
+        aliyun::AliYunClient::new(config)?,
+        traffic,
+        aliyun::AliYunClient::with_traffic_log,
+    ))
+}
+
+/// Streams a DeepSeek reply, passing each delta to `on_chunk`, and returns the full text.
+pub async fn call_real_deepseek_api<F>(
+    prompt: &Prompt,
+    settings: &ProviderSettings,
+    traffic: Option<&TrafficLog>,
+    on_chunk: F,
+) -> Result<String, ProviderError>
+where
+    F: FnMut(String) + Send + 'static,
+{
+    deepseek_client(settings, traffic)?
+        .chat_stream_with_options(prompt.messages.clone(), prompt.temperature, on_chunk)
+        .await
+}
+
+/// Streams a DashScope reply, passing each delta to `on_chunk`, and returns the full text.
+/// Qwen is told to answer in `language` when one is given.
+pub async fn call_real_aliyun_api<F>(
+    prompt: &Prompt,
+    language: Option<Language>,
+    model_type: aliyun::AliYunModelType,
+    settings: &ProviderSettings,
+    traffic: Option<&TrafficLog>,
+    on_chunk: F,
+) -> Result<String, ProviderError>
+where
+    F: FnMut(String) + Send + 'static,
+{
+    aliyun_client(model_type, settings, traffic)?
+        .chat_stream_with_options(
+            prompt.messages.clone(),
+            prompt.temperature,
+            language,
+            on_chunk,
+        )
+        .await
+}
+
+/// Streams a reply from `model` once its provider's rate limit lets the request
+/// through. `on_queue(true)` runs if the request has to wait for the limit and
+/// `on_queue(false)` once it may go. `None` for models without a real API.
+pub async fn stream_reply<Q, F>(
+    model: &AIModel,
+    prompt: &Prompt,
+    language: Option<Language>,
+    providers: &Providers,
+    traffic: Option<&TrafficLog>,
+    mut on_queue: Q,
+    on_chunk: F,
+) -> Option<Result<String, ProviderError>>
+where
+    Q: FnMut(bool) + Send,
+    F: FnMut(String) + Send + 'static,
+{
+    let settings = providers.for_model(model)?;
+    let mut queued = false;
+    let _permit = settings
+        .limiter
+        .acquire(limiter::estimate_tokens(&prompt.text()), || {
+            queued = true;
+            on_queue(true);
+        })
+        .await;
+    if queued {
+        on_queue(false);
+    }
+    Some(match model {
+        AIModel::AliYun(model_type) => {
+            call_real_aliyun_api(prompt, language, *model_type, settings, traffic, on_chunk).await
+        }
+        _ => call_real_deepseek_api(prompt, settings, traffic, on_chunk).await,
+    })
+}
+
+/// Asks `model` for a whole reply with its token usage once the provider's rate limit
+/// lets the request through. `None` for models without a real API.
+pub async fn complete(
+    model: &AIModel,
+    prompt: &Prompt,
+    max_tokens: Option<i32>,
+    language: Option<Language>,
+    providers: &Providers,
+    traffic: Option<&TrafficLog>,
+) -> Option<Result<Completion, ProviderError>> {
+    let settings = providers.for_model(model)?;
+    let _permit = settings
+        .limiter
+        .acquire(limiter::estimate_tokens(&prompt.text()), || {})
+        .await;
+    let messages = prompt.messages.clone();
+    let result = async {
+        match model {
+            AIModel::AliYun(model_type) => {
+                aliyun_client(*model_type, settings, traffic)?
+                    .complete(messages, prompt.temperature, max_tokens, language)
+                    .await
             }
-            Language::English => {
-                return "⚠️ Aliyun API key not found. Please configure as follows:\n\
-                       1. Create .env file in project root with content:\n\
-                          ALIYUN_API_KEY=your_api_key_here\n\
-                       2. Or set in system environment variables:\n\
-                          export ALIYUN_API_KEY=your_api_key_here"
-                    .to_string();
+            _ => {
+                deepseek_client(settings, traffic)?
+                    .complete(messages, prompt.temperature, max_tokens)
+                    .await
             }
-        },
+        }
     };
-    match aliyun::AliYunClient::with_api_key_and_model(&api_key, model_type) {
-        Ok(client) => match client.simple_chat(user_input, None, language).await {
-            Ok(response) => response,
-            Err(e) => match language {
-                Language::Chinese => format!("⚠️ 阿里云API调用失败: {}", e),
-                Language::English => format!("⚠️ Aliyun API call failed: {}", e),
-            },
-        },
-        Err(e) => match language {
-            Language::Chinese => format!("⚠️ 阿里云客户端创建失败: {}", e),
-            Language::English => format!("⚠️ Aliyun client creation failed: {}", e),
-        },
-    }
+    Some(result.await)
 }
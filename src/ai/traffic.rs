@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::ai::cassette::{
+    Cassette, CassetteBody, CassetteChunk, CassetteRequest, CassetteResponse, Interaction,
+    redact_headers,
+};
+
+pub const DEFAULT_CAPACITY: usize = 20;
+
+/// One HTTP exchange with a provider, as seen by the client.
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub id: u64,
+    pub provider: String,
+    pub endpoint: String,
+    pub started_at: chrono::DateTime<Local>,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: String,
+    pub status: Option<u16>,
+    pub response_body: Option<String>,
+    pub sse_lines: Vec<SseLine>,
+    pub error: Option<String>,
+    pub duration_ms: Option<u64>,
+}
+
+/// A raw SSE line and when it arrived, relative to the start of the exchange.
+#[derive(Debug, Clone)]
+pub struct SseLine {
+    pub elapsed_ms: u64,
+    pub line: String,
+}
+
+impl Exchange {
+    pub fn is_finished(&self) -> bool {
+        self.duration_ms.is_some()
+    }
+
+    /// Converts the exchange into a single-interaction cassette so a bug report can be
+    /// replayed with `AICHAT_REPLAY_CASSETTE`.
+    pub fn to_cassette(&self) -> Cassette {
+        let path = self
+            .endpoint
+            .find("://")
+            .and_then(|scheme| {
+                let rest = &self.endpoint[scheme + 3..];
+                rest.find('/').map(|slash| rest[slash..].to_string())
+            })
+            .unwrap_or_else(|| self.endpoint.clone());
+        let body = if self.sse_lines.is_empty() {
+            CassetteBody::Json {
+                body: self.response_body.clone().unwrap_or_default(),
+            }
+        } else {
+            let mut previous = 0;
+            CassetteBody::Sse {
+                chunks: self
+                    .sse_lines
+                    .iter()
+                    .map(|line| {
+                        let delay_ms = line.elapsed_ms.saturating_sub(previous);
+                        previous = line.elapsed_ms;
+                        CassetteChunk {
+                            delay_ms,
+                            data: format!("{}\n", line.line),
+                        }
+                    })
+                    .collect(),
+            }
+        };
+        Cassette {
+            interactions: vec![Interaction {
+                provider: self.provider.clone(),
+                request: CassetteRequest {
+                    method: "POST".to_string(),
+                    path,
+                    headers: self.request_headers.clone(),
+                    body: self.request_body.clone(),
+                },
+                response: CassetteResponse {
+                    status: self.status.unwrap_or(0),
+                    delay_ms: 0,
+                    body,
+                },
+            }],
+        }
+    }
+
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        self.to_cassette().save(path)
+    }
+}
+
+/// The last few provider exchanges, shared between the clients and the inspector pane.
+#[derive(Debug, Clone)]
+pub struct TrafficLog {
+    inner: Arc<Mutex<TrafficLogInner>>,
+}
+
+#[derive(Debug)]
+struct TrafficLogInner {
+    capacity: usize,
+    next_id: u64,
+    exchanges: VecDeque<Exchange>,
+}
+
+impl Default for TrafficLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl TrafficLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(TrafficLogInner {
+                capacity: capacity.max(1),
+                next_id: 1,
+                exchanges: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Oldest first.
+    pub fn snapshot(&self) -> Vec<Exchange> {
+        self.inner
+            .lock()
+            .unwrap()
+            .exchanges
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().exchanges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut Exchange)) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(exchange) = inner.exchanges.iter_mut().find(|e| e.id == id) {
+            f(exchange);
+        }
+    }
+}
+
+/// Records one exchange into an optional log; every method is a no-op without a log.
+pub struct ExchangeHandle {
+    log: Option<TrafficLog>,
+    id: u64,
+    started: Instant,
+}
+
+impl ExchangeHandle {
+    pub fn begin<T: Serialize>(
+        log: Option<&TrafficLog>,
+        provider: &str,
+        endpoint: &str,
+        headers: &[(String, String)],
+        body: &T,
+    ) -> Self {
+        let started = Instant::now();
+        let Some(log) = log else {
+            return Self {
+                log: None,
+                id: 0,
+                started,
+            };
+        };
+        let mut inner = log.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.exchanges.push_back(Exchange {
+            id,
+            provider: provider.to_string(),
+            endpoint: endpoint.to_string(),
+            started_at: Local::now(),
+            request_headers: redact_headers(headers),
+            request_body: serde_json::to_string_pretty(body).unwrap_or_default(),
+            status: None,
+            response_body: None,
+            sse_lines: Vec::new(),
+            error: None,
+            duration_ms: None,
+        });
+        while inner.exchanges.len() > inner.capacity {
+            inner.exchanges.pop_front();
+        }
+        drop(inner);
+        Self {
+            log: Some(log.clone()),
+            id,
+            started,
+        }
+    }
+
+    pub fn status(&self, status: u16) {
+        if let Some(log) = &self.log {
+            log.update(self.id, |e| e.status = Some(status));
+        }
+    }
+
+    pub fn sse_line(&self, line: &str) {
+        if let Some(log) = &self.log {
+            let elapsed_ms = self.started.elapsed().as_millis() as u64;
+            log.update(self.id, |e| {
+                e.sse_lines.push(SseLine {
+                    elapsed_ms,
+                    line: line.to_string(),
+                })
+            });
+        }
+    }
+
+    pub fn finish_with_body(&self, body: &str) {
+        if let Some(log) = &self.log {
+            let elapsed_ms = self.started.elapsed().as_millis() as u64;
+            log.update(self.id, |e| {
+                e.response_body = Some(body.to_string());
+                e.duration_ms = Some(elapsed_ms);
+            });
+        }
+    }
+
+    pub fn fail(&self, error: &str) {
+        if let Some(log) = &self.log {
+            let elapsed_ms = self.started.elapsed().as_millis() as u64;
+            log.update(self.id, |e| {
+                e.error = Some(error.to_string());
+                e.duration_ms = Some(elapsed_ms);
+            });
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(log) = &self.log {
+            let elapsed_ms = self.started.elapsed().as_millis() as u64;
+            log.update(self.id, |e| e.duration_ms = Some(elapsed_ms));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn begin(log: &TrafficLog) -> ExchangeHandle {
+        ExchangeHandle::begin(
+            Some(log),
+            "deepseek",
+            "https://api.deepseek.com/v1/chat/completions",
+            &[("Authorization".to_string(), "Bearer sk-live".to_string())],
+            &serde_json::json!({ "stream": true }),
+        )
+    }
+
+    #[test]
+    fn test_log_keeps_last_n_and_redacts() {
+        let log = TrafficLog::new(2);
+        for _ in 0..3 {
+            begin(&log).finish();
+        }
+        let exchanges = log.snapshot();
+        assert_eq!(exchanges.len(), 2);
+        assert_eq!(exchanges[0].id, 2);
+        assert_eq!(
+            exchanges[1].request_headers[0].1,
+            "Bearer [REDACTED]".to_string()
+        );
+    }
+
+    #[test]
+    fn test_sse_exchange_exports_as_cassette() {
+        let log = TrafficLog::default();
+        let exchange = begin(&log);
+        exchange.status(200);
+        exchange.sse_line("data: [DONE]");
+        exchange.finish();
+        let cassette = log.snapshot()[0].to_cassette();
+        let interaction = &cassette.interactions[0];
+        assert_eq!(interaction.request.path, "/v1/chat/completions");
+        match &interaction.response.body {
+            CassetteBody::Sse { chunks } => assert_eq!(chunks[0].data, "data: [DONE]\n"),
+            CassetteBody::Json { .. } => panic!("expected SSE body"),
+        }
+    }
+}
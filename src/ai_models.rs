@@ -113,6 +113,24 @@ impl AIModel {
         }
     }
 
+    /// Maps a model name as sent to a provider API (`qwen-plus`, `deepseek-chat`) to the
+    /// model aichat would use for it; anything else is kept as `Custom`.
+    pub fn from_api_name(name: &str) -> Self {
+        let name = name.trim();
+        if let Some(model_type) = AliYunModelType::all()
+            .into_iter()
+            .find(|model_type| model_type.name() == name)
+        {
+            return AIModel::AliYun(model_type);
+        }
+        match name {
+            "deepseek-chat" | "deepseek-reasoner" => AIModel::DeepSeek,
+            _ => name
+                .parse()
+                .unwrap_or_else(|_| AIModel::Custom(name.to_string())),
+        }
+    }
+
     pub fn is_real_api(&self) -> bool {
         match self {
             AIModel::DeepSeek => true,
@@ -237,44 +255,6 @@ impl AIModel {
         }
     }
 
-    pub fn from_str(name: &str) -> Option<Self> {
-        match name.to_lowercase().as_str() {
-            "deepseek" => Some(AIModel::DeepSeek),
-            "openaigpt" | "openai" => Some(AIModel::OpenAI),
-            "claude" => Some(AIModel::Claude),
-            "gemini" => Some(AIModel::Gemini),
-            "localllm" | "local" => Some(AIModel::LocalLLM),
-            "qwenturbo" => Some(AIModel::AliYun(aliyun::AliYunModelType::QwenTurbo)),
-            "qwenplus" => Some(AIModel::AliYun(aliyun::AliYunModelType::QwenPlus)),
-            "qwenmax" => Some(AIModel::AliYun(aliyun::AliYunModelType::QwenMax)),
-            _ => {
-                if name.starts_with("custom:") {
-                    let custom_name = name.trim_start_matches("custom:").to_string();
-                    Some(AIModel::Custom(custom_name))
-                } else {
-                    None
-                }
-            }
-        }
-    }
-
-    pub fn to_string(&self) -> String {
-        match self {
-            AIModel::DeepSeek => "deepseek".to_string(),
-            AIModel::AliYun(model_type) => match model_type {
-                aliyun::AliYunModelType::QwenTurbo => "qwenturbo".to_string(),
-                aliyun::AliYunModelType::QwenPlus => "qwenplus".to_string(),
-                aliyun::AliYunModelType::QwenMax => "qwenmax".to_string(),
-                aliyun::AliYunModelType::QwenMaxLongContext => "qwenmaxlongcontext".to_string(),
-            },
-            AIModel::OpenAI => "openai".to_string(),
-            AIModel::Claude => "claude".to_string(),
-            AIModel::Gemini => "gemini".to_string(),
-            AIModel::LocalLLM => "localllm".to_string(),
-            AIModel::Custom(name) => format!("custom:{}", name),
-        }
-    }
-
     pub fn default_model() -> Self {
         AIModel::DeepSeek
     }
@@ -375,3 +355,60 @@ pub fn create_aliyun_model(model_type: aliyun::AliYunModelType) -> AIModel {
 pub fn create_custom_model(name: &str) -> AIModel {
     AIModel::Custom(name.to_string())
 }
+
+impl std::str::FromStr for AIModel {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "deepseek" => Ok(AIModel::DeepSeek),
+            "openaigpt" | "openai" => Ok(AIModel::OpenAI),
+            "claude" => Ok(AIModel::Claude),
+            "gemini" => Ok(AIModel::Gemini),
+            "localllm" | "local" => Ok(AIModel::LocalLLM),
+            "qwenturbo" => Ok(AIModel::AliYun(aliyun::AliYunModelType::QwenTurbo)),
+            "qwenplus" => Ok(AIModel::AliYun(aliyun::AliYunModelType::QwenPlus)),
+            "qwenmax" => Ok(AIModel::AliYun(aliyun::AliYunModelType::QwenMax)),
+            "qwenmaxlongcontext" => {
+                Ok(AIModel::AliYun(aliyun::AliYunModelType::QwenMaxLongContext))
+            }
+            _ => match name.strip_prefix("custom:") {
+                Some(custom_name) => Ok(AIModel::Custom(custom_name.to_string())),
+                None => Err(format!("Unknown model: {}", name)),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for AIModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AIModel::DeepSeek => write!(f, "deepseek"),
+            AIModel::AliYun(model_type) => match model_type {
+                aliyun::AliYunModelType::QwenTurbo => write!(f, "qwenturbo"),
+                aliyun::AliYunModelType::QwenPlus => write!(f, "qwenplus"),
+                aliyun::AliYunModelType::QwenMax => write!(f, "qwenmax"),
+                aliyun::AliYunModelType::QwenMaxLongContext => write!(f, "qwenmaxlongcontext"),
+            },
+            AIModel::OpenAI => write!(f, "openai"),
+            AIModel::Claude => write!(f, "claude"),
+            AIModel::Gemini => write!(f, "gemini"),
+            AIModel::LocalLLM => write!(f, "localllm"),
+            AIModel::Custom(name) => write!(f, "custom:{}", name),
+        }
+    }
+}
+
+/// Stored by its `Display` name; names this build does not know load as `Custom`.
+impl serde::Serialize for AIModel {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for AIModel {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(name.parse().unwrap_or(AIModel::Custom(name)))
+    }
+}
@@ -1,30 +1,82 @@
 use chrono::Local;
-use ratatui::widgets::{ListState, ScrollbarState};
+use ratatui::widgets::ScrollbarState;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use crate::ai::{call_real_aliyun_api, call_real_deepseek_api};
+use crate::ai::deepseek::create_system_message;
+use crate::ai::error::ProviderError;
+use crate::ai::traffic::TrafficLog;
+use crate::ai::{DEFAULT_TEMPERATURE, Prompt, Provider, Providers, stream_reply};
 use crate::ai_models::AIModel;
+use crate::clipboard;
+use crate::config::{Config, ConfigError, Persona};
+use crate::export::ExportFormat;
 use crate::i18n::{Language, Translations};
+use crate::journal::{Journal, JournalFile};
+use crate::keymap::{Keymap, PendingKeys};
+use crate::keystore::{KeyEntry, KeySource, KeyStore, KeysScreen, Profile};
+use crate::motion;
+use crate::prefs::{Preferences, PreferencesFile};
+use crate::search::{SearchHighlight, SearchState, tokenize};
+use crate::session::{Branches, Session, branch_off, context};
+use crate::storage::{Conversation, ConversationStore};
+use crate::theme::{Theme, ThemeRegistry};
+use crate::title;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub content: String,
     pub sender: Sender,
     pub timestamp: chrono::DateTime<Local>,
+    /// Set on the first message of a conversation that has more than one version from
+    /// this point on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branches: Option<Box<Branches>>,
+    /// Sent as context however long the conversation grows.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Message {
+    /// A message sent now, unpinned and without other versions.
+    pub fn new(content: impl Into<String>, sender: Sender) -> Self {
+        Self {
+            content: content.into(),
+            sender,
+            timestamp: Local::now(),
+            branches: None,
+            pinned: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "role", content = "model", rename_all = "snake_case")]
 pub enum Sender {
     User,
     AI(AIModel),
     Thinking(AIModel),
+    Error(AIModel),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum InputMode {
     Normal,
     Editing,
+    Renaming,
+    Searching,
+    Selecting,
+    /// Moving the cursor over the input without typing, in the vim preset.
+    EditingNormal,
+    /// Typing a `:` command, in the vim preset.
+    Command,
+    /// The API key screen.
+    Keys,
+    /// Typing on the API key screen.
+    KeyEntry,
 }
 
 #[derive(Debug, PartialEq)]
@@ -36,15 +88,16 @@ pub enum AppState {
 
 pub struct App {
     pub ai_models: Vec<AIModel>,
-    pub selected_model_index: usize,
-    pub messages: Arc<Mutex<Vec<Message>>>,
+    pub sessions: Vec<Session>,
+    pub active_session: usize,
     pub input: String,
+    /// Where typing goes in `input`, as a byte offset; at the end when `None`.
+    pub input_cursor: Option<usize>,
+    pub rename_input: String,
+    pub command_input: String,
     pub input_mode: InputMode,
-    pub ai_list_state: ListState,
-    pub user_list_state: ListState,
-    pub ai_scrollbar_state: ScrollbarState,
-    pub user_scrollbar_state: ScrollbarState,
     pub theme_index: usize,
+    pub themes: ThemeRegistry,
     pub cursor_blink_state: bool,
     pub last_blink_time: Instant,
     pub show_help: bool,
@@ -54,8 +107,40 @@ pub struct App {
     pub translations: Translations,
     pub app_state: AppState,
     pub thinking_message_index: Option<usize>,
-    pub auto_scroll: bool,
-    pub model_display_offset: usize,
+    pub providers: Providers,
+    pub traffic: TrafficLog,
+    pub show_inspector: bool,
+    pub inspector_selected: usize,
+    pub store: Option<ConversationStore>,
+    pub search: SearchState,
+    /// The message highlighted in selection mode.
+    pub selected_message: usize,
+    /// The earlier user message being rewritten; sending starts a new branch there.
+    pub editing_message: Option<usize>,
+    /// Writes titles for new conversations; `None` turns automatic titles off.
+    pub title_model: Option<AIModel>,
+    /// Where the open sessions are journaled in case the app crashes.
+    pub journal: Option<JournalFile>,
+    /// What a crashed run left behind, until the user recovers or discards it.
+    pub recovery: Option<Journal>,
+    /// Where the language, theme and model chosen in the UI are kept between runs.
+    pub preferences: Option<PreferencesFile>,
+    /// Whether the help has been shown, here or in an earlier run.
+    pub help_seen: bool,
+    /// The API keys, unlocked at startup; `None` without a data dir to keep them in.
+    pub key_store: Option<KeyStore>,
+    pub keys_screen: KeysScreen,
+    pub keymap: Keymap,
+    /// The start of a key sequence such as `g g`, until the next key.
+    pub pending_keys: PendingKeys,
+    /// System prompts a session can be given, from the config file.
+    pub personas: Vec<Persona>,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl App {
@@ -65,15 +150,15 @@ impl App {
         let translations = Translations::new(language);
         App {
             ai_models,
-            selected_model_index: 0,
-            messages: Arc::new(Mutex::new(Vec::new())),
+            sessions: vec![Session::new(0)],
+            active_session: 0,
             input: String::new(),
+            input_cursor: None,
+            rename_input: String::new(),
+            command_input: String::new(),
             input_mode: InputMode::Normal,
-            ai_list_state: ListState::default(),
-            user_list_state: ListState::default(),
-            ai_scrollbar_state: ScrollbarState::new(0),
-            user_scrollbar_state: ScrollbarState::new(0),
             theme_index: 0,
+            themes: ThemeRegistry::default(),
             cursor_blink_state: true,
             last_blink_time: Instant::now(),
             show_help: false,
@@ -83,74 +168,156 @@ impl App {
             translations,
             app_state: AppState::Welcome,
             thinking_message_index: None,
-            auto_scroll: true,
-            model_display_offset: 0,
+            providers: Providers::default(),
+            traffic: TrafficLog::default(),
+            show_inspector: false,
+            inspector_selected: 0,
+            store: None,
+            search: SearchState::default(),
+            selected_message: 0,
+            editing_message: None,
+            title_model: title::parse_model(""),
+            journal: None,
+            recovery: None,
+            preferences: None,
+            help_seen: false,
+            key_store: None,
+            keys_screen: KeysScreen::default(),
+            keymap: Keymap::default(),
+            pending_keys: PendingKeys::default(),
+            personas: Vec::new(),
+        }
+    }
+
+    /// Takes over the settings of a loaded config, with the environment and command-line
+    /// flags already applied.
+    pub fn apply_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        config.validate()?;
+        self.keymap = Keymap::from_config(&config.keybindings).map_err(ConfigError::Invalid)?;
+        if let Some(models) = &config.models {
+            let previous: Vec<AIModel> = self
+                .sessions
+                .iter()
+                .map(|session| self.ai_models[session.selected_model_index].clone())
+                .collect();
+            self.ai_models = models
+                .iter()
+                .map(|name| AIModel::from_api_name(name))
+                .collect();
+            // Keep each session on its model when the new list still offers it.
+            for (session, model) in self.sessions.iter_mut().zip(previous) {
+                session.selected_model_index =
+                    self.ai_models.iter().position(|m| *m == model).unwrap_or(0);
+                session.model_display_offset = 0;
+            }
+        }
+        if let Some(name) = &config.model {
+            self.select_model(AIModel::from_api_name(name));
+        }
+        if let Some(language) = config.language {
+            self.set_language(language);
+        }
+        if let Some(theme) = &config.theme {
+            self.theme_index = self.themes.find(theme).map_err(ConfigError::Invalid)?;
+        }
+        if let Some(name) = &config.title_model {
+            self.title_model = title::parse_model(name);
         }
+        self.providers = Providers::from_config(&config.providers);
+        self.personas = config.personas.clone();
+        self.session_mut().persona = config.persona.clone();
+        Ok(())
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.sessions[self.active_session]
+    }
+
+    pub fn session_mut(&mut self) -> &mut Session {
+        &mut self.sessions[self.active_session]
     }
 
     pub fn update_model_display_offset(&mut self, max_visible: usize) {
         let total_models = self.ai_models.len();
+        let session = &mut self.sessions[self.active_session];
         let max_visible = max_visible.min(total_models);
-        if self.selected_model_index < self.model_display_offset {
-            self.model_display_offset = self.selected_model_index;
-        } else if self.selected_model_index >= self.model_display_offset + max_visible {
-            self.model_display_offset = self.selected_model_index - max_visible + 1;
+        if session.selected_model_index < session.model_display_offset {
+            session.model_display_offset = session.selected_model_index;
+        } else if session.selected_model_index >= session.model_display_offset + max_visible {
+            session.model_display_offset = session.selected_model_index - max_visible + 1;
         }
-        if self.model_display_offset + max_visible > total_models {
-            self.model_display_offset = total_models.saturating_sub(max_visible);
+        if session.model_display_offset + max_visible > total_models {
+            session.model_display_offset = total_models.saturating_sub(max_visible);
         }
     }
 
+    /// Selects `model`, adding it to the front of the selector if it is not offered.
+    pub fn select_model(&mut self, model: AIModel) {
+        let index = match self.ai_models.iter().position(|m| *m == model) {
+            Some(index) => index,
+            None => {
+                self.ai_models.insert(0, model);
+                0
+            }
+        };
+        self.session_mut().selected_model_index = index;
+    }
+
     pub fn current_model(&self) -> AIModel {
-        self.ai_models[self.selected_model_index].clone()
+        self.ai_models[self.session().selected_model_index].clone()
     }
 
     pub fn select_previous_model(&mut self, max_visible: usize) {
         let total_models = self.ai_models.len();
-        if self.selected_model_index > 0 {
-            self.selected_model_index -= 1;
+        let session = &mut self.sessions[self.active_session];
+        if session.selected_model_index > 0 {
+            session.selected_model_index -= 1;
         } else {
-            self.selected_model_index = total_models - 1;
+            session.selected_model_index = total_models - 1;
         }
         self.ensure_selected_visible(max_visible);
     }
 
     pub fn select_next_model(&mut self, max_visible: usize) {
         let total_models = self.ai_models.len();
-        if self.selected_model_index < total_models - 1 {
-            self.selected_model_index += 1;
+        let session = &mut self.sessions[self.active_session];
+        if session.selected_model_index < total_models - 1 {
+            session.selected_model_index += 1;
         } else {
-            self.selected_model_index = 0;
+            session.selected_model_index = 0;
         }
         self.ensure_selected_visible(max_visible);
     }
 
     pub fn ensure_selected_visible(&mut self, max_visible: usize) {
         let total_models = self.ai_models.len();
-        if self.selected_model_index < self.model_display_offset {
-            self.model_display_offset = self.selected_model_index;
-        } else if self.selected_model_index >= self.model_display_offset + max_visible {
-            self.model_display_offset = self.selected_model_index - max_visible + 1;
+        let session = &mut self.sessions[self.active_session];
+        if session.selected_model_index < session.model_display_offset {
+            session.model_display_offset = session.selected_model_index;
+        } else if session.selected_model_index >= session.model_display_offset + max_visible {
+            session.model_display_offset = session.selected_model_index - max_visible + 1;
         }
-        if self.model_display_offset + max_visible > total_models {
-            self.model_display_offset = total_models.saturating_sub(max_visible);
+        if session.model_display_offset + max_visible > total_models {
+            session.model_display_offset = total_models.saturating_sub(max_visible);
         }
         if total_models <= max_visible {
-            self.model_display_offset = 0;
+            session.model_display_offset = 0;
         }
     }
 
     pub fn scroll_models_left(&mut self, max_visible: usize) {
-        if self.model_display_offset > 0 {
-            self.model_display_offset -= 1;
+        let session = &mut self.sessions[self.active_session];
+        if session.model_display_offset > 0 {
+            session.model_display_offset -= 1;
         }
         self.ensure_selected_visible(max_visible);
     }
 
     pub fn scroll_models_right(&mut self, max_visible: usize) {
         let total_models = self.ai_models.len();
-        if self.model_display_offset + max_visible < total_models {
-            self.model_display_offset += 1;
+        let session = &mut self.sessions[self.active_session];
+        if session.model_display_offset + max_visible < total_models {
+            session.model_display_offset += 1;
         }
         self.ensure_selected_visible(max_visible);
     }
@@ -161,99 +328,274 @@ impl App {
         max_visible.max(1)
     }
 
+    /// The request for the next reply: the session's persona as a system message, if
+    /// it has one, then the context of `messages`.
+    fn prompt(&self, messages: &[Message]) -> Prompt {
+        let persona = self
+            .session()
+            .persona
+            .as_ref()
+            .and_then(|name| self.personas.iter().find(|persona| &persona.name == name));
+        let mut history: Vec<_> = persona
+            .map(|persona| create_system_message(&persona.prompt))
+            .into_iter()
+            .collect();
+        history.extend(context(messages));
+        Prompt {
+            messages: history,
+            temperature: self.session().temperature,
+        }
+    }
+
+    /// Gives the session the next persona from the config, or none after the last one.
+    pub fn next_persona(&mut self) {
+        if self.personas.is_empty() {
+            self.set_notification(self.t("notification_no_personas"));
+            return;
+        }
+        let next = match &self.session().persona {
+            Some(name) => self
+                .personas
+                .iter()
+                .position(|persona| &persona.name == name)
+                .and_then(|index| self.personas.get(index + 1)),
+            None => self.personas.first(),
+        }
+        .map(|persona| persona.name.clone());
+        self.set_persona(next);
+    }
+
+    /// Gives the session the persona called `name`, or none.
+    pub fn set_persona(&mut self, name: Option<String>) {
+        self.session_mut().persona = name.clone();
+        let name = name.unwrap_or_else(|| self.t("persona_none"));
+        self.set_notification(format!("{}: {}", self.t("notification_persona"), name));
+    }
+
     pub fn send_message(&mut self) {
         if self.input.trim().is_empty() {
             return;
         }
-        self.auto_scroll = true;
-        let user_input = self.input.clone();
-        let current_model = self.current_model().clone();
-        let language = self.language;
-        let mut messages = self.messages.lock().unwrap();
+        self.session_mut().auto_scroll = true;
+        self.session_mut().highlight = None;
+        let user_input = std::mem::take(&mut self.input);
+        self.input_cursor = None;
+        let current_model = self.current_model();
+        let messages_ref = Arc::clone(&self.session().messages);
+        let mut messages = messages_ref.lock().unwrap();
+        let branches = self
+            .editing_message
+            .take()
+            .and_then(|index| branch_off(&mut messages, index));
         if messages.is_empty() {
-            let welcome_message = Message {
-                content: self.t("welcome_message"),
-                sender: Sender::AI(current_model.clone()),
-                timestamp: Local::now(),
-            };
-            messages.push(welcome_message);
+            messages.push(Message::new(
+                self.t("welcome_message"),
+                Sender::AI(current_model),
+            ));
         }
-        let user_message = Message {
-            content: user_input.clone(),
-            sender: Sender::User,
-            timestamp: Local::now(),
-        };
-        messages.push(user_message);
-        let thinking_message = Message {
-            content: match language {
-                Language::Chinese => format!("🤔 {} 正在思考中...", current_model.name(language)),
-                Language::English => format!("🤔 {} is thinking...", current_model.name(language)),
-            },
-            sender: Sender::Thinking(current_model.clone()),
-            timestamp: Local::now(),
-        };
-        messages.push(thinking_message);
+        messages.push(Message {
+            branches,
+            ..Message::new(user_input, Sender::User)
+        });
+        let prompt = self.prompt(&messages);
+        drop(messages);
+        self.request_reply(None, prompt);
+    }
+
+    /// Asks the selected model for another reply to the turn the selected reply answers.
+    /// The old reply and everything after it stay available as a branch.
+    pub fn regenerate_selected(&mut self) {
+        if self.session().has_pending() {
+            self.set_notification(self.t("notification_wait_for_reply"));
+            return;
+        }
+        let index = self.selected_message;
+        let messages_ref = Arc::clone(&self.session().messages);
+        let mut messages = messages_ref.lock().unwrap();
+        let is_reply = index > 0
+            && matches!(
+                messages.get(index).map(|msg| &msg.sender),
+                Some(Sender::AI(_) | Sender::Error(_))
+            )
+            && messages[index - 1].sender == Sender::User;
+        if !is_reply {
+            drop(messages);
+            self.set_notification(self.t("notification_regenerate_reply_only"));
+            return;
+        }
+        let branches = branch_off(&mut messages, index);
+        let prompt = self.prompt(&messages);
+        drop(messages);
+        self.session_mut().highlight = None;
+        self.request_reply(branches, prompt);
+    }
+
+    /// Raises or lowers the sampling temperature of the active session by `delta`,
+    /// starting from the provider default of 0.7.
+    pub fn adjust_temperature(&mut self, delta: f32) {
+        let session = self.session_mut();
+        let current = session.temperature.unwrap_or(DEFAULT_TEMPERATURE);
+        let adjusted = ((current + delta) * 10.0).round() / 10.0;
+        session.temperature = Some(adjusted.clamp(0.0, 2.0));
+    }
+
+    /// Appends a thinking placeholder carrying `branches` and streams the current
+    /// model's reply to `prompt` into it.
+    fn request_reply(&mut self, branches: Option<Box<Branches>>, prompt: Prompt) {
+        let current_model = self.current_model();
+        let language = self.language;
+        let messages_ref = Arc::clone(&self.session().messages);
+        let unread = Arc::clone(&self.session().unread);
+        let thinking_text = Self::placeholder_text(&current_model, language, false);
+        let waiting_text = Self::placeholder_text(&current_model, language, true);
+        let mut messages = messages_ref.lock().unwrap();
+        messages.push(Message {
+            branches,
+            ..Message::new(
+                thinking_text.clone(),
+                Sender::Thinking(current_model.clone()),
+            )
+        });
+        let placeholder = messages.len() - 1;
         let ai_messages_count = messages
             .iter()
-            .filter(|msg| matches!(msg.sender, Sender::AI(_) | Sender::Thinking(_)))
+            .filter(|msg| {
+                matches!(
+                    msg.sender,
+                    Sender::AI(_) | Sender::Thinking(_) | Sender::Error(_)
+                )
+            })
             .count();
         let user_messages_count = messages.len() - ai_messages_count;
-        self.ai_scrollbar_state = ScrollbarState::new(ai_messages_count);
-        self.user_scrollbar_state = ScrollbarState::new(user_messages_count);
         drop(messages);
-        let messages_ref = Arc::clone(&self.messages);
+        let session = self.session_mut();
+        session.ai_scrollbar_state = ScrollbarState::new(ai_messages_count);
+        session.user_scrollbar_state = ScrollbarState::new(user_messages_count);
         let model = current_model.clone();
-        match model {
-            AIModel::DeepSeek => {
-                let current_language = self.language;
-                tokio::spawn(async move {
-                    let response = call_real_deepseek_api(&user_input, current_language).await;
-                    Self::process_ai_response(messages_ref, model, response).await;
-                });
-            }
-            AIModel::AliYun(aliyun_model_type) => {
-                let current_language = self.language;
-                tokio::spawn(async move {
-                    let response =
-                        call_real_aliyun_api(&user_input, current_language, aliyun_model_type)
-                            .await;
-                    Self::process_ai_response(messages_ref, model, response).await;
-                });
-            }
-            _ => {
-                let language_for_sim = self.language;
-                tokio::spawn(async move {
+        let traffic = self.traffic.clone();
+        let providers = self.providers.clone();
+        let on_chunk = Self::stream_into(Arc::clone(&messages_ref), placeholder);
+        tokio::spawn(async move {
+            let set_placeholder = |waiting: bool| {
+                if let Some(message) = messages_ref.lock().unwrap().get_mut(placeholder) {
+                    message.content = if waiting {
+                        waiting_text.clone()
+                    } else {
+                        thinking_text.clone()
+                    };
+                }
+            };
+            let reply = stream_reply(
+                &model,
+                &prompt,
+                Some(language),
+                &providers,
+                Some(&traffic),
+                set_placeholder,
+                on_chunk,
+            )
+            .await;
+            let response = match reply {
+                Some(response) => response,
+                None => {
+                    let user_input = prompt
+                        .messages
+                        .last()
+                        .map(|message| message.content.clone())
+                        .unwrap_or_default();
                     tokio::time::sleep(Duration::from_millis(500)).await;
-                    let response = model.simulate_response(&user_input, language_for_sim);
-                    Self::process_ai_response(messages_ref, model, response).await;
-                });
+                    Ok(model.simulate_response(&user_input, language))
+                }
+            };
+            Self::process_ai_response(
+                messages_ref,
+                &unread,
+                placeholder,
+                model,
+                language,
+                response,
+            )
+            .await;
+        });
+    }
+
+    /// What a reply placeholder shows before the first chunk: thinking, or waiting for
+    /// the rate limit.
+    fn placeholder_text(model: &AIModel, language: Language, waiting: bool) -> String {
+        let key = if waiting {
+            "reply_waiting"
+        } else {
+            "reply_thinking"
+        };
+        Translations::new(language)
+            .get(key)
+            .replace("{model}", &model.name(language))
+    }
+
+    /// Whether `content` is still a placeholder for `model`, in either language.
+    fn is_placeholder(model: &AIModel, content: &str) -> bool {
+        [Language::English, Language::Chinese]
+            .into_iter()
+            .any(|language| {
+                [false, true]
+                    .into_iter()
+                    .any(|waiting| Self::placeholder_text(model, language, waiting) == content)
+            })
+    }
+
+    /// Streams deltas into the placeholder at `index`. It stays a `Thinking` message
+    /// until the reply completes, so the UI can tell a partial reply from a final one.
+    fn stream_into(
+        messages_ref: Arc<Mutex<Vec<Message>>>,
+        index: usize,
+    ) -> impl FnMut(String) + Send + 'static {
+        let mut started = false;
+        move |chunk| {
+            let mut messages = messages_ref.lock().unwrap();
+            if let Some(message) = messages.get_mut(index) {
+                if !started {
+                    message.content.clear();
+                    started = true;
+                }
+                message.content.push_str(&chunk);
             }
         }
-        self.input.clear();
     }
 
+    /// Replaces the placeholder at `index` with the reply, keeping its branches and pin,
+    /// and badges the session.
     async fn process_ai_response(
         messages_ref: Arc<Mutex<Vec<Message>>>,
+        unread: &AtomicBool,
+        index: usize,
         model: AIModel,
-        response: String,
+        language: Language,
+        response: Result<String, ProviderError>,
     ) {
         let mut messages = messages_ref.lock().unwrap();
-        if let Some(pos) = messages
-            .iter()
-            .position(|msg| matches!(msg.sender, Sender::Thinking(_)))
-        {
-            messages.remove(pos);
-        }
-        let ai_message = Message {
-            content: response,
-            sender: Sender::AI(model),
-            timestamp: Local::now(),
+        let ai_message = match response {
+            Ok(content) => Message::new(content, Sender::AI(model)),
+            Err(error) => Message::new(
+                error.localized(&Translations::new(language)),
+                Sender::Error(model),
+            ),
         };
-        messages.push(ai_message);
+        match messages.get_mut(index) {
+            Some(message) => {
+                let branches = message.branches.take();
+                *message = Message {
+                    branches,
+                    pinned: message.pinned,
+                    ..ai_message
+                };
+            }
+            None => messages.push(ai_message),
+        }
+        unread.store(true, Ordering::Relaxed);
     }
 
     pub fn get_max_scroll_offset(&self) -> usize {
-        let messages = self.messages.lock().unwrap();
+        let messages = self.session().messages.lock().unwrap();
         if messages.is_empty() {
             return 0;
         }
@@ -267,62 +609,913 @@ impl App {
     }
 
     pub fn scroll_up(&mut self) {
-        let current = self.ai_list_state.selected().unwrap_or(0);
+        let session = self.session_mut();
+        let current = session.ai_list_state.selected().unwrap_or(0);
         if current > 0 {
-            self.ai_list_state.select(Some(current - 1));
-            self.auto_scroll = false;
+            session.ai_list_state.select(Some(current - 1));
+            session.auto_scroll = false;
         }
     }
 
     pub fn scroll_down(&mut self) {
-        let current = self.ai_list_state.selected().unwrap_or(0);
-        self.ai_list_state.select(Some(current + 1));
-        self.auto_scroll = false;
+        let session = self.session_mut();
+        let current = session.ai_list_state.selected().unwrap_or(0);
+        session.ai_list_state.select(Some(current + 1));
+        session.auto_scroll = false;
     }
 
     pub fn scroll_to_home(&mut self) {
-        self.ai_list_state.select(Some(0));
-        self.auto_scroll = false;
+        let session = self.session_mut();
+        session.ai_list_state.select(Some(0));
+        session.auto_scroll = false;
     }
 
     pub fn scroll_to_end(&mut self) {
-        let messages = self.messages.lock().unwrap();
+        let session = self.session_mut();
+        let messages = session.messages.lock().unwrap();
         let ai_messages_count = messages
             .iter()
-            .filter(|msg| matches!(msg.sender, Sender::AI(_) | Sender::Thinking(_)))
+            .filter(|msg| {
+                matches!(
+                    msg.sender,
+                    Sender::AI(_) | Sender::Thinking(_) | Sender::Error(_)
+                )
+            })
             .count();
-        self.ai_list_state
+        drop(messages);
+        session
+            .ai_list_state
             .select(Some(ai_messages_count.saturating_sub(1)));
-        self.auto_scroll = true;
+        session.auto_scroll = true;
+    }
+
+    /// The title shown for the active session, if it has a user message yet.
+    pub fn title(&self) -> Option<String> {
+        self.session().title()
+    }
+
+    /// Saves every session that changed since it was last saved, journals the ones
+    /// still streaming, and clears the badge of the session on screen. Called every tick.
+    pub fn autosave(&mut self) {
+        self.session().mark_read();
+        self.write_journal();
+        self.save_preferences();
+        let Some(store) = self.store.clone() else {
+            return;
+        };
+        let mut failure = None;
+        for session in &mut self.sessions {
+            let model = self.ai_models[session.selected_model_index].clone();
+            if let Err(e) = session.save_if_changed(&store, model) {
+                failure = Some(e);
+            }
+        }
+        if let Some(e) = failure {
+            self.set_notification(format!("{}: {}", self.t("notification_save_failed"), e));
+        }
+    }
+
+    /// Journals every open session, partial replies included. Left alone while a
+    /// recovery is being offered, so the crashed run's journal is not overwritten.
+    fn write_journal(&mut self) {
+        if self.recovery.is_some() {
+            return;
+        }
+        let Some(journal) = self.journal.as_mut() else {
+            return;
+        };
+        let sessions = &self.sessions;
+        let ai_models = &self.ai_models;
+        let state: Vec<_> = sessions
+            .iter()
+            .map(|session| (&session.id, session.fingerprint()))
+            .collect();
+        let result = journal.write_if_changed((self.active_session, state), || Journal {
+            active: Some(sessions[self.active_session].id.clone()),
+            sessions: sessions
+                .iter()
+                .filter_map(|session| {
+                    session.journal_entry(ai_models[session.selected_model_index].clone())
+                })
+                .collect(),
+        });
+        if let Err(e) = result {
+            self.set_notification(format!("{}: {}", self.t("notification_save_failed"), e));
+        }
+    }
+
+    /// Looks for the journal of a run that did not exit cleanly and offers to recover it.
+    pub fn open_journal(&mut self, file: JournalFile) {
+        match file.load() {
+            Ok(recovery) => self.recovery = recovery,
+            Err(e) => {
+                self.set_notification(format!("{}: {}", self.t("notification_load_failed"), e))
+            }
+        }
+        self.journal = Some(file);
+    }
+
+    /// Restores the language, theme and model of the last run. Called before the
+    /// config is applied, so settings there still win.
+    pub fn restore_preferences(&mut self, mut file: PreferencesFile) {
+        match file.load() {
+            Ok(preferences) => {
+                if let Some(language) = preferences.language {
+                    self.set_language(language);
+                }
+                if let Some(index) = preferences
+                    .theme
+                    .and_then(|name| self.themes.position(&name))
+                {
+                    self.theme_index = index;
+                }
+                if let Some(model) = preferences.model {
+                    self.select_model(model);
+                }
+                self.help_seen = preferences.help_seen;
+            }
+            Err(e) => {
+                self.set_notification(format!("{}: {}", self.t("notification_load_failed"), e))
+            }
+        }
+        self.preferences = Some(file);
+    }
+
+    fn save_preferences(&mut self) {
+        let preferences = Preferences {
+            language: Some(self.language),
+            theme: Some(self.theme().name.clone()),
+            model: Some(self.current_model()),
+            help_seen: self.help_seen,
+        };
+        let Some(file) = self.preferences.as_mut() else {
+            return;
+        };
+        if let Err(e) = file.save_if_changed(&preferences) {
+            self.set_notification(format!("{}: {}", self.t("notification_save_failed"), e));
+        }
+    }
+
+    /// Reopens the sessions of the crashed run. Replies that were still streaming keep
+    /// what had arrived and are marked as interrupted, so they can be regenerated.
+    pub fn recover_journal(&mut self) {
+        let Some(journal) = self.recovery.take() else {
+            return;
+        };
+        let interrupted = self.t("reply_interrupted");
+        let count = journal.sessions.len();
+        for mut conversation in journal.sessions {
+            for message in &mut conversation.messages {
+                if let Sender::Thinking(model) = &message.sender {
+                    message.content = if Self::is_placeholder(model, &message.content) {
+                        format!("⚠️ {}", interrupted)
+                    } else {
+                        format!("{}\n\n⚠️ {}", message.content.trim_end(), interrupted)
+                    };
+                    message.sender = Sender::Error(model.clone());
+                }
+            }
+            self.resume_conversation(conversation);
+        }
+        if let Some(index) = self
+            .sessions
+            .iter()
+            .position(|session| journal.active.as_ref() == Some(&session.id))
+        {
+            self.switch_session(index);
+        }
+        self.set_notification(format!("{}: {}", self.t("notification_recovered"), count));
+    }
+
+    pub fn discard_recovery(&mut self) {
+        self.recovery = None;
+    }
+
+    /// Removes the journal on a clean exit, unless a recovery is still being offered.
+    pub fn close_journal(&mut self) {
+        if self.recovery.is_some() {
+            return;
+        }
+        if let Some(journal) = self.journal.as_mut() {
+            let _ = journal.remove();
+        }
+    }
+
+    /// Asks the title model to name every session whose first exchange has completed,
+    /// including resumed and imported ones that only have a fallback title. Called every
+    /// tick; sessions named by the user or already given a generated title are left
+    /// alone, and a title that arrives after a rename is only used if the name is
+    /// cleared again.
+    pub fn generate_titles(&mut self) {
+        let Some(model) = self.title_model.clone() else {
+            return;
+        };
+        for session in &mut self.sessions {
+            if session.has_manual_title() || !session.needs_auto_title() || session.has_pending() {
+                continue;
+            }
+            let Some(prompt) = title::prompt(&session.messages.lock().unwrap(), self.language)
+            else {
+                continue;
+            };
+            session.title_requested = true;
+            let auto_title = Arc::clone(&session.auto_title);
+            let model = model.clone();
+            let language = self.language;
+            let providers = self.providers.clone();
+            let traffic = self.traffic.clone();
+            tokio::spawn(async move {
+                if let Some(title) =
+                    title::request(&model, &prompt, language, &providers, &traffic).await
+                {
+                    *auto_title.lock().unwrap() = Some(title);
+                }
+            });
+        }
+    }
+
+    /// Opens a saved conversation in a session of its own, reusing the active session
+    /// when it is still empty. An already open conversation is just switched to.
+    pub fn resume_conversation(&mut self, conversation: Conversation) {
+        if let Some(index) = self.sessions.iter().position(|s| s.id == conversation.id) {
+            self.switch_session(index);
+            self.app_state = AppState::Chatting;
+            return;
+        }
+        let model_index = match self
+            .ai_models
+            .iter()
+            .position(|model| *model == conversation.model)
+        {
+            Some(index) => index,
+            None => {
+                self.ai_models.push(conversation.model.clone());
+                self.ai_models.len() - 1
+            }
+        };
+        let session = Session::from_conversation(conversation, model_index);
+        if self.session().is_empty() && !self.session().has_pending() {
+            *self.session_mut() = session;
+        } else {
+            self.sessions.push(session);
+            self.active_session = self.sessions.len() - 1;
+        }
+        self.app_state = AppState::Chatting;
+    }
+
+    /// Resumes the most recently updated saved conversation.
+    pub fn resume_latest(&mut self) {
+        let latest = self.store.as_ref().map(|store| store.latest());
+        match latest {
+            Some(Ok(Some(conversation))) => {
+                let title = conversation.title.clone();
+                self.resume_conversation(conversation);
+                self.set_notification(format!("{}: {}", self.t("notification_resumed"), title));
+            }
+            Some(Err(e)) => {
+                self.set_notification(format!("{}: {}", self.t("notification_load_failed"), e))
+            }
+            _ => self.set_notification(self.t("notification_no_saved_conversations")),
+        }
+    }
+
+    /// Opens an empty session with the active session's model and persona and switches
+    /// to it.
+    pub fn new_session(&mut self) {
+        let mut session = Session::new(self.session().selected_model_index);
+        session.persona = self.session().persona.clone();
+        self.sessions.push(session);
+        self.switch_session(self.sessions.len() - 1);
+        self.set_notification(self.t("notification_session_created"));
+    }
+
+    pub fn switch_session(&mut self, index: usize) {
+        if index < self.sessions.len() {
+            self.active_session = index;
+            self.session().mark_read();
+        }
+    }
+
+    pub fn next_session(&mut self) {
+        self.switch_session((self.active_session + 1) % self.sessions.len());
+    }
+
+    pub fn previous_session(&mut self) {
+        let count = self.sessions.len();
+        self.switch_session((self.active_session + count - 1) % count);
+    }
+
+    /// Closes the active session after saving it. Replies still streaming into it are
+    /// dropped. Closing the last session leaves a fresh empty one.
+    pub fn close_session(&mut self) {
+        self.autosave();
+        let model_index = self.session().selected_model_index;
+        self.sessions.remove(self.active_session);
+        if self.sessions.is_empty() {
+            self.sessions.push(Session::new(model_index));
+        }
+        self.active_session = self.active_session.min(self.sessions.len() - 1);
+        self.session().mark_read();
+        self.set_notification(self.t("notification_session_closed"));
+    }
+
+    pub fn start_rename(&mut self) {
+        self.rename_input = self.title().unwrap_or_default();
+        self.input_mode = InputMode::Renaming;
+    }
+
+    /// Applies the rename buffer; an empty name goes back to the automatic title.
+    pub fn finish_rename(&mut self) {
+        let title = self.rename_input.trim().to_string();
+        let session = self.session_mut();
+        if title.is_empty() {
+            session.clear_title();
+        } else {
+            session.rename(title);
+        }
+        self.rename_input.clear();
+        self.input_mode = InputMode::Normal;
+    }
+
+    pub fn cancel_rename(&mut self) {
+        self.rename_input.clear();
+        self.input_mode = InputMode::Normal;
+    }
+
+    /// Takes over the unlocked key store and gives the providers the keys of its
+    /// active profile. Called after the config is applied, which resets them.
+    pub fn open_key_store(&mut self, store: KeyStore) {
+        self.key_store = Some(store);
+        self.apply_keys();
+    }
+
+    fn apply_keys(&mut self) {
+        let Some(store) = &self.key_store else {
+            return;
+        };
+        self.keys_screen.errors = store.keyring.apply(&mut self.providers);
+        if let Some((provider, e)) = self.keys_screen.errors.iter().next() {
+            self.set_notification(format!(
+                "{} {}: {}",
+                self.t("notification_key_failed"),
+                provider.name(),
+                e
+            ));
+        }
+    }
+
+    pub fn open_keys(&mut self) {
+        let Some(store) = &self.key_store else {
+            self.set_notification(self.t("notification_keys_unavailable"));
+            return;
+        };
+        self.keys_screen.profile = store.keyring.active.clone();
+        self.input_mode = InputMode::Keys;
+    }
+
+    pub fn close_keys(&mut self) {
+        self.input_mode = InputMode::Normal;
+    }
+
+    pub fn select_previous_key(&mut self) {
+        self.keys_screen.selected = self.keys_screen.selected.saturating_sub(1);
+    }
+
+    pub fn select_next_key(&mut self) {
+        self.keys_screen.selected = (self.keys_screen.selected + 1).min(Provider::ALL.len() - 1);
+    }
+
+    /// Shows the next or previous profile, wrapping around.
+    pub fn switch_key_profile(&mut self, forward: bool) {
+        let Some(store) = &self.key_store else {
+            return;
+        };
+        let names: Vec<&String> = store.keyring.profiles.keys().collect();
+        let Some(at) = names
+            .iter()
+            .position(|name| **name == self.keys_screen.profile)
+        else {
+            self.keys_screen.profile = store.keyring.active.clone();
+            return;
+        };
+        let next = if forward {
+            (at + 1) % names.len()
+        } else {
+            (at + names.len() - 1) % names.len()
+        };
+        self.keys_screen.profile = names[next].clone();
+    }
+
+    /// The profile on screen, created if a key file edit removed it.
+    fn shown_profile(&mut self) -> Option<&mut Profile> {
+        let store = self.key_store.as_mut()?;
+        Some(
+            store
+                .keyring
+                .profiles
+                .entry(self.keys_screen.profile.clone())
+                .or_default(),
+        )
+    }
+
+    pub fn start_key_entry(&mut self, entry: KeyEntry) {
+        let provider = self.keys_screen.provider();
+        self.keys_screen.input = match (entry, self.shown_profile().and_then(|p| p.get(&provider)))
+        {
+            (KeyEntry::Command, Some(KeySource::Command(command))) => command.clone(),
+            _ => String::new(),
+        };
+        self.keys_screen.entry = Some(entry);
+        self.input_mode = InputMode::KeyEntry;
+    }
+
+    pub fn cancel_key_entry(&mut self) {
+        self.keys_screen.entry = None;
+        self.keys_screen.input.clear();
+        self.input_mode = InputMode::Keys;
+    }
+
+    /// Stores what was typed and saves the key file. A key that does not look like
+    /// the provider's is kept, with a warning, since a proxy may hand out others.
+    pub fn finish_key_entry(&mut self) {
+        let Some(entry) = self.keys_screen.entry.take() else {
+            return;
+        };
+        let input = std::mem::take(&mut self.keys_screen.input);
+        let text = input.trim().to_string();
+        self.input_mode = InputMode::Keys;
+        let provider = self.keys_screen.provider();
+        let mut warning = None;
+        match entry {
+            KeyEntry::Key | KeyEntry::Command | KeyEntry::Profile if text.is_empty() => return,
+            KeyEntry::Passphrase if input.is_empty() => return,
+            KeyEntry::Key => {
+                warning = provider.check_key(&text).err();
+                if let Some(profile) = self.shown_profile() {
+                    profile.insert(provider, KeySource::Stored(text));
+                }
+            }
+            KeyEntry::Command => {
+                if let Some(profile) = self.shown_profile() {
+                    profile.insert(provider, KeySource::Command(text));
+                }
+            }
+            KeyEntry::Profile => {
+                let Some(store) = self.key_store.as_mut() else {
+                    return;
+                };
+                if store.keyring.profiles.contains_key(&text) {
+                    self.set_notification(self.t("notification_profile_exists"));
+                    return;
+                }
+                store.keyring.profiles.insert(text.clone(), Profile::new());
+                self.keys_screen.profile = text;
+            }
+            KeyEntry::Passphrase => {
+                if let Some(store) = self.key_store.as_mut() {
+                    store.set_passphrase(input);
+                }
+            }
+        }
+        self.save_keys();
+        if let Some(warning) = warning {
+            self.set_notification(format!(
+                "{}: {}",
+                self.t("notification_key_invalid"),
+                warning.localized(&self.translations)
+            ));
+        }
+    }
+
+    pub fn remove_key(&mut self) {
+        let provider = self.keys_screen.provider();
+        if self
+            .shown_profile()
+            .is_some_and(|profile| profile.remove(&provider).is_some())
+        {
+            self.save_keys();
+        }
+    }
+
+    /// Makes the profile on screen the one whose keys are used.
+    pub fn use_key_profile(&mut self) {
+        if let Some(store) = self.key_store.as_mut() {
+            store.keyring.active = self.keys_screen.profile.clone();
+            self.save_keys();
+        }
+    }
+
+    pub fn delete_key_profile(&mut self) {
+        let Some(store) = self.key_store.as_mut() else {
+            return;
+        };
+        if store.keyring.profiles.len() <= 1 {
+            self.set_notification(self.t("notification_last_profile"));
+            return;
+        }
+        store.keyring.profiles.remove(&self.keys_screen.profile);
+        if !store.keyring.profiles.contains_key(&store.keyring.active) {
+            store.keyring.active = store
+                .keyring
+                .profiles
+                .keys()
+                .next()
+                .cloned()
+                .unwrap_or_default();
+        }
+        self.keys_screen.profile = store.keyring.active.clone();
+        self.save_keys();
+    }
+
+    /// Writes the key file and puts the active profile to use again, asking for a
+    /// passphrase first when the file is new.
+    fn save_keys(&mut self) {
+        let Some(store) = &self.key_store else {
+            return;
+        };
+        if !store.has_passphrase() {
+            self.start_key_entry(KeyEntry::Passphrase);
+            return;
+        }
+        match store.save() {
+            Ok(()) => {
+                self.set_notification(self.t("notification_keys_saved"));
+                self.apply_keys();
+            }
+            Err(e) => {
+                self.set_notification(format!("{}: {}", self.t("notification_save_failed"), e))
+            }
+        }
+    }
+
+    /// Opens the results view over every saved conversation, saving open sessions first
+    /// so they are found too.
+    pub fn open_search(&mut self) {
+        self.autosave();
+        let Some(store) = self.store.clone() else {
+            self.set_notification(self.t("notification_search_unavailable"));
+            return;
+        };
+        if let Err(e) = self.search.index.refresh(&store) {
+            self.set_notification(format!("{}: {}", self.t("notification_load_failed"), e));
+            return;
+        }
+        self.search.update();
+        self.input_mode = InputMode::Searching;
+    }
+
+    pub fn close_search(&mut self) {
+        self.input_mode = InputMode::Normal;
+    }
+
+    pub fn search_push(&mut self, c: char) {
+        self.search.query.push(c);
+        self.search.selected = 0;
+        self.search.update();
+    }
+
+    pub fn search_pop(&mut self) {
+        self.search.query.pop();
+        self.search.update();
+    }
+
+    /// Opens the selected result's conversation scrolled to the message, with the
+    /// query terms highlighted.
+    pub fn open_search_result(&mut self) {
+        let Some(hit) = self.search.selected_hit().cloned() else {
+            return;
+        };
+        let open = self
+            .sessions
+            .iter()
+            .position(|s| s.id == hit.conversation_id);
+        match open {
+            Some(index) => self.switch_session(index),
+            None => {
+                let loaded = self
+                    .store
+                    .as_ref()
+                    .map(|store| store.load(&hit.conversation_id));
+                match loaded {
+                    Some(Ok(conversation)) => self.resume_conversation(conversation),
+                    Some(Err(e)) => {
+                        self.set_notification(format!(
+                            "{}: {}",
+                            self.t("notification_load_failed"),
+                            e
+                        ));
+                        return;
+                    }
+                    None => return,
+                }
+            }
+        }
+        let terms = tokenize(&self.search.query);
+        let session = self.session_mut();
+        session.scroll_to_message(hit.message_index);
+        session.highlight = Some(SearchHighlight {
+            message_index: hit.message_index,
+            terms,
+        });
+        self.app_state = AppState::Chatting;
+        self.input_mode = InputMode::Normal;
+    }
+
+    /// Enters selection mode on the newest message.
+    pub fn start_selecting(&mut self) {
+        let count = self.session().messages.lock().unwrap().len();
+        if count == 0 {
+            return;
+        }
+        self.selected_message = count - 1;
+        self.session_mut().scroll_to_message(count - 1);
+        self.input_mode = InputMode::Selecting;
+    }
+
+    pub fn stop_selecting(&mut self) {
+        self.input_mode = InputMode::Normal;
+    }
+
+    pub fn select_previous_message(&mut self) {
+        self.selected_message = self.selected_message.saturating_sub(1);
+        let index = self.selected_message;
+        self.session_mut().scroll_to_message(index);
+    }
+
+    pub fn select_next_message(&mut self) {
+        let last = self
+            .session()
+            .messages
+            .lock()
+            .unwrap()
+            .len()
+            .saturating_sub(1);
+        self.selected_message = (self.selected_message + 1).min(last);
+        let index = self.selected_message;
+        self.session_mut().scroll_to_message(index);
+    }
+
+    /// Shows the previous or next version of the conversation at the selected message.
+    pub fn switch_selected_branch(&mut self, forward: bool) {
+        if self.session().has_pending() {
+            self.set_notification(self.t("notification_wait_for_reply"));
+            return;
+        }
+        let index = self.selected_message;
+        if self.session_mut().switch_branch(index, forward) {
+            let last = self
+                .session()
+                .messages
+                .lock()
+                .unwrap()
+                .len()
+                .saturating_sub(1);
+            self.selected_message = index.min(last);
+        }
+    }
+
+    /// Loads the selected user message into the input; sending it starts a new branch
+    /// and keeps the old one.
+    pub fn edit_selected_message(&mut self) {
+        if self.session().has_pending() {
+            self.set_notification(self.t("notification_wait_for_reply"));
+            return;
+        }
+        let content = {
+            let messages = self.session().messages.lock().unwrap();
+            match messages.get(self.selected_message) {
+                Some(message) if message.sender == Sender::User => message.content.clone(),
+                _ => {
+                    drop(messages);
+                    self.set_notification(self.t("notification_edit_user_only"));
+                    return;
+                }
+            }
+        };
+        self.input = content;
+        self.input_cursor = None;
+        self.editing_message = Some(self.selected_message);
+        self.input_mode = InputMode::Editing;
+    }
+
+    pub fn cancel_editing(&mut self) {
+        self.clear_input();
+        self.editing_message = None;
+        self.input_mode = InputMode::Normal;
+    }
+
+    /// Deletes the selected message from the conversation and from later context.
+    pub fn delete_selected_message(&mut self) {
+        if self.session().has_pending() {
+            self.set_notification(self.t("notification_wait_for_reply"));
+            return;
+        }
+        let index = self.selected_message;
+        if !self.session_mut().delete_message(index) {
+            self.set_notification(self.t("notification_delete_branched"));
+            return;
+        }
+        let count = self.session().messages.lock().unwrap().len();
+        if count == 0 {
+            self.stop_selecting();
+        } else {
+            self.selected_message = index.min(count - 1);
+        }
+        self.set_notification(self.t("notification_message_deleted"));
+    }
+
+    /// Pins the selected message so it is always sent as context, or unpins it.
+    pub fn toggle_pin_selected(&mut self) {
+        let sendable = matches!(
+            self.session()
+                .messages
+                .lock()
+                .unwrap()
+                .get(self.selected_message)
+                .map(|message| &message.sender),
+            Some(Sender::User | Sender::AI(_))
+        );
+        if !sendable {
+            self.set_notification(self.t("notification_pin_unsupported"));
+            return;
+        }
+        let index = self.selected_message;
+        match self.session_mut().toggle_pin(index) {
+            Some(true) => self.set_notification(self.t("notification_message_pinned")),
+            Some(false) => self.set_notification(self.t("notification_message_unpinned")),
+            None => {}
+        }
+    }
+
+    fn selected_content(&self) -> Option<String> {
+        self.session()
+            .messages
+            .lock()
+            .unwrap()
+            .get(self.selected_message)
+            .map(|message| message.content.clone())
+    }
+
+    pub fn copy_selected_message(&mut self) {
+        let Some(content) = self.selected_content() else {
+            return;
+        };
+        self.copy_to_clipboard(&content);
+    }
+
+    /// Copies the latest reply in the active session.
+    pub fn copy_last_reply(&mut self) {
+        let reply = self
+            .session()
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|message| matches!(message.sender, Sender::AI(_)))
+            .map(|message| message.content.clone());
+        match reply {
+            Some(content) => self.copy_to_clipboard(&content),
+            None => self.set_notification(self.t("notification_no_reply")),
+        }
+    }
+
+    fn copy_to_clipboard(&mut self, content: &str) {
+        match clipboard::copy(content) {
+            Ok(()) => self.set_notification(self.t("notification_copied")),
+            Err(e) => {
+                self.set_notification(format!("{}: {}", self.t("notification_copy_failed"), e))
+            }
+        }
+    }
+
+    /// Appends the selected message to the input as a Markdown blockquote and starts
+    /// typing below it.
+    pub fn quote_selected_message(&mut self) {
+        let Some(content) = self.selected_content() else {
+            return;
+        };
+        if !self.input.is_empty() && !self.input.ends_with('\n') {
+            self.input.push_str("\n\n");
+        }
+        for line in content.trim_end().lines() {
+            self.input.push('>');
+            if !line.is_empty() {
+                self.input.push(' ');
+                self.input.push_str(line);
+            }
+            self.input.push('\n');
+        }
+        self.input.push('\n');
+        self.input_cursor = None;
+        self.input_mode = InputMode::Editing;
+    }
+
+    pub fn toggle_inspector(&mut self) {
+        self.show_inspector = !self.show_inspector;
+        self.inspector_selected = self.traffic.len().saturating_sub(1);
+    }
+
+    pub fn select_previous_exchange(&mut self) {
+        self.inspector_selected = self.inspector_selected.saturating_sub(1);
+    }
+
+    pub fn select_next_exchange(&mut self) {
+        let last = self.traffic.len().saturating_sub(1);
+        self.inspector_selected = (self.inspector_selected + 1).min(last);
+    }
+
+    pub fn export_selected_exchange(&mut self) {
+        let exchanges = self.traffic.snapshot();
+        let Some(exchange) = exchanges.get(self.inspector_selected) else {
+            self.set_notification(self.t("inspector_empty"));
+            return;
+        };
+        let path = PathBuf::from(format!(
+            "aichat-exchange-{}-{}.json",
+            exchange.id,
+            exchange.started_at.format("%Y%m%d-%H%M%S")
+        ));
+        match exchange.export(&path) {
+            Ok(()) => self.set_notification(format!(
+                "{} {}",
+                self.t("notification_exchange_exported"),
+                path.display()
+            )),
+            Err(e) => {
+                self.set_notification(format!("{} {}", self.t("notification_export_failed"), e))
+            }
+        }
+    }
+
+    /// Writes the active session as Markdown, HTML and JSON into the working directory.
+    pub fn export_session(&mut self) {
+        let Some(conversation) = self.session().snapshot(self.current_model()) else {
+            self.set_notification(self.t("notification_nothing_to_export"));
+            return;
+        };
+        let theme = self.themes.source(self.theme_index).clone();
+        let mut written = Vec::new();
+        for format in ExportFormat::all() {
+            let path = PathBuf::from(format!("aichat-{}.{}", conversation.id, format.extension()));
+            if let Err(e) =
+                std::fs::write(&path, format.render(&conversation, self.language, &theme))
+            {
+                self.set_notification(format!("{} {}", self.t("notification_export_failed"), e));
+                return;
+            }
+            written.push(path.display().to_string());
+        }
+        self.set_notification(format!(
+            "{} {}",
+            self.t("notification_conversation_exported"),
+            written.join(", ")
+        ));
     }
 
     pub fn toggle_help(&mut self) {
         if self.app_state == AppState::Chatting {
             self.show_help = !self.show_help;
+            self.help_seen = true;
         }
     }
 
+    pub fn theme(&self) -> &Theme {
+        self.themes.get(self.theme_index)
+    }
+
     pub fn change_theme(&mut self, index: usize) {
-        if index < 4 {
+        if index < self.themes.len() {
             self.theme_index = index;
             self.set_notification(self.translations.get("notification_theme_changed"));
         }
     }
 
     pub fn next_theme(&mut self) {
-        self.theme_index = (self.theme_index + 1) % 4;
+        self.theme_index = (self.theme_index + 1) % self.themes.len();
         self.set_notification(self.translations.get("notification_theme_changed"));
     }
 
     pub fn previous_theme(&mut self) {
         if self.theme_index == 0 {
-            self.theme_index = 3;
+            self.theme_index = self.themes.len() - 1;
         } else {
             self.theme_index -= 1;
         }
         self.set_notification(self.translations.get("notification_theme_changed"));
     }
 
+    /// Picks up added, removed and edited theme files, staying on the active theme
+    /// while it still exists.
+    pub fn reload_themes(&mut self) {
+        let name = self.theme().name.clone();
+        match self.themes.reload_if_changed() {
+            Some(Ok(())) => self.theme_index = self.themes.position(&name).unwrap_or(0),
+            Some(Err(e)) => self.set_notification(e.to_string()),
+            None => {}
+        }
+    }
+
     pub fn update_cursor_blink(&mut self) {
         let now = Instant::now();
         if now.duration_since(self.last_blink_time) >= Duration::from_millis(500) {
@@ -341,22 +1534,78 @@ impl App {
 
     pub fn clear_input(&mut self) {
         self.input.clear();
+        self.input_cursor = None;
+    }
+
+    /// Where typing goes in `input`.
+    pub fn cursor(&self) -> usize {
+        self.input_cursor
+            .map_or(self.input.len(), |at| at.min(self.input.len()))
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        match self.input_cursor {
+            Some(at) if at < self.input.len() => {
+                self.input.insert(at, c);
+                self.input_cursor = Some(at + c.len_utf8());
+            }
+            _ => {
+                self.input.push(c);
+                self.input_cursor = None;
+            }
+        }
+    }
+
+    /// Deletes the character before the cursor.
+    pub fn delete_char_before_cursor(&mut self) {
+        let at = self.cursor();
+        let previous = motion::previous_char(&self.input, at);
+        self.input.replace_range(previous..at, "");
+        if self.input_cursor.is_some() {
+            self.input_cursor = Some(previous);
+        }
+    }
+
+    /// Leaves typing for moving around the input, with the cursor back on the last
+    /// character typed, as vim does.
+    pub fn enter_editing_normal(&mut self) {
+        let at = self.cursor();
+        let start = motion::line_start(&self.input, at);
+        let at = if at > start {
+            motion::previous_char(&self.input, at)
+        } else {
+            at
+        };
+        self.input_cursor = Some(motion::on_char(&self.input, at));
+        self.input_mode = InputMode::EditingNormal;
+    }
+
+    pub fn start_command_line(&mut self) {
+        self.command_input.clear();
+        self.input_mode = InputMode::Command;
+    }
+
+    pub fn cancel_command_line(&mut self) {
+        self.command_input.clear();
+        self.input_mode = InputMode::Normal;
     }
 
     pub fn switch_language(&mut self, lang: Language) {
-        self.language = lang;
-        self.translations = Translations::new(lang);
+        self.set_language(lang);
         {
-            let mut messages = self.messages.lock().unwrap();
-            if !messages.is_empty() {
-                if let Some(first_msg) = messages.first_mut() {
-                    first_msg.content = self.translations.get("welcome_message");
-                }
+            let mut messages = self.session().messages.lock().unwrap();
+            if let Some(first_msg) = messages.first_mut() {
+                first_msg.content = self.translations.get("welcome_message");
             }
         }
         self.set_notification(self.translations.get("notification_language_changed"));
     }
 
+    fn set_language(&mut self, lang: Language) {
+        self.language = lang;
+        self.translations = Translations::new(lang);
+    }
+
     pub fn switch_to_chinese(&mut self) {
         self.switch_language(Language::Chinese);
     }
@@ -369,11 +1618,852 @@ impl App {
         self.translations.get(key)
     }
 
+    /// Leaves the welcome page. On the first run, with preferences to remember it
+    /// in, the help opens once.
     pub fn start_chatting(&mut self) {
         self.app_state = AppState::Chatting;
+        if self.preferences.is_some() && !self.help_seen {
+            self.show_help = true;
+            self.help_seen = true;
+        }
     }
 
     pub fn show_welcome(&mut self) {
         self.app_state = AppState::Welcome;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::aliyun::AliYunModelType;
+    use crate::ai::limiter::{RateLimiter, RateLimits};
+    use crate::ai::mock::{MockBody, MockReply, MockServer};
+    use crate::storage::TitleSource;
+    use crate::testing::temp_path;
+    use crate::theme::ThemeChoice;
+
+    async fn wait_for_reply(app: &App) -> Vec<Message> {
+        for _ in 0..200 {
+            {
+                let messages = app.session().messages.lock().unwrap();
+                if !messages
+                    .iter()
+                    .any(|msg| matches!(msg.sender, Sender::Thinking(_)))
+                {
+                    return messages.clone();
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        panic!("no reply arrived");
+    }
+
+    fn app_with(server: &MockServer, model: AIModel) -> App {
+        let mut app = App::new();
+        app.providers = server.providers();
+        app.session_mut().selected_model_index =
+            app.ai_models.iter().position(|m| *m == model).unwrap();
+        app
+    }
+
+    async fn ask(app: &mut App, input: &str) -> Vec<Message> {
+        app.input = input.to_string();
+        app.send_message();
+        wait_for_reply(app).await
+    }
+
+    async fn regenerate_last(app: &mut App) -> Vec<Message> {
+        app.start_selecting();
+        app.regenerate_selected();
+        wait_for_reply(app).await
+    }
+
+    const TEST_KEY: &str = "sk-0123456789abcdef0123456789abcdef";
+
+    fn type_key_entry(app: &mut App, text: &str) {
+        app.keys_screen.input = text.to_string();
+        app.finish_key_entry();
+    }
+
+    /// An app on the key screen whose key file holds a DeepSeek key under "hunter2".
+    fn app_with_saved_key(path: &std::path::Path) -> App {
+        let mut app = App::new();
+        app.open_key_store(KeyStore::new(path));
+        app.open_keys();
+        app.start_key_entry(KeyEntry::Key);
+        type_key_entry(&mut app, TEST_KEY);
+        type_key_entry(&mut app, "hunter2");
+        app
+    }
+
+    #[tokio::test]
+    async fn test_send_message_deepseek_end_to_end() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["Mocked ", "answer"]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.input = "What is Rust?".to_string();
+        app.send_message();
+        assert!(app.input.is_empty());
+        let messages = wait_for_reply(&app).await;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1].sender, Sender::User);
+        assert_eq!(messages[1].content, "What is Rust?");
+        assert_eq!(messages[2].sender, Sender::AI(AIModel::DeepSeek));
+        assert_eq!(messages[2].content, "Mocked answer");
+        let request = &server.requests()[0];
+        assert_eq!(request.json()["messages"][0]["content"], "What is Rust?");
+        assert_eq!(request.json()["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn test_config_sets_model_language_and_persona() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["LGTM"]));
+        let config = Config::parse(
+            r#"
+model = "qwen-plus"
+language = "en"
+theme = 2
+persona = "reviewer"
+
+[[personas]]
+name = "reviewer"
+prompt = "You review Rust code."
+"#,
+            std::path::Path::new("config.toml"),
+        )
+        .unwrap();
+        let mut app = App::new();
+        app.apply_config(&config).unwrap();
+        app.providers = server.providers();
+        assert_eq!(
+            app.current_model(),
+            AIModel::AliYun(AliYunModelType::QwenPlus)
+        );
+        assert_eq!(app.language, Language::English);
+        assert_eq!(app.theme_index, 1);
+        app.input = "fn main() {}".to_string();
+        app.send_message();
+        wait_for_reply(&app).await;
+        let request = server.requests()[0].json();
+        assert_eq!(request["messages"][0]["role"], "system");
+        assert_eq!(request["messages"][0]["content"], "You review Rust code.");
+        let messages = request["messages"].as_array().unwrap();
+        assert_eq!(messages.last().unwrap()["content"], "fn main() {}");
+
+        app.next_persona();
+        assert_eq!(app.session().persona, None);
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_key_modifier() {
+        let mut app = App::new();
+        let mut config = Config::default();
+        config.keybindings.normal.insert(
+            "quit".to_string(),
+            crate::config::Keys::One("hyper+q".to_string()),
+        );
+        assert_eq!(
+            app.apply_config(&config).unwrap_err().to_string(),
+            "invalid setting: [keybindings.normal] quit: unknown modifier hyper in hyper+q"
+        );
+    }
+
+    #[test]
+    fn test_config_picks_theme_by_name() {
+        let mut app = App::new();
+        let mut config = Config {
+            theme: Some(ThemeChoice::Name("Neon".to_string())),
+            ..Config::default()
+        };
+        app.apply_config(&config).unwrap();
+        assert_eq!(app.theme().name, "neon");
+        config.theme = Some(ThemeChoice::Name("paper".to_string()));
+        assert_eq!(
+            app.apply_config(&config).unwrap_err().to_string(),
+            "invalid setting: unknown theme paper; the themes are blue, green, orange, neon, light, solarized-light"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queued_request_shows_rate_limit_wait() {
+        let server = MockServer::start().await.unwrap();
+        server.set_fallback(MockReply::stream(&["ok"]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.providers.deepseek.limiter = RateLimiter::new(RateLimits {
+            requests_per_minute: Some(1),
+            ..RateLimits::unlimited()
+        });
+        app.input = "first".to_string();
+        app.send_message();
+        wait_for_reply(&app).await;
+        app.input = "second".to_string();
+        app.send_message();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let messages = app.session().messages.lock().unwrap();
+        let queued = messages.last().unwrap();
+        assert_eq!(queued.sender, Sender::Thinking(AIModel::DeepSeek));
+        assert!(queued.content.contains("waiting for rate limit"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_first_exchange_gets_generated_title() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["Tasks run on a work-stealing pool."]));
+        server.enqueue(MockReply::stream(&["Title: ", "\"Tokio Task Scheduling\""]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.title_model = Some(AIModel::DeepSeek);
+        app.input = "How does tokio schedule tasks?".to_string();
+        app.send_message();
+        app.generate_titles();
+        wait_for_reply(&app).await;
+        assert_eq!(server.requests().len(), 1);
+        assert_eq!(
+            app.title().as_deref(),
+            Some("How does tokio schedule tasks?")
+        );
+
+        app.generate_titles();
+        app.generate_titles();
+        for _ in 0..200 {
+            if app.session().auto_title.lock().unwrap().is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        assert_eq!(app.title().as_deref(), Some("Tokio Task Scheduling"));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        let system = requests[1].json()["messages"][0]["content"].clone();
+        assert!(system.as_str().unwrap().contains("in English"));
+    }
+
+    #[test]
+    fn test_clearing_a_rename_brings_back_the_generated_title() {
+        let mut app = App::new();
+        *app.session().auto_title.lock().unwrap() = Some("Tokio Task Scheduling".to_string());
+        app.start_rename();
+        app.rename_input = "Mine".to_string();
+        app.finish_rename();
+        assert_eq!(app.title().as_deref(), Some("Mine"));
+        app.start_rename();
+        app.rename_input.clear();
+        app.finish_rename();
+        assert_eq!(app.title().as_deref(), Some("Tokio Task Scheduling"));
+    }
+
+    #[tokio::test]
+    async fn test_resumed_untitled_conversation_gets_generated_title() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["Title: Borrow Checker Basics"]));
+        let now = Local::now();
+        let conversation = |id: &str, title: &str, title_source| Conversation {
+            id: id.to_string(),
+            title: title.to_string(),
+            title_source,
+            model: AIModel::DeepSeek,
+            persona: None,
+            created_at: now,
+            updated_at: now,
+            messages: vec![
+                Message::new("Why does the borrow checker complain?", Sender::User),
+                Message::new(
+                    "Two mutable borrows overlap.",
+                    Sender::AI(AIModel::DeepSeek),
+                ),
+            ],
+        };
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.title_model = Some(AIModel::DeepSeek);
+        app.resume_conversation(conversation(
+            "untitled",
+            "Why does the borrow checker complain?",
+            Some(TitleSource::Fallback),
+        ));
+        app.resume_conversation(conversation(
+            "generated",
+            "Overlapping Borrows",
+            Some(TitleSource::Generated),
+        ));
+        app.resume_conversation(conversation("renamed", "Mine", Some(TitleSource::Manual)));
+        app.generate_titles();
+        for _ in 0..200 {
+            if app.sessions[0].auto_title.lock().unwrap().is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        assert_eq!(
+            app.sessions[0].title().as_deref(),
+            Some("Borrow Checker Basics")
+        );
+        assert_eq!(
+            app.sessions[1].title().as_deref(),
+            Some("Overlapping Borrows")
+        );
+        assert_eq!(app.sessions[2].title().as_deref(), Some("Mine"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_partial_reply_is_journaled_and_recovered() {
+        let server = MockServer::start().await.unwrap();
+        let mut reply = MockReply::stream(&["partial ", "rest"]);
+        if let MockBody::Sse(chunks) = &mut reply.body {
+            chunks[1].delay = Duration::from_secs(30);
+        }
+        server.enqueue(reply);
+        let path = temp_path("journal.json");
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.open_journal(JournalFile::new(&path));
+        assert!(app.recovery.is_none());
+        app.input = "Tell me a story".to_string();
+        app.send_message();
+        for _ in 0..200 {
+            if app.session().messages.lock().unwrap()[2].content == "partial " {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        app.autosave();
+
+        let mut restarted = App::new();
+        restarted.open_journal(JournalFile::new(&path));
+        assert_eq!(restarted.recovery.as_ref().unwrap().sessions.len(), 1);
+        restarted.autosave();
+        restarted.recover_journal();
+        assert_eq!(restarted.app_state, AppState::Chatting);
+        let messages = restarted.session().messages.lock().unwrap().clone();
+        assert_eq!(messages[1].content, "Tell me a story");
+        assert_eq!(messages[2].sender, Sender::Error(AIModel::DeepSeek));
+        assert_eq!(messages[2].content, "partial\n\n⚠️ Reply interrupted");
+        restarted.close_journal();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_completed_reply_is_journaled_as_complete() {
+        let server = MockServer::start().await.unwrap();
+        let mut reply = MockReply::stream(&["whole answer"]);
+        if let MockBody::Sse(chunks) = &mut reply.body {
+            chunks[1].delay = Duration::from_millis(200);
+        }
+        server.enqueue(reply);
+        let path = temp_path("journal.json");
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.open_journal(JournalFile::new(&path));
+        app.input = "Tell me a story".to_string();
+        app.send_message();
+        for _ in 0..200 {
+            if app.session().messages.lock().unwrap()[2].content == "whole answer" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        app.autosave();
+        wait_for_reply(&app).await;
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        app.autosave();
+
+        let mut restarted = App::new();
+        restarted.open_journal(JournalFile::new(&path));
+        restarted.recover_journal();
+        let messages = restarted.session().messages.lock().unwrap().clone();
+        assert_eq!(messages[2].sender, Sender::AI(AIModel::DeepSeek));
+        assert_eq!(messages[2].content, "whole answer");
+        restarted.close_journal();
+    }
+
+    #[tokio::test]
+    async fn test_reply_without_chunks_is_recovered_as_interrupted() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::completion("late").delayed(Duration::from_secs(30)));
+        let path = temp_path("journal.json");
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.open_journal(JournalFile::new(&path));
+        app.input = "Tell me a story".to_string();
+        app.send_message();
+        app.autosave();
+
+        let mut restarted = App::new();
+        restarted.open_journal(JournalFile::new(&path));
+        restarted.recover_journal();
+        let messages = restarted.session().messages.lock().unwrap().clone();
+        assert_eq!(messages[2].sender, Sender::Error(AIModel::DeepSeek));
+        assert_eq!(messages[2].content, "⚠️ Reply interrupted");
+        restarted.close_journal();
+    }
+
+    #[test]
+    fn test_preferences_are_saved_and_restored() {
+        let path = temp_path("preferences.json");
+        let mut app = App::new();
+        app.restore_preferences(PreferencesFile::new(&path));
+        app.switch_to_chinese();
+        app.change_theme(4);
+        app.select_model(AIModel::Claude);
+        app.autosave();
+
+        let mut restarted = App::new();
+        restarted.restore_preferences(PreferencesFile::new(&path));
+        assert_eq!(restarted.language, Language::Chinese);
+        assert_eq!(restarted.theme().name, "light");
+        assert_eq!(restarted.current_model(), AIModel::Claude);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_help_opens_on_the_first_run_only() {
+        let path = temp_path("preferences.json");
+        let mut app = App::new();
+        app.restore_preferences(PreferencesFile::new(&path));
+        app.start_chatting();
+        assert!(app.show_help);
+        app.autosave();
+
+        let mut restarted = App::new();
+        restarted.restore_preferences(PreferencesFile::new(&path));
+        restarted.start_chatting();
+        assert!(!restarted.show_help);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_config_language_overrides_saved_preferences() {
+        let path = temp_path("preferences.json");
+        let mut app = App::new();
+        app.restore_preferences(PreferencesFile::new(&path));
+        app.switch_to_chinese();
+        app.change_theme(4);
+        app.autosave();
+
+        let mut restarted = App::new();
+        restarted.restore_preferences(PreferencesFile::new(&path));
+        let config = Config {
+            language: Some(Language::English),
+            ..Config::default()
+        };
+        restarted.apply_config(&config).unwrap();
+        assert_eq!(restarted.language, Language::English);
+        assert_eq!(restarted.theme().name, "light");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shorter_model_list_keeps_a_valid_selection() {
+        let path = temp_path("preferences.json");
+        let mut app = App::new();
+        app.restore_preferences(PreferencesFile::new(&path));
+        app.select_model(AIModel::LocalLLM);
+        app.autosave();
+
+        let mut restarted = App::new();
+        restarted.restore_preferences(PreferencesFile::new(&path));
+        assert_eq!(restarted.current_model(), AIModel::LocalLLM);
+        let config = Config {
+            models: Some(vec!["deepseek".to_string()]),
+            ..Config::default()
+        };
+        restarted.apply_config(&config).unwrap();
+        assert_eq!(restarted.current_model(), AIModel::DeepSeek);
+        let config = Config {
+            models: Some(vec!["qwen-plus".to_string(), "deepseek".to_string()]),
+            ..Config::default()
+        };
+        restarted.apply_config(&config).unwrap();
+        assert_eq!(restarted.current_model(), AIModel::DeepSeek);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_key_screen_asks_for_a_passphrase_before_saving() {
+        let path = temp_path("keys.json");
+        let mut app = App::new();
+        app.open_key_store(KeyStore::new(&path));
+        app.open_keys();
+        assert_eq!(app.input_mode, InputMode::Keys);
+        app.start_key_entry(KeyEntry::Key);
+        type_key_entry(&mut app, TEST_KEY);
+        assert_eq!(app.keys_screen.entry, Some(KeyEntry::Passphrase));
+        assert_eq!(app.providers.deepseek.api_key, None);
+        type_key_entry(&mut app, "hunter2");
+        assert_eq!(app.input_mode, InputMode::Keys);
+        assert_eq!(app.notification.as_deref(), Some("Keys saved"));
+        assert_eq!(app.providers.deepseek.api_key.as_deref(), Some(TEST_KEY));
+        let mut reopened = KeyStore::new(&path);
+        reopened.unlock("hunter2").unwrap();
+        app.close_keys();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_key_profiles_hold_their_own_keys() {
+        let path = temp_path("keys.json");
+        let mut app = app_with_saved_key(&path);
+        app.start_key_entry(KeyEntry::Profile);
+        type_key_entry(&mut app, "work");
+        assert_eq!(app.keys_screen.profile, "work");
+        app.select_next_key();
+        app.start_key_entry(KeyEntry::Key);
+        type_key_entry(&mut app, "sk-0123456789abcdef0123456789abcdef");
+        assert_eq!(app.providers.aliyun.api_key, None);
+        app.use_key_profile();
+        assert!(app.providers.aliyun.api_key.is_some());
+        assert_eq!(app.providers.deepseek.api_key, None);
+        let mut reopened = KeyStore::new(&path);
+        reopened.unlock("hunter2").unwrap();
+        assert_eq!(reopened.keyring.active, "work");
+        app.switch_key_profile(true);
+        assert_eq!(app.keys_screen.profile, "default");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_suspicious_key_is_saved_with_a_warning() {
+        let path = temp_path("keys.json");
+        let mut app = app_with_saved_key(&path);
+        app.select_next_key();
+        app.start_key_entry(KeyEntry::Key);
+        type_key_entry(&mut app, "ak-123");
+        assert_eq!(
+            app.notification.as_deref(),
+            Some("Key saved, but it looks wrong: aliyun keys start with sk-")
+        );
+        assert_eq!(app.providers.aliyun.api_key.as_deref(), Some("ak-123"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_last_key_profile_cannot_be_deleted() {
+        let path = temp_path("keys.json");
+        let mut app = app_with_saved_key(&path);
+        app.start_key_entry(KeyEntry::Profile);
+        type_key_entry(&mut app, "work");
+        app.delete_key_profile();
+        assert_eq!(app.keys_screen.profile, "default");
+        app.delete_key_profile();
+        assert_eq!(
+            app.notification.as_deref(),
+            Some("The last profile cannot be deleted")
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_autosave_and_resume() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["Saved answer"]));
+        let store = ConversationStore::new(temp_path("resume"));
+        let model = AIModel::AliYun(AliYunModelType::QwenPlus);
+        let mut app = app_with(&server, model.clone());
+        app.store = Some(store.clone());
+        app.input = "Remember me".to_string();
+        app.send_message();
+        app.autosave();
+        assert!(store.list().unwrap().is_empty());
+        wait_for_reply(&app).await;
+        app.autosave();
+        let mut resumed = App::new();
+        resumed.store = Some(store.clone());
+        resumed.resume_latest();
+        assert_eq!(resumed.app_state, AppState::Chatting);
+        assert_eq!(resumed.session().id, app.session().id);
+        assert_eq!(resumed.title().as_deref(), Some("Remember me"));
+        assert_eq!(resumed.current_model(), model);
+        let messages = resumed.session().messages.lock().unwrap();
+        assert_eq!(messages.last().unwrap().content, "Saved answer");
+        std::fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_edit_and_resend_keeps_old_branch() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["first answer"]));
+        server.enqueue(MockReply::stream(&["second answer"]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.input = "first question".to_string();
+        app.send_message();
+        wait_for_reply(&app).await;
+        app.start_selecting();
+        app.select_previous_message();
+        app.edit_selected_message();
+        assert_eq!(app.input, "first question");
+        assert_eq!(app.input_mode, InputMode::Editing);
+        app.input = "better question".to_string();
+        app.send_message();
+        let messages = wait_for_reply(&app).await;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1].content, "better question");
+        assert_eq!(messages[2].content, "second answer");
+        let branches = messages[1].branches.as_ref().unwrap();
+        assert_eq!((branches.position(), branches.count()), (2, 2));
+        assert_eq!(
+            server.requests()[1].json()["messages"]
+                .as_array()
+                .unwrap()
+                .last()
+                .unwrap()["content"],
+            "better question"
+        );
+        app.start_selecting();
+        app.select_previous_message();
+        app.switch_selected_branch(false);
+        let messages = app.session().messages.lock().unwrap().clone();
+        assert_eq!(messages[1].content, "first question");
+        assert_eq!(messages[2].content, "first answer");
+    }
+
+    #[tokio::test]
+    async fn test_deleted_message_leaves_context() {
+        let server = MockServer::start().await.unwrap();
+        server.set_fallback(MockReply::stream(&["answer"]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        ask(&mut app, "first").await;
+        ask(&mut app, "second").await;
+        app.start_selecting();
+        app.select_previous_message();
+        app.select_previous_message();
+        app.delete_selected_message();
+        let messages = app.session().messages.lock().unwrap().clone();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2].content, "second");
+        app.stop_selecting();
+        ask(&mut app, "why?").await;
+        let sent: Vec<String> = server.requests()[2].json()["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["content"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(sent, vec!["first", "second", "answer", "why?"]);
+    }
+
+    #[test]
+    fn test_pin_toggles_on_the_selected_message() {
+        let mut app = App::new();
+        app.session().messages.lock().unwrap().extend([
+            Message::new("question", Sender::User),
+            Message::new("answer", Sender::AI(AIModel::DeepSeek)),
+        ]);
+        app.start_selecting();
+        app.select_previous_message();
+        app.toggle_pin_selected();
+        let messages = app.session().messages.lock().unwrap().clone();
+        assert!(messages[0].pinned);
+        assert!(!messages[1].pinned);
+        app.toggle_pin_selected();
+        assert!(!app.session().messages.lock().unwrap()[0].pinned);
+    }
+
+    #[test]
+    fn test_quote_fills_input() {
+        let mut app = App::new();
+        app.session().messages.lock().unwrap().extend([
+            Message::new("question", Sender::User),
+            Message::new("answer", Sender::AI(AIModel::DeepSeek)),
+        ]);
+        app.start_selecting();
+        app.quote_selected_message();
+        assert_eq!(app.input_mode, InputMode::Editing);
+        assert_eq!(app.input, "> answer\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_regenerate_uses_the_current_model_and_temperature() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["first"]));
+        server.enqueue(MockReply::stream(&["second"]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        ask(&mut app, "question").await;
+        let qwen = AIModel::AliYun(AliYunModelType::QwenPlus);
+        app.session_mut().selected_model_index =
+            app.ai_models.iter().position(|m| *m == qwen).unwrap();
+        app.adjust_temperature(0.1);
+        let messages = regenerate_last(&mut app).await;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2].content, "second");
+        assert_eq!(messages[2].sender, Sender::AI(qwen));
+        let branches = messages[2].branches.as_ref().unwrap();
+        assert_eq!((branches.position(), branches.count()), (2, 2));
+        let request = server.requests()[1].json();
+        assert_eq!(request["model"], "qwen-plus");
+        assert!((request["temperature"].as_f64().unwrap() - 0.8).abs() < 1e-6);
+        assert_eq!(request["messages"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_chosen_reply_is_context() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["first"]));
+        server.enqueue(MockReply::stream(&["second"]));
+        server.enqueue(MockReply::stream(&["follow-up"]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        ask(&mut app, "question").await;
+        regenerate_last(&mut app).await;
+        app.stop_selecting();
+        ask(&mut app, "next").await;
+        let history: Vec<String> = server.requests()[2].json()["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["content"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(history, ["question", "second", "next"]);
+    }
+
+    #[tokio::test]
+    async fn test_switching_back_to_an_earlier_reply_drops_what_followed() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["first"]));
+        server.enqueue(MockReply::stream(&["second"]));
+        server.enqueue(MockReply::stream(&["follow-up"]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        ask(&mut app, "question").await;
+        regenerate_last(&mut app).await;
+        app.stop_selecting();
+        ask(&mut app, "next").await;
+        app.start_selecting();
+        app.selected_message = 2;
+        app.switch_selected_branch(false);
+        let messages = app.session().messages.lock().unwrap().clone();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2].content, "first");
+    }
+
+    #[test]
+    fn test_search_opens_result_scrolled_and_highlighted() {
+        let store = ConversationStore::new(temp_path("search"));
+        let now = Local::now();
+        let mut messages: Vec<Message> = (0..10)
+            .map(|i| Message::new(format!("line {}\nmore", i), Sender::User))
+            .collect();
+        messages.push(Message::new(
+            "The answer about Tokio",
+            Sender::AI(AIModel::DeepSeek),
+        ));
+        store
+            .save(&Conversation {
+                id: "saved".to_string(),
+                title: "Saved".to_string(),
+                title_source: Some(TitleSource::Manual),
+                model: AIModel::DeepSeek,
+                persona: None,
+                created_at: now,
+                updated_at: now,
+                messages,
+            })
+            .unwrap();
+        let mut app = App::new();
+        app.store = Some(store.clone());
+        app.open_search();
+        assert_eq!(app.input_mode, InputMode::Searching);
+        for c in "tokio".chars() {
+            app.search_push(c);
+        }
+        assert_eq!(app.search.results.len(), 1);
+        app.open_search_result();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert_eq!(app.app_state, AppState::Chatting);
+        let session = app.session();
+        assert_eq!(session.id, "saved");
+        assert!(!session.auto_scroll);
+        assert_eq!(session.ai_list_state.selected(), Some(40 / 3));
+        assert_eq!(
+            session.highlight,
+            Some(SearchHighlight {
+                message_index: 10,
+                terms: vec!["tokio".to_string()],
+            })
+        );
+        std::fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_sessions_keep_their_own_model() {
+        let mut app = App::new();
+        app.new_session();
+        let qwen = AIModel::AliYun(AliYunModelType::QwenTurbo);
+        app.session_mut().selected_model_index =
+            app.ai_models.iter().position(|m| *m == qwen).unwrap();
+        assert_eq!(app.sessions.len(), 2);
+        assert_eq!(app.sessions[0].selected_model_index, 0);
+        assert_eq!(app.current_model(), qwen);
+        app.previous_session();
+        app.close_session();
+        assert_eq!(app.sessions.len(), 1);
+        assert_eq!(app.current_model(), qwen);
+    }
+
+    #[tokio::test]
+    async fn test_background_reply_is_badged_until_viewed() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["slow"]).delayed(Duration::from_millis(200)));
+        server.enqueue(MockReply::stream(&["fast"]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.input = "first".to_string();
+        app.send_message();
+        app.new_session();
+        ask(&mut app, "second").await;
+        app.autosave();
+        assert!(!app.session().is_unread());
+        while app.sessions[0].has_pending() {
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        assert!(app.sessions[0].is_unread());
+        let first = app.sessions[0].messages.lock().unwrap().clone();
+        assert_eq!(first.last().unwrap().content, "slow");
+        assert_eq!(first.len(), 3);
+        app.previous_session();
+        assert!(!app.session().is_unread());
+    }
+
+    #[tokio::test]
+    async fn test_inspector_captures_exchange() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["Hi"]));
+        let mut app = app_with(&server, AIModel::DeepSeek);
+        app.input = "Hello".to_string();
+        app.send_message();
+        wait_for_reply(&app).await;
+        let exchanges = app.traffic.snapshot();
+        assert_eq!(exchanges.len(), 1);
+        let exchange = &exchanges[0];
+        assert_eq!(exchange.provider, "deepseek");
+        assert_eq!(exchange.status, Some(200));
+        assert!(exchange.request_body.contains("\"Hello\""));
+        assert!(
+            exchange
+                .request_headers
+                .iter()
+                .all(|(_, value)| !value.contains("sk-mock"))
+        );
+        assert!(
+            exchange
+                .sse_lines
+                .iter()
+                .any(|sse| sse.line == "data: [DONE]")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_message_aliyun_reports_api_error() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::error(
+            429,
+            "Throttling",
+            "Requests rate limit exceeded",
+        ));
+        let model = AIModel::AliYun(AliYunModelType::QwenTurbo);
+        let mut app = app_with(&server, model.clone());
+        app.input = "Hello".to_string();
+        app.send_message();
+        let messages = wait_for_reply(&app).await;
+        let reply = messages.last().unwrap();
+        assert_eq!(reply.sender, Sender::Error(model));
+        assert!(reply.content.starts_with("⏳ Rate limited · aliyun"));
+        assert!(reply.content.contains("HTTP 429 (Throttling)"));
+    }
+}
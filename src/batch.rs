@@ -0,0 +1,359 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::deepseek::{Usage, create_system_message, create_user_message};
+use crate::ai::{Prompt, Providers, complete};
+use crate::ai_models::AIModel;
+use crate::i18n::Language;
+
+/// One line of the input file, e.g.
+/// `{"id": "q1", "prompt": "...", "model": "qwen-plus", "params": {"temperature": 0.2}}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchRequest {
+    /// Names the result; the line number when unset.
+    #[serde(default)]
+    pub id: String,
+    pub prompt: String,
+    /// The default model when unset.
+    pub model: Option<String>,
+    pub system: Option<String>,
+    #[serde(default)]
+    pub params: Params,
+    #[serde(skip)]
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Params {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+}
+
+/// One line of the output file. `latency_ms` includes any wait for the rate limit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub id: String,
+    pub line: usize,
+    pub model: AIModel,
+    pub reply: Option<String>,
+    pub usage: Option<Usage>,
+    pub latency_ms: u64,
+    pub error: Option<BatchError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchError {
+    /// `no_api` or a provider error kind such as `rate_limited`.
+    pub kind: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Requests that already had a successful result in the output file.
+    pub skipped: usize,
+}
+
+/// Reads the requests, one JSON object per line; blank lines are ignored. Any bad
+/// line or repeated id rejects the whole file, before anything is sent.
+pub fn parse_requests(text: &str) -> io::Result<Vec<BatchRequest>> {
+    let mut requests = Vec::new();
+    let mut ids = HashSet::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, message),
+            )
+        };
+        let mut request: BatchRequest =
+            serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?;
+        request.line = index + 1;
+        if request.id.is_empty() {
+            request.id = request.line.to_string();
+        }
+        if !ids.insert(request.id.clone()) {
+            return Err(invalid(format!("id {} is used twice", request.id)));
+        }
+        requests.push(request);
+    }
+    Ok(requests)
+}
+
+/// The ids with a successful result in `output`. Lines cut off by an interruption
+/// are skipped, so their requests run again.
+fn finished_ids(output: &Path) -> io::Result<HashSet<String>> {
+    let text = match fs::read_to_string(output) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str::<BatchResult>(line).ok())
+        .filter(|result| result.error.is_none())
+        .map(|result| result.id)
+        .collect())
+}
+
+fn open_for_append(output: &Path) -> io::Result<File> {
+    let mut file = OpenOptions::new().create(true).append(true).open(output)?;
+    let ends_cleanly = fs::read(output)?.last().is_none_or(|byte| *byte == b'\n');
+    if !ends_cleanly {
+        file.write_all(b"\n")?;
+    }
+    Ok(file)
+}
+
+impl BatchRequest {
+    async fn run(
+        &self,
+        default_model: &AIModel,
+        language: Option<Language>,
+        providers: &Providers,
+    ) -> BatchResult {
+        let model = self
+            .model
+            .as_deref()
+            .map(AIModel::from_api_name)
+            .unwrap_or_else(|| default_model.clone());
+        let mut messages: Vec<_> = self
+            .system
+            .as_deref()
+            .map(create_system_message)
+            .into_iter()
+            .collect();
+        messages.push(create_user_message(&self.prompt));
+        let prompt = Prompt {
+            messages,
+            temperature: self.params.temperature,
+        };
+        let start = Instant::now();
+        let outcome = complete(
+            &model,
+            &prompt,
+            self.params.max_tokens,
+            language,
+            providers,
+            None,
+        )
+        .await;
+        let mut result = BatchResult {
+            id: self.id.clone(),
+            line: self.line,
+            model: model.clone(),
+            reply: None,
+            usage: None,
+            latency_ms: start.elapsed().as_millis() as u64,
+            error: None,
+        };
+        match outcome {
+            Some(Ok(completion)) => {
+                result.reply = Some(completion.content);
+                result.usage = Some(completion.usage);
+            }
+            Some(Err(e)) => {
+                result.error = Some(BatchError {
+                    kind: e.kind().to_string(),
+                    message: e.to_string(),
+                })
+            }
+            None => {
+                result.error = Some(BatchError {
+                    kind: "no_api".to_string(),
+                    message: format!("{} has no API to call", model),
+                })
+            }
+        }
+        result
+    }
+}
+
+/// Runs `requests` at most `concurrency` at a time, still subject to each provider's
+/// rate limits, and appends a result line to `output` as each one finishes. Requests
+/// that already succeeded in `output` are skipped, so an interrupted run can resume.
+/// Prompts are sent as written; Qwen models are told to answer in `language` only
+/// when it is given.
+pub async fn run(
+    requests: &[BatchRequest],
+    default_model: &AIModel,
+    language: Option<Language>,
+    providers: &Providers,
+    concurrency: usize,
+    output: &Path,
+) -> io::Result<Summary> {
+    let finished = finished_ids(output)?;
+    let mut file = open_for_append(output)?;
+    let pending: Vec<_> = requests
+        .iter()
+        .filter(|request| !finished.contains(&request.id))
+        .collect();
+    let mut summary = Summary {
+        skipped: requests.len() - pending.len(),
+        ..Summary::default()
+    };
+    let mut results = futures::stream::iter(
+        pending
+            .into_iter()
+            .map(|request| request.run(default_model, language, providers)),
+    )
+    .buffer_unordered(concurrency.max(1));
+    while let Some(result) = results.next().await {
+        if result.error.is_some() {
+            summary.failed += 1;
+        } else {
+            summary.succeeded += 1;
+        }
+        let line = serde_json::to_string(&result)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writeln!(file, "{}", line)?;
+        file.flush()?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::mock::{MockReply, MockServer};
+    use crate::testing::temp_path;
+
+    #[test]
+    fn test_parse_requests_numbers_lines() {
+        let requests = parse_requests(
+            "{\"prompt\": \"a\"}\n\n{\"id\": \"b\", \"prompt\": \"b\", \"params\": {\"max_tokens\": 5}}\n",
+        )
+        .unwrap();
+        assert_eq!(requests[0].id, "1");
+        assert_eq!(requests[1].id, "b");
+        assert_eq!(requests[1].line, 3);
+        assert_eq!(requests[1].params.max_tokens, Some(5));
+        let error = parse_requests("{\"prompt\": \"a\"}\n{\"promt\": \"b\"}").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("line 2: unknown field `promt`")
+        );
+        let error = parse_requests(
+            "{\"id\": \"x\", \"prompt\": \"a\"}\n{\"id\": \"x\", \"prompt\": \"b\"}",
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "line 2: id x is used twice");
+    }
+
+    #[tokio::test]
+    async fn test_results_record_usage_and_errors_and_resume() {
+        let server = MockServer::start().await.unwrap();
+        let output = temp_path("batch.jsonl");
+        let requests = parse_requests(
+            "{\"id\": \"ok\", \"prompt\": \"hi\", \"params\": {\"temperature\": 0.5}}\n\
+             {\"id\": \"fails\", \"prompt\": \"hi\", \"model\": \"qwen-plus\"}\n\
+             {\"id\": \"simulated\", \"prompt\": \"hi\", \"model\": \"claude\"}\n",
+        )
+        .unwrap();
+        server.enqueue(MockReply::completion("hello"));
+        server.enqueue(MockReply::error(429, "rate_limit_exceeded", "slow down"));
+        let summary = run(
+            &requests,
+            &AIModel::DeepSeek,
+            None,
+            &server.providers(),
+            1,
+            &output,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                succeeded: 1,
+                failed: 2,
+                skipped: 0
+            }
+        );
+        let results: Vec<BatchResult> = fs::read_to_string(&output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let ok = results.iter().find(|r| r.id == "ok").unwrap();
+        assert_eq!(ok.reply.as_deref(), Some("hello"));
+        assert_eq!(ok.usage.unwrap().total_tokens, 2);
+        let fails = results.iter().find(|r| r.id == "fails").unwrap();
+        assert_eq!(fails.error.as_ref().unwrap().kind, "rate_limited");
+        let simulated = results.iter().find(|r| r.id == "simulated").unwrap();
+        assert_eq!(simulated.error.as_ref().unwrap().kind, "no_api");
+        assert_eq!(server.requests()[0].json()["temperature"], 0.5);
+        assert_eq!(server.requests()[0].json()["stream"], false);
+
+        // An interrupted write leaves half a line behind.
+        let mut file = OpenOptions::new().append(true).open(&output).unwrap();
+        file.write_all(b"{\"id\": \"fai").unwrap();
+        server.enqueue(MockReply::completion("retried"));
+        let summary = run(
+            &requests[..2],
+            &AIModel::DeepSeek,
+            None,
+            &server.providers(),
+            4,
+            &output,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                succeeded: 1,
+                failed: 0,
+                skipped: 1
+            }
+        );
+        let text = fs::read_to_string(&output).unwrap();
+        let last: BatchResult = serde_json::from_str(text.lines().last().unwrap()).unwrap();
+        assert_eq!(last.id, "fails");
+        assert_eq!(last.reply.as_deref(), Some("retried"));
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_request_is_sent_as_written() {
+        let server = MockServer::start().await.unwrap();
+        let output = temp_path("batch.jsonl");
+        let requests = parse_requests(
+            "{\"prompt\": \"Translate: bonjour\", \"system\": \"Reply with one word.\", \"model\": \"qwen-plus\"}\n",
+        )
+        .unwrap();
+        server.enqueue(MockReply::completion("hello"));
+        run(
+            &requests,
+            &AIModel::DeepSeek,
+            None,
+            &server.providers(),
+            1,
+            &output,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            server.requests()[0].json()["messages"],
+            serde_json::json!([
+                { "role": "system", "content": "Reply with one word." },
+                { "role": "user", "content": "Translate: bonjour" }
+            ])
+        );
+        fs::remove_file(&output).unwrap();
+    }
+}
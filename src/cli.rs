@@ -0,0 +1,559 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::ai::{Providers, cassette};
+use crate::ai_models::AIModel;
+use crate::batch;
+use crate::config::{Config, ConfigError};
+use crate::export::ExportFormat;
+use crate::i18n::Language;
+use crate::import::ImportFormat;
+use crate::keystore::{KeyStore, PASSPHRASE_ENV_VAR};
+use crate::oneshot::{Ask, AskError, attach};
+use crate::storage::{Conversation, ConversationStore};
+use crate::theme::{ThemeChoice, ThemeRegistry};
+
+/// Command-line options. Without any, the TUI starts on the welcome screen.
+#[derive(Debug, Parser)]
+#[command(
+    name = "aichat",
+    version,
+    about = "A terminal-based AI chat application"
+)]
+pub struct Cli {
+    /// Resume a saved conversation by id, or `last` for the most recent one. With a
+    /// prompt, the question and its reply are added to that conversation.
+    #[arg(long, value_name = "ID", conflicts_with = "continue_last")]
+    pub resume: Option<String>,
+
+    /// Resume the most recent conversation, like `--resume last`.
+    #[arg(long = "continue", short = 'c', short_alias = 'r')]
+    pub continue_last: bool,
+
+    /// List saved conversations and exit.
+    #[arg(long, short = 'l')]
+    pub list: bool,
+
+    /// Config file to use instead of ~/.config/aichat/config.toml.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Model to start with, e.g. qwen-plus or deepseek.
+    #[arg(long, short = 'm')]
+    pub model: Option<String>,
+
+    /// en or zh.
+    #[arg(long)]
+    pub language: Option<Language>,
+
+    /// Theme to start with, by name or number.
+    #[arg(long)]
+    pub theme: Option<ThemeChoice>,
+
+    /// Key profile to use instead of the one selected on the key screen.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Ask once, stream the reply to stdout and exit instead of starting the TUI.
+    /// `-` asks the text piped to stdin.
+    #[arg(value_name = "PROMPT")]
+    pub prompt: Vec<String>,
+
+    /// What to do with the text piped to stdin, e.g. `git diff | aichat -p "write a
+    /// commit message"`. Piped text without a prompt is asked as it is with `-`.
+    #[arg(long = "prompt", short = 'p', value_name = "PROMPT")]
+    pub instruction: Option<String>,
+
+    /// System prompt for the question; the configured persona's prompt when unset.
+    #[arg(long, short = 's')]
+    pub system: Option<String>,
+
+    /// Sampling temperature for the question, 0-2.
+    #[arg(long, short = 't', value_parser = parse_temperature)]
+    pub temperature: Option<f32>,
+
+    /// Save the question and its reply as a new conversation and print its id to stderr.
+    #[arg(long)]
+    pub save: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// The conversation to resume: an id, `last`, or `None` to start afresh.
+    pub fn resume_id(&self) -> Option<&str> {
+        if self.continue_last {
+            Some("last")
+        } else {
+            self.resume.as_deref()
+        }
+    }
+
+    /// Whether stdin should be read: only for a one-shot question, so a plain `aichat`
+    /// with stdin left open, e.g. under an editor or `ssh -T`, still starts the TUI.
+    /// A terminal is only read from when `-` asks for it.
+    pub fn reads_stdin(&self, stdin_is_terminal: bool) -> bool {
+        let explicit = self.prompt.iter().any(|part| part == "-");
+        explicit || (!stdin_is_terminal && (self.instruction.is_some() || !self.prompt.is_empty()))
+    }
+
+    /// The one-shot question: `-p` and the prompt words, with `piped` (stdin, when
+    /// `reads_stdin`) attached. `None` when there is nothing to ask.
+    pub fn question(&self, piped: Option<&str>) -> Option<String> {
+        let text = self
+            .instruction
+            .iter()
+            .chain(&self.prompt)
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty() && *part != "-")
+            .collect::<Vec<_>>()
+            .join(" ");
+        let piped = piped.filter(|piped| !piped.trim().is_empty());
+        match (text.is_empty(), piped) {
+            (false, Some(piped)) => Some(attach(&text, piped)),
+            (false, None) => Some(text),
+            (true, Some(piped)) => Some(piped.to_string()),
+            (true, None) => None,
+        }
+    }
+}
+
+fn parse_temperature(text: &str) -> Result<f32, String> {
+    match text.parse::<f32>() {
+        Ok(temperature) if (0.0..=2.0).contains(&temperature) => Ok(temperature),
+        _ => Err(format!("{} is not a temperature between 0 and 2", text)),
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Export a saved conversation as Markdown, HTML or JSON.
+    Export(ExportArgs),
+    /// Import conversations from a ChatGPT export or an OpenAI-style JSONL log.
+    Import(ImportArgs),
+    /// Run every request in a JSONL file and write the results as JSONL.
+    Batch(BatchArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Conversation id, or `last` for the most recent one.
+    #[arg(default_value = "last")]
+    pub id: String,
+
+    /// md, html or json.
+    #[arg(long, short = 'f', default_value = "md")]
+    pub format: ExportFormat,
+
+    /// Output file; defaults to stdout.
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+
+    /// Theme for HTML export, by name or number as in the TUI.
+    #[arg(long, default_value = "1")]
+    pub theme: ThemeChoice,
+
+    /// Use Chinese speaker names and dates.
+    #[arg(long)]
+    pub chinese: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// `conversations.json` from a ChatGPT data export, or a JSONL message log.
+    pub file: PathBuf,
+
+    /// chatgpt or jsonl; detected from the file when omitted.
+    #[arg(long, short = 'f')]
+    pub format: Option<ImportFormat>,
+}
+
+#[derive(Debug, Args)]
+pub struct BatchArgs {
+    /// One request per line: `{"id", "prompt", "model", "system", "params"}`.
+    pub file: PathBuf,
+
+    /// Results file; defaults to the input name with `.results.jsonl`. Requests that
+    /// already succeeded there are skipped, so an interrupted run can be resumed.
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+
+    /// Requests in flight at once, on top of the providers' own rate limits.
+    #[arg(long, short = 'j', default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: u16,
+
+    /// Model for requests that do not name one; the configured model otherwise.
+    #[arg(long, short = 'm')]
+    pub model: Option<String>,
+}
+
+impl BatchArgs {
+    pub fn output(&self) -> PathBuf {
+        self.output
+            .clone()
+            .unwrap_or_else(|| self.file.with_extension("results.jsonl"))
+    }
+}
+
+/// The config file, then the environment, then the flags given here.
+pub fn load_config(cli: &Cli) -> Result<Config, ConfigError> {
+    let mut config = Config::load(cli.config.as_deref())?;
+    config.apply_env(|name| std::env::var(name).ok())?;
+    if let Some(model) = &cli.model {
+        config.model = Some(model.clone());
+    }
+    if let Some(language) = cli.language {
+        config.language = Some(language);
+    }
+    if let Some(theme) = &cli.theme {
+        config.theme = Some(theme.clone());
+    }
+    config.validate()?;
+    Ok(config)
+}
+
+/// The key store, unlocked with `AICHAT_PASSPHRASE` or a passphrase typed at the
+/// terminal, with `profile` in use if given. A store that has no file yet is returned
+/// as it is, for the key screen to create; `None` without a data dir.
+pub fn open_keys(profile: Option<&str>) -> io::Result<Option<KeyStore>> {
+    let Some(mut keys) = KeyStore::open_default() else {
+        return Ok(None);
+    };
+    if keys.exists() {
+        let passphrase = match std::env::var(PASSPHRASE_ENV_VAR) {
+            Ok(passphrase) if !passphrase.is_empty() => passphrase,
+            _ => rpassword::prompt_password(format!(
+                "aichat: passphrase for {}: ",
+                keys.path().display()
+            ))?,
+        };
+        keys.unlock(&passphrase)?;
+    }
+    if let Some(name) = profile {
+        if !keys.keyring.profiles.contains_key(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no key profile named {}", name),
+            ));
+        }
+        keys.keyring.active = name.to_string();
+    }
+    Ok(Some(keys))
+}
+
+/// Gives `providers` the keys of the key store, warning about those it cannot get.
+fn use_keys(cli: &Cli, providers: &mut Providers) -> io::Result<()> {
+    if let Some(keys) = open_keys(cli.profile.as_deref())? {
+        for (provider, e) in keys.keyring.apply(providers) {
+            eprintln!("aichat: no {} key: {}", provider.name(), e);
+        }
+    }
+    Ok(())
+}
+
+fn no_data_dir() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        "no data directory for conversations",
+    )
+}
+
+fn find_conversation(store: &ConversationStore, id: &str) -> io::Result<Conversation> {
+    if id == "last" {
+        store
+            .latest()?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no saved conversations"))
+    } else {
+        store.load(id)
+    }
+}
+
+pub fn list_conversations(store: Option<&ConversationStore>) -> io::Result<()> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    for conversation in store.list()? {
+        println!(
+            "{}  {}  {:<20}  {}",
+            conversation.id,
+            conversation.updated_at.format("%Y-%m-%d %H:%M"),
+            conversation.model.to_string(),
+            conversation.title
+        );
+    }
+    Ok(())
+}
+
+pub fn export(store: Option<&ConversationStore>, args: &ExportArgs) -> io::Result<()> {
+    let conversation = find_conversation(store.ok_or_else(no_data_dir)?, &args.id)?;
+    let language = if args.chinese {
+        Language::Chinese
+    } else {
+        Language::English
+    };
+    let themes = ThemeRegistry::load_default()?;
+    let index = themes
+        .find(&args.theme)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let rendered = args
+        .format
+        .render(&conversation, language, themes.source(index));
+    match &args.output {
+        Some(path) => std::fs::write(path, rendered),
+        None => io::stdout().write_all(rendered.as_bytes()),
+    }
+}
+
+pub fn import(store: Option<&ConversationStore>, args: &ImportArgs) -> io::Result<()> {
+    let store = store.ok_or_else(no_data_dir)?;
+    let text = std::fs::read_to_string(&args.file)?;
+    let format = args.format.unwrap_or_else(|| ImportFormat::detect(&text));
+    let conversations = format.parse(&text)?;
+    for conversation in &conversations {
+        store.save(conversation)?;
+    }
+    println!(
+        "Imported {} conversations into {}",
+        conversations.len(),
+        store.dir().display()
+    );
+    Ok(())
+}
+
+/// Answers `question` on stdout as it streams in, without decorations. With `--resume` the exchange is
+/// added to that conversation, whose model and persona are used unless overridden.
+pub async fn ask(
+    cli: &Cli,
+    question: String,
+    config: &Config,
+    store: Option<&ConversationStore>,
+) -> Result<(), AskError> {
+    let conversation = match cli.resume_id() {
+        Some(id) => Some(find_conversation(store.ok_or_else(no_data_dir)?, id)?),
+        None => None,
+    };
+    let model = cli
+        .model
+        .as_deref()
+        .map(AIModel::from_api_name)
+        .or_else(|| conversation.as_ref().map(|c| c.model.clone()))
+        .or_else(|| config.model.as_deref().map(AIModel::from_api_name))
+        .unwrap_or_else(AIModel::default_model);
+    let persona = conversation
+        .as_ref()
+        .and_then(|c| c.persona.as_ref())
+        .or(config.persona.as_ref())
+        .and_then(|name| config.personas.iter().find(|p| &p.name == name));
+    let ask = Ask {
+        model,
+        input: question,
+        system: cli
+            .system
+            .clone()
+            .or_else(|| persona.map(|p| p.prompt.clone())),
+        temperature: cli.temperature,
+        language: config.language,
+    };
+    let mut providers = Providers::from_config(&config.providers);
+    use_keys(cli, &mut providers)?;
+    let _cassette = cassette::attach_from_env(&mut providers).await?;
+    let conversation = ask
+        .run(conversation, &providers, None, |chunk| {
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(chunk.as_bytes());
+            let _ = stdout.flush();
+        })
+        .await?;
+    if let Some(reply) = conversation.messages.last()
+        && !reply.content.ends_with('\n')
+    {
+        println!();
+    }
+    if cli.resume_id().is_some() || cli.save {
+        store.ok_or_else(no_data_dir)?.save(&conversation)?;
+        if cli.resume_id().is_none() {
+            eprintln!("aichat: saved as {}", conversation.id);
+        }
+    }
+    Ok(())
+}
+
+/// Runs a batch file and reports the counts on stderr. Failed requests are kept in
+/// the results file and make the command fail, so a rerun retries them.
+pub async fn batch(cli: &Cli, args: &BatchArgs) -> io::Result<()> {
+    let config = load_config(cli).map_err(io::Error::other)?;
+    let requests = batch::parse_requests(&std::fs::read_to_string(&args.file)?)?;
+    let model = args
+        .model
+        .as_deref()
+        .or(config.model.as_deref())
+        .map(AIModel::from_api_name)
+        .unwrap_or_else(AIModel::default_model);
+    let mut providers = Providers::from_config(&config.providers);
+    use_keys(cli, &mut providers)?;
+    let _cassette = cassette::attach_from_env(&mut providers).await?;
+    let output = args.output();
+    let summary = batch::run(
+        &requests,
+        &model,
+        config.language,
+        &providers,
+        args.concurrency as usize,
+        &output,
+    )
+    .await?;
+    eprintln!(
+        "aichat: {} succeeded, {} failed, {} already done; results in {}",
+        summary.succeeded,
+        summary.failed,
+        summary.skipped,
+        output.display()
+    );
+    if summary.failed > 0 {
+        return Err(io::Error::other(format!(
+            "{} requests failed; run the batch again to retry them",
+            summary.failed
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    #[test]
+    fn test_resume_takes_id_and_continue_takes_none() {
+        let cli = Cli::parse_from(["aichat", "--continue"]);
+        assert_eq!(cli.resume_id(), Some("last"));
+        let cli = Cli::parse_from(["aichat", "--resume", "20250101-120000-000"]);
+        assert_eq!(cli.resume_id(), Some("20250101-120000-000"));
+        assert!(Cli::parse_from(["aichat"]).resume_id().is_none());
+        assert!(Cli::try_parse_from(["aichat", "--resume"]).is_err());
+        assert!(Cli::try_parse_from(["aichat", "-c", "--resume", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_short_resume_keeps_the_prompt() {
+        for flag in ["-r", "-c"] {
+            let cli = Cli::parse_from(["aichat", flag, "what next?"]);
+            assert_eq!(cli.resume_id(), Some("last"));
+            assert_eq!(cli.prompt, vec!["what next?"]);
+        }
+    }
+
+    #[test]
+    fn test_flags_override_config_file() {
+        let path = temp_path("config.toml");
+        std::fs::write(&path, "model = \"deepseek\"\ntheme = 2\n").unwrap();
+        let cli = Cli::parse_from([
+            "aichat",
+            "--config",
+            path.to_str().unwrap(),
+            "-m",
+            "qwen-max",
+            "--language",
+            "zh",
+        ]);
+        let config = load_config(&cli).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.model.as_deref(), Some("qwen-max"));
+        assert_eq!(config.language, Some(Language::Chinese));
+        assert_eq!(config.theme, Some(ThemeChoice::Number(2)));
+        let error = load_config(&cli).unwrap_err().to_string();
+        assert!(error.starts_with("cannot read "));
+    }
+
+    #[test]
+    fn test_prompt_runs_once_without_tui() {
+        let cli = Cli::parse_from(["aichat", "-m", "qwen-plus", "-t", "0.3", "explain", "this"]);
+        assert_eq!(cli.prompt, vec!["explain", "this"]);
+        assert_eq!(cli.temperature, Some(0.3));
+        assert!(cli.command.is_none());
+        let cli = Cli::parse_from([
+            "aichat",
+            "--resume",
+            "last",
+            "and in Go?",
+            "-s",
+            "Be brief.",
+        ]);
+        assert_eq!(cli.resume_id(), Some("last"));
+        assert_eq!(cli.prompt, vec!["and in Go?"]);
+        assert_eq!(cli.system.as_deref(), Some("Be brief."));
+        assert!(Cli::try_parse_from(["aichat", "-t", "3", "hi"]).is_err());
+        assert!(Cli::parse_from(["aichat"]).prompt.is_empty());
+    }
+
+    #[test]
+    fn test_piped_input_is_attached_to_prompt() {
+        let cli = Cli::parse_from(["aichat", "-p", "write a commit message"]);
+        assert_eq!(
+            cli.question(Some("+fix\n")).unwrap(),
+            "write a commit message\n\n```\n+fix\n```"
+        );
+        assert_eq!(cli.question(None).unwrap(), "write a commit message");
+        let cli = Cli::parse_from(["aichat", "-"]);
+        assert_eq!(cli.question(Some("what is 2+2?")).unwrap(), "what is 2+2?");
+        assert_eq!(cli.question(Some(" \n")), None);
+        assert_eq!(cli.question(None), None);
+    }
+
+    #[test]
+    fn test_stdin_is_only_read_for_a_question() {
+        let cli = Cli::parse_from(["aichat"]);
+        assert!(!cli.reads_stdin(false));
+        assert!(!cli.reads_stdin(true));
+        let cli = Cli::parse_from(["aichat", "-m", "deepseek", "--continue"]);
+        assert!(!cli.reads_stdin(false));
+        let cli = Cli::parse_from(["aichat", "-p", "summarize"]);
+        assert!(cli.reads_stdin(false));
+        assert!(!cli.reads_stdin(true));
+        let cli = Cli::parse_from(["aichat", "explain", "this"]);
+        assert!(cli.reads_stdin(false));
+        let cli = Cli::parse_from(["aichat", "-"]);
+        assert!(cli.reads_stdin(true));
+    }
+
+    #[test]
+    fn test_batch_output_defaults_next_to_input() {
+        let cli = Cli::parse_from(["aichat", "batch", "evals/prompts.jsonl", "-j", "8"]);
+        let Some(Command::Batch(args)) = cli.command else {
+            panic!("expected batch");
+        };
+        assert_eq!(args.concurrency, 8);
+        assert_eq!(args.output(), PathBuf::from("evals/prompts.results.jsonl"));
+        assert!(Cli::try_parse_from(["aichat", "batch", "p.jsonl", "-j", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_export_arguments() {
+        let cli = Cli::parse_from(["aichat", "export", "abc", "-f", "html", "--theme", "4"]);
+        let Some(Command::Export(args)) = cli.command else {
+            panic!("expected export");
+        };
+        assert_eq!(args.id, "abc");
+        assert_eq!(args.format, ExportFormat::Html);
+        assert_eq!(args.theme, ThemeChoice::Number(4));
+        assert!(Cli::try_parse_from(["aichat", "export", "-f", "pdf"]).is_err());
+    }
+
+    #[test]
+    fn test_import_format_is_optional() {
+        let cli = Cli::parse_from(["aichat", "import", "conversations.json"]);
+        let Some(Command::Import(args)) = cli.command else {
+            panic!("expected import");
+        };
+        assert_eq!(args.file, PathBuf::from("conversations.json"));
+        assert!(args.format.is_none());
+        let cli = Cli::parse_from(["aichat", "import", "log.jsonl", "-f", "jsonl"]);
+        let Some(Command::Import(args)) = cli.command else {
+            panic!("expected import");
+        };
+        assert_eq!(args.format, Some(ImportFormat::Jsonl));
+    }
+}
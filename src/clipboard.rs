@@ -0,0 +1,26 @@
+use std::io::{self, Write};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// Copies `text` to the system clipboard through the terminal with an OSC 52 escape
+/// sequence, which works over SSH and without a display server.
+pub fn copy(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(osc52(text).as_bytes())?;
+    stdout.flush()
+}
+
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_encodes_utf8() {
+        assert_eq!(osc52("hi 你好"), "\x1b]52;c;aGkg5L2g5aW9\x07");
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::ai::Provider;
+use crate::i18n::Language;
+use crate::theme::ThemeChoice;
+
+pub const CONFIG_ENV_VAR: &str = "AICHAT_CONFIG";
+
+/// `~/.config/aichat/config.toml`. Every setting is optional. Environment variables
+/// override the file, and command-line flags override both.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The model selected at startup, by name (`deepseek`, `qwenplus`) or API name
+    /// (`qwen-plus`).
+    pub model: Option<String>,
+    /// The models offered in the selector, in order; all built-in models when unset.
+    pub models: Option<Vec<String>>,
+    /// The model that writes conversation titles, or `off`.
+    pub title_model: Option<String>,
+    pub language: Option<Language>,
+    /// A theme by name, or by number counting from 1 as with the number keys.
+    pub theme: Option<ThemeChoice>,
+    /// The persona the first session starts with.
+    pub persona: Option<String>,
+    pub providers: ProvidersConfig,
+    pub personas: Vec<Persona>,
+    pub keybindings: KeybindingsConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProvidersConfig {
+    pub deepseek: ProviderConfig,
+    pub aliyun: ProviderConfig,
+}
+
+impl ProvidersConfig {
+    pub fn get_mut(&mut self, provider: Provider) -> &mut ProviderConfig {
+        match provider {
+            Provider::DeepSeek => &mut self.deepseek,
+            Provider::AliYun => &mut self.aliyun,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderConfig {
+    pub base_url: Option<String>,
+    pub timeout_seconds: Option<u64>,
+    /// The environment variable holding the API key, instead of the provider's usual one.
+    pub api_key_env: Option<String>,
+    /// Requests per minute; `0` removes the limit.
+    pub rpm: Option<u32>,
+    /// Estimated prompt tokens per minute; `0` removes the limit.
+    pub tpm: Option<u32>,
+    /// Concurrent requests; `0` removes the limit.
+    pub max_in_flight: Option<u32>,
+}
+
+/// A named system prompt a session can be given.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Persona {
+    pub name: String,
+    pub prompt: String,
+}
+
+/// Key names per command and mode, e.g. `quit = "ctrl+q"` or
+/// `search = ["/", "ctrl+f"]` under `[keybindings.normal]`. Keys separated by spaces,
+/// like `"g g"`, are pressed one after the other.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeybindingsConfig {
+    /// The defaults the sections below are laid over.
+    pub preset: Preset,
+    pub welcome: BTreeMap<String, Keys>,
+    pub normal: BTreeMap<String, Keys>,
+    pub editing: BTreeMap<String, Keys>,
+    pub editing_normal: BTreeMap<String, Keys>,
+    pub command: BTreeMap<String, Keys>,
+    pub renaming: BTreeMap<String, Keys>,
+    pub searching: BTreeMap<String, Keys>,
+    pub selecting: BTreeMap<String, Keys>,
+    pub keys: BTreeMap<String, Keys>,
+    pub key_entry: BTreeMap<String, Keys>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    /// `j`/`k`, `g g`/`G`, `y y`, `d d`, `:` commands and normal mode in the input.
+    Vim,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Keys {
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Keys::One(name) => vec![name.as_str()],
+            Keys::Many(names) => names.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => {
+                write!(f, "invalid config file {}:\n{}", path.display(), e)
+            }
+            ConfigError::Invalid(message) => write!(f, "invalid setting: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// `AICHAT_CONFIG`, else `config.toml` in the user's config dir.
+    pub fn default_path() -> Option<PathBuf> {
+        match std::env::var(CONFIG_ENV_VAR) {
+            Ok(path) if !path.trim().is_empty() => Some(PathBuf::from(path)),
+            _ => dirs::config_dir().map(|dir| dir.join("aichat").join("config.toml")),
+        }
+    }
+
+    /// Reads the file at `path`, or at the default path when `None`. Only a file that
+    /// was asked for explicitly has to exist.
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, std::env::var_os(CONFIG_ENV_VAR).is_some()),
+                None => return Ok(Config::default()),
+            },
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text, &path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Config::default()),
+            Err(e) => Err(ConfigError::Read(path, e)),
+        }
+    }
+
+    pub fn parse(text: &str, path: &Path) -> Result<Config, ConfigError> {
+        toml::from_str(text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    /// Overrides settings with `AICHAT_MODEL`, `AICHAT_LANGUAGE`, `AICHAT_THEME`,
+    /// `AICHAT_TITLE_MODEL` and, per provider, `AICHAT_<PROVIDER>_BASE_URL`, `_TIMEOUT`,
+    /// `_RPM`, `_TPM` and `_MAX_IN_FLIGHT`, read through `var`.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        let read = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+        let number = |name: &str| -> Result<Option<u64>, ConfigError> {
+            read(name)
+                .map(|value| {
+                    value.trim().parse().map_err(|_| {
+                        ConfigError::Invalid(format!("{} is not a number: {}", name, value))
+                    })
+                })
+                .transpose()
+        };
+        let count = |name: &str| -> Result<Option<u32>, ConfigError> {
+            number(name)?
+                .map(|value| {
+                    u32::try_from(value).map_err(|_| {
+                        ConfigError::Invalid(format!("{} is too large: {}", name, value))
+                    })
+                })
+                .transpose()
+        };
+        if let Some(model) = read("AICHAT_MODEL") {
+            self.model = Some(model);
+        }
+        if let Some(language) = read("AICHAT_LANGUAGE") {
+            self.language = Some(
+                language
+                    .parse()
+                    .map_err(|e| ConfigError::Invalid(format!("AICHAT_LANGUAGE: {}", e)))?,
+            );
+        }
+        if let Some(theme) = read("AICHAT_THEME") {
+            self.theme = Some(
+                theme
+                    .parse()
+                    .map_err(|e| ConfigError::Invalid(format!("AICHAT_THEME: {}", e)))?,
+            );
+        }
+        if let Some(title_model) = read(crate::title::TITLE_MODEL_ENV_VAR) {
+            self.title_model = Some(title_model);
+        }
+        for provider in [Provider::DeepSeek, Provider::AliYun] {
+            let prefix = format!("AICHAT_{}", provider.name().to_uppercase());
+            let settings = self.providers.get_mut(provider);
+            if let Some(base_url) = read(&format!("{}_BASE_URL", prefix)) {
+                settings.base_url = Some(base_url);
+            }
+            if let Some(timeout) = number(&format!("{}_TIMEOUT", prefix))? {
+                settings.timeout_seconds = Some(timeout);
+            }
+            if let Some(rpm) = count(&format!("{}_RPM", prefix))? {
+                settings.rpm = Some(rpm);
+            }
+            if let Some(tpm) = count(&format!("{}_TPM", prefix))? {
+                settings.tpm = Some(tpm);
+            }
+            if let Some(max) = count(&format!("{}_MAX_IN_FLIGHT", prefix))? {
+                settings.max_in_flight = Some(max);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks what the file format cannot: the theme number and the persona names.
+    /// Whether the theme exists is checked once the theme files are loaded.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.theme == Some(ThemeChoice::Number(0)) {
+            return Err(ConfigError::Invalid("theme numbers start at 1".to_string()));
+        }
+        if self.models.as_ref().is_some_and(|models| models.is_empty()) {
+            return Err(ConfigError::Invalid("models must not be empty".to_string()));
+        }
+        for (index, persona) in self.personas.iter().enumerate() {
+            if persona.name.trim().is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "persona {} has no name",
+                    index + 1
+                )));
+            }
+            if self.personas[..index]
+                .iter()
+                .any(|other| other.name == persona.name)
+            {
+                return Err(ConfigError::Invalid(format!(
+                    "persona \"{}\" is defined twice",
+                    persona.name
+                )));
+            }
+        }
+        if let Some(name) = &self.persona
+            && !self.personas.iter().any(|persona| &persona.name == name)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "persona \"{}\" is not defined in [[personas]]",
+                name
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+model = "qwen-plus"
+language = "zh"
+theme = 3
+
+[providers.deepseek]
+base_url = "http://localhost:8080"
+timeout_seconds = 60
+api_key_env = "WORK_DEEPSEEK_KEY"
+rpm = 20
+
+[[personas]]
+name = "reviewer"
+prompt = "You review Rust code."
+
+[keybindings]
+preset = "vim"
+
+[keybindings.normal]
+quit = "ctrl+q"
+search = ["/", "ctrl+f"]
+"#;
+
+    #[test]
+    fn test_parse_full_config() {
+        let config = Config::parse(EXAMPLE, Path::new("config.toml")).unwrap();
+        assert_eq!(config.model.as_deref(), Some("qwen-plus"));
+        assert_eq!(config.language, Some(Language::Chinese));
+        assert_eq!(config.theme, Some(ThemeChoice::Number(3)));
+        let deepseek = &config.providers.deepseek;
+        assert_eq!(deepseek.base_url.as_deref(), Some("http://localhost:8080"));
+        assert_eq!(deepseek.timeout_seconds, Some(60));
+        assert_eq!(deepseek.api_key_env.as_deref(), Some("WORK_DEEPSEEK_KEY"));
+        assert_eq!(deepseek.rpm, Some(20));
+        assert_eq!(config.personas[0].name, "reviewer");
+        assert_eq!(config.keybindings.preset, Preset::Vim);
+        assert_eq!(
+            config.keybindings.normal["search"].names(),
+            vec!["/", "ctrl+f"]
+        );
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_errors_name_the_file_and_line() {
+        let error = Config::parse("theme = 2\nlanguge = \"en\"\n", Path::new("/tmp/c.toml"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("invalid config file /tmp/c.toml:"));
+        assert!(error.contains("line 2"));
+        assert!(error.contains("unknown field `languge`"));
+        let error = Config::parse("theme = 0", Path::new("c.toml"))
+            .unwrap()
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid setting: theme numbers start at 1"
+        );
+        let config = Config::parse("theme = \"solarized\"", Path::new("c.toml")).unwrap();
+        assert_eq!(
+            config.theme,
+            Some(ThemeChoice::Name("solarized".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_environment_overrides_file() {
+        let mut config = Config::parse(EXAMPLE, Path::new("config.toml")).unwrap();
+        config
+            .apply_env(|name| match name {
+                "AICHAT_LANGUAGE" => Some("english".to_string()),
+                "AICHAT_DEEPSEEK_RPM" => Some("0".to_string()),
+                "AICHAT_ALIYUN_TIMEOUT" => Some("5".to_string()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(config.language, Some(Language::English));
+        assert_eq!(config.model.as_deref(), Some("qwen-plus"));
+        assert_eq!(config.providers.deepseek.rpm, Some(0));
+        assert_eq!(config.providers.aliyun.timeout_seconds, Some(5));
+        config
+            .apply_env(|name| (name == "AICHAT_THEME").then(|| "neon".to_string()))
+            .unwrap();
+        assert_eq!(config.theme, Some(ThemeChoice::Name("neon".to_string())));
+        let error = config
+            .apply_env(|name| (name == "AICHAT_ALIYUN_TPM").then(|| "lots".to_string()))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid setting: AICHAT_ALIYUN_TPM is not a number: lots"
+        );
+        let error = config
+            .apply_env(|name| (name == "AICHAT_DEEPSEEK_RPM").then(|| "4294967296".to_string()))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid setting: AICHAT_DEEPSEEK_RPM is too large: 4294967296"
+        );
+        assert_eq!(config.providers.deepseek.rpm, Some(0));
+    }
+}
@@ -1,35 +1,67 @@
+use crate::ai_models::AIModel;
 use crate::app::{App, AppState, InputMode};
-use crossterm::event::{KeyCode, KeyEventKind};
+use crate::keymap::{Command, Lookup, Mode, find_command};
+use crate::keystore::KeyEntry;
+use crate::motion;
+use crate::theme::ThemeChoice;
+use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
 
 pub fn handle_key_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
     if key.kind != KeyEventKind::Press {
         return false;
     }
-    let should_quit = match app.app_state {
+    if app.notification.is_some() {
+        app.clear_notification();
+        return false;
+    }
+    if app.recovery.is_some() {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => app.recover_journal(),
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => app.discard_recovery(),
+            _ => {}
+        }
+        return false;
+    }
+    match app.app_state {
         AppState::Welcome => handle_welcome_event(key, app),
         AppState::Chatting => handle_chatting_event(key, app),
         AppState::Help => handle_help_event(key, app),
-    };
-    app.clear_notification();
-    should_quit
+    }
+}
+
+/// What `key` runs in `mode`, remembering the start of a sequence such as `g g`.
+fn lookup(app: &mut App, mode: Mode, key: &crossterm::event::KeyEvent) -> Lookup {
+    app.keymap.lookup(mode, &mut app.pending_keys, key)
 }
 
 fn handle_welcome_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
-    match key.code {
-        KeyCode::Enter => app.start_chatting(),
-        KeyCode::Char('c') | KeyCode::Char('C') => app.switch_to_chinese(),
-        KeyCode::Char('e') | KeyCode::Char('E') => app.switch_to_english(),
-        KeyCode::Char('1') => app.change_theme(0),
-        KeyCode::Char('2') => app.change_theme(1),
-        KeyCode::Char('3') => app.change_theme(2),
-        KeyCode::Char('4') => app.change_theme(3),
-        KeyCode::F(1) => app.show_help = true,
-        KeyCode::Char('q') => return true,
+    match lookup(app, Mode::Welcome, &key).command() {
+        Some(Command::Start) => app.start_chatting(),
+        Some(Command::Resume) => app.resume_latest(),
+        Some(Command::Chinese) => app.switch_to_chinese(),
+        Some(Command::English) => app.switch_to_english(),
+        Some(Command::Theme(index)) => app.change_theme(index),
+        Some(Command::Help) => app.show_help = true,
+        Some(Command::Quit) => return true,
         _ => {}
     }
     false
 }
 
+/// The character a key types in a text field, if it types one.
+fn typed(key: &crossterm::event::KeyEvent) -> Option<char> {
+    match key.code {
+        KeyCode::Char(c)
+            if !key
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+        {
+            Some(c)
+        }
+        _ => None,
+    }
+}
+
 fn handle_chatting_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
     if app.show_help {
         app.show_help = false;
@@ -39,99 +71,365 @@ fn handle_chatting_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool
     match app.input_mode {
         InputMode::Normal => handle_normal_mode_event(key, app),
         InputMode::Editing => handle_editing_mode_event(key, app),
+        InputMode::Renaming => handle_renaming_mode_event(key, app),
+        InputMode::Searching => handle_searching_mode_event(key, app),
+        InputMode::Selecting => handle_selecting_mode_event(key, app),
+        InputMode::EditingNormal => handle_editing_normal_mode_event(key, app),
+        InputMode::Command => handle_command_mode_event(key, app),
+        InputMode::Keys => handle_keys_mode_event(key, app),
+        InputMode::KeyEntry => handle_key_entry_mode_event(key, app),
     }
 }
 
 fn handle_normal_mode_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
-    match key.code {
-        KeyCode::Left => {
-            if app.input_mode == InputMode::Normal {
-                let available_width = 100;
-                let max_visible = app.calculate_max_visible(available_width);
-                app.select_previous_model(max_visible);
-            }
+    match lookup(app, Mode::Normal, &key).command() {
+        Some(command) => run_command(command, app),
+        None => false,
+    }
+}
+
+/// Runs a normal-mode command; returns true when the app should quit.
+fn run_command(command: Command, app: &mut App) -> bool {
+    match command {
+        Command::PreviousModel => {
+            let available_width = 100;
+            let max_visible = app.calculate_max_visible(available_width);
+            app.select_previous_model(max_visible);
         }
-        KeyCode::Right => {
-            if app.input_mode == InputMode::Normal {
-                let available_width = 100;
-                let max_visible = app.calculate_max_visible(available_width);
-                app.select_next_model(max_visible);
-            }
+        Command::NextModel => {
+            let available_width = 100;
+            let max_visible = app.calculate_max_visible(available_width);
+            app.select_next_model(max_visible);
         }
-        KeyCode::Up => {
-            let current = app.ai_list_state.selected().unwrap_or(0);
+        Command::ScrollUp => {
             let max_scroll = app.get_max_scroll_offset();
+            let session = app.session_mut();
+            let current = session.ai_list_state.selected().unwrap_or(0);
             if current < max_scroll {
-                app.ai_list_state.select(Some(current + 1));
-                app.auto_scroll = false;
+                session.ai_list_state.select(Some(current + 1));
+                session.auto_scroll = false;
             }
         }
-        KeyCode::Down => {
-            let current = app.ai_list_state.selected().unwrap_or(0);
+        Command::ScrollDown => {
+            let session = app.session_mut();
+            let current = session.ai_list_state.selected().unwrap_or(0);
             if current > 0 {
-                app.ai_list_state.select(Some(current - 1));
-                app.auto_scroll = false;
+                session.ai_list_state.select(Some(current - 1));
+                session.auto_scroll = false;
             }
         }
-        KeyCode::PageUp => {
-            let current = app.ai_list_state.selected().unwrap_or(0);
+        Command::PageUp => {
             let max_scroll = app.get_max_scroll_offset();
-            app.ai_list_state
+            let session = app.session_mut();
+            let current = session.ai_list_state.selected().unwrap_or(0);
+            session
+                .ai_list_state
                 .select(Some((current + 10).min(max_scroll)));
-            app.auto_scroll = false;
+            session.auto_scroll = false;
         }
-        KeyCode::PageDown => {
-            let current = app.ai_list_state.selected().unwrap_or(0);
-            app.ai_list_state
-                .select(Some(current.saturating_sub(10).max(0)));
-            app.auto_scroll = false;
+        Command::PageDown => {
+            let session = app.session_mut();
+            let current = session.ai_list_state.selected().unwrap_or(0);
+            session
+                .ai_list_state
+                .select(Some(current.saturating_sub(10)));
+            session.auto_scroll = false;
         }
-        KeyCode::Home => {
-            app.ai_list_state.select(Some(0));
-            app.auto_scroll = false;
-        }
-        KeyCode::End => {
+        Command::ScrollHome => app.scroll_to_home(),
+        Command::ScrollEnd => {
             app.scroll_to_end();
         }
-        KeyCode::Char('i') => app.input_mode = InputMode::Editing,
-        KeyCode::Char('c') | KeyCode::Char('C') => app.switch_to_chinese(),
-        KeyCode::Char('e') | KeyCode::Char('E') => app.switch_to_english(),
-        KeyCode::Enter => {
-            if !app.input.is_empty() {
-                app.send_message();
-                app.scroll_to_end();
-            }
+        Command::Edit => app.input_mode = InputMode::Editing,
+        Command::Chinese => app.switch_to_chinese(),
+        Command::English => app.switch_to_english(),
+        Command::Send if !app.input.is_empty() => {
+            app.send_message();
+            app.scroll_to_end();
         }
-        KeyCode::F(1) => app.toggle_help(),
-        KeyCode::Char('1') => app.change_theme(0),
-        KeyCode::Char('2') => app.change_theme(1),
-        KeyCode::Char('3') => app.change_theme(2),
-        KeyCode::Char('4') => app.change_theme(3),
-        KeyCode::Char('q') => return true,
+        Command::Send => {}
+        Command::Help => app.toggle_help(),
+        Command::Inspector => app.toggle_inspector(),
+        Command::NewSession => app.new_session(),
+        Command::NextSession => app.next_session(),
+        Command::PreviousSession => app.previous_session(),
+        Command::Rename => app.start_rename(),
+        Command::CloseSession => app.close_session(),
+        Command::Export => app.export_session(),
+        Command::Search => app.open_search(),
+        Command::SelectMessages => app.start_selecting(),
+        Command::TemperatureUp => app.adjust_temperature(0.1),
+        Command::TemperatureDown => app.adjust_temperature(-0.1),
+        Command::ClearHighlight => app.session_mut().highlight = None,
+        Command::NextPersona => app.next_persona(),
+        Command::InspectorPrevious if app.show_inspector => app.select_previous_exchange(),
+        Command::InspectorNext if app.show_inspector => app.select_next_exchange(),
+        Command::InspectorExport if app.show_inspector => app.export_selected_exchange(),
+        Command::InspectorPrevious | Command::InspectorNext | Command::InspectorExport => {}
+        Command::Theme(index) => app.change_theme(index),
+        Command::NextTheme => app.next_theme(),
+        Command::PreviousTheme => app.previous_theme(),
+        Command::CopyReply => app.copy_last_reply(),
+        Command::CommandLine => app.start_command_line(),
+        Command::Keys => app.open_keys(),
+        Command::Quit => return true,
         _ => {}
     }
     false
 }
 
 fn handle_editing_mode_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
-    match key.code {
-        KeyCode::Enter => {
+    match lookup(app, Mode::Editing, &key) {
+        Lookup::Command(Command::Send) => {
             app.send_message();
             app.input_mode = InputMode::Normal;
         }
-        KeyCode::Esc => {
-            app.clear_input();
+        Lookup::Command(Command::Cancel) => app.cancel_editing(),
+        Lookup::Command(Command::NormalMode) => app.enter_editing_normal(),
+        Lookup::Command(Command::DeleteChar) => app.delete_char_before_cursor(),
+        Lookup::Command(Command::ClearInput) => app.clear_input(),
+        Lookup::Command(_) | Lookup::Pending => {}
+        Lookup::Unbound => {
+            if let Some(c) = typed(&key) {
+                app.insert_char(c);
+            }
+        }
+    }
+    false
+}
+
+/// Moves the cursor to where `motion` takes it, staying on a character.
+fn move_cursor(app: &mut App, motion: impl Fn(&str, usize) -> usize) {
+    let at = motion(&app.input, app.cursor());
+    app.input_cursor = Some(motion::on_char(&app.input, at));
+}
+
+/// Starts typing at `at`.
+fn insert_at(app: &mut App, at: usize) {
+    app.input_cursor = Some(at);
+    app.input_mode = InputMode::Editing;
+}
+
+fn handle_editing_normal_mode_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
+    let at = app.cursor();
+    match lookup(app, Mode::EditingNormal, &key).command() {
+        Some(Command::Insert) => insert_at(app, at),
+        Some(Command::Append) => {
+            let end = motion::line_end(&app.input, at);
+            insert_at(app, motion::next_char(&app.input, at).min(end));
+        }
+        Some(Command::InsertLineStart) => insert_at(app, motion::line_start(&app.input, at)),
+        Some(Command::AppendLineEnd) => insert_at(app, motion::line_end(&app.input, at)),
+        Some(Command::CursorLeft) => move_cursor(app, |text, at| {
+            motion::previous_char(text, at).max(motion::line_start(text, at))
+        }),
+        Some(Command::CursorRight) => move_cursor(app, motion::next_char),
+        Some(Command::WordForward) => move_cursor(app, motion::word_forward),
+        Some(Command::WordBackward) => move_cursor(app, motion::word_backward),
+        Some(Command::LineStart) => move_cursor(app, motion::line_start),
+        Some(Command::LineEnd) => move_cursor(app, motion::line_end),
+        Some(Command::DeleteChar) if at < motion::line_end(&app.input, at) => {
+            let next = motion::next_char(&app.input, at);
+            app.input.replace_range(at..next, "");
+            move_cursor(app, |_, at| at);
+        }
+        Some(Command::ChangeInnerWord) => {
+            let word = motion::inner_word(&app.input, at);
+            let start = word.start;
+            app.input.replace_range(word, "");
+            insert_at(app, start);
+        }
+        Some(Command::Send) => {
+            app.send_message();
             app.input_mode = InputMode::Normal;
         }
-        KeyCode::Char(c) => {
-            app.input.push(c);
+        Some(Command::Cancel) => app.cancel_editing(),
+        _ => {}
+    }
+    false
+}
+
+fn handle_command_mode_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
+    match lookup(app, Mode::Command, &key) {
+        Lookup::Command(Command::Confirm) => return run_command_line(app),
+        Lookup::Command(Command::Cancel) => app.cancel_command_line(),
+        Lookup::Command(Command::DeleteChar) => {
+            if app.command_input.pop().is_none() {
+                app.cancel_command_line();
+            }
+        }
+        Lookup::Command(_) | Lookup::Pending => {}
+        Lookup::Unbound => {
+            if let Some(c) = typed(&key) {
+                app.command_input.push(c);
+            }
+        }
+    }
+    false
+}
+
+/// Runs the `:` command line: a normal-mode command by its config name, vim's `q`,
+/// `w`, `new`, `bn`, `bp` or `noh`, or `theme`, `model`, `persona` or `temp` with an
+/// argument. Returns true when the app should quit.
+fn run_command_line(app: &mut App) -> bool {
+    let line = std::mem::take(&mut app.command_input);
+    app.input_mode = InputMode::Normal;
+    let (name, argument) = line
+        .trim()
+        .split_once(char::is_whitespace)
+        .map_or((line.trim(), ""), |(name, argument)| {
+            (name, argument.trim())
+        });
+    let command = match (name, argument) {
+        ("", _) => return false,
+        ("q" | "quit" | "qa", "") => Command::Quit,
+        ("w", "") => Command::Export,
+        ("new" | "tabnew", "") => Command::NewSession,
+        ("bn" | "tabnext", "") => Command::NextSession,
+        ("bp" | "tabprevious", "") => Command::PreviousSession,
+        ("noh" | "nohlsearch", "") => Command::ClearHighlight,
+        ("theme", choice) => match choice
+            .parse::<ThemeChoice>()
+            .and_then(|choice| app.themes.find(&choice))
+        {
+            Ok(index) => Command::Theme(index),
+            Err(_) => return invalid_argument(app, &line),
+        },
+        ("model", name) if !name.is_empty() => {
+            app.select_model(AIModel::from_api_name(name));
+            let max_visible = app.calculate_max_visible(100);
+            app.ensure_selected_visible(max_visible);
+            return false;
+        }
+        ("persona", "") => {
+            app.set_persona(None);
+            return false;
+        }
+        ("persona", name) => {
+            if !app.personas.iter().any(|persona| persona.name == name) {
+                return invalid_argument(app, &line);
+            }
+            app.set_persona(Some(name.to_string()));
+            return false;
+        }
+        ("temp" | "temperature", value) => match value.parse::<f32>() {
+            Ok(temperature) if (0.0..=2.0).contains(&temperature) => {
+                app.session_mut().temperature = Some(temperature);
+                return false;
+            }
+            _ => return invalid_argument(app, &line),
+        },
+        (name, "") => match find_command(Mode::Normal, name) {
+            Ok(command) => command,
+            Err(_) => {
+                app.set_notification(format!(
+                    "{}: {}",
+                    app.t("notification_unknown_command"),
+                    name
+                ));
+                return false;
+            }
+        },
+        _ => return invalid_argument(app, &line),
+    };
+    run_command(command, app)
+}
+
+fn invalid_argument(app: &mut App, line: &str) -> bool {
+    app.set_notification(format!(
+        "{}: {}",
+        app.t("notification_invalid_argument"),
+        line.trim()
+    ));
+    false
+}
+
+fn handle_renaming_mode_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
+    match lookup(app, Mode::Renaming, &key) {
+        Lookup::Command(Command::Confirm) => app.finish_rename(),
+        Lookup::Command(Command::Cancel) => app.cancel_rename(),
+        Lookup::Command(Command::DeleteChar) => {
+            app.rename_input.pop();
+        }
+        Lookup::Command(_) | Lookup::Pending => {}
+        Lookup::Unbound => {
+            if let Some(c) = typed(&key) {
+                app.rename_input.push(c);
+            }
+        }
+    }
+    false
+}
+
+fn handle_keys_mode_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
+    match lookup(app, Mode::Keys, &key).command() {
+        Some(Command::SelectPrevious) => app.select_previous_key(),
+        Some(Command::SelectNext) => app.select_next_key(),
+        Some(Command::PreviousProfile) => app.switch_key_profile(false),
+        Some(Command::NextProfile) => app.switch_key_profile(true),
+        Some(Command::SetKey) => app.start_key_entry(KeyEntry::Key),
+        Some(Command::SetKeyCommand) => app.start_key_entry(KeyEntry::Command),
+        Some(Command::RemoveKey) => app.remove_key(),
+        Some(Command::UseProfile) => app.use_key_profile(),
+        Some(Command::NewProfile) => app.start_key_entry(KeyEntry::Profile),
+        Some(Command::DeleteProfile) => app.delete_key_profile(),
+        Some(Command::Cancel) => app.close_keys(),
+        _ => {}
+    }
+    false
+}
+
+fn handle_key_entry_mode_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
+    match lookup(app, Mode::KeyEntry, &key) {
+        Lookup::Command(Command::Confirm) => app.finish_key_entry(),
+        Lookup::Command(Command::Cancel) => app.cancel_key_entry(),
+        Lookup::Command(Command::DeleteChar) => {
+            app.keys_screen.input.pop();
         }
-        KeyCode::Backspace => {
-            app.input.pop();
+        Lookup::Command(_) | Lookup::Pending => {}
+        Lookup::Unbound => {
+            if let Some(c) = typed(&key) {
+                app.keys_screen.input.push(c);
+            }
         }
-        KeyCode::Delete => {
-            app.clear_input();
+    }
+    false
+}
+
+fn handle_searching_mode_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
+    match lookup(app, Mode::Searching, &key) {
+        Lookup::Command(Command::Confirm) => app.open_search_result(),
+        Lookup::Command(Command::Cancel) => app.close_search(),
+        Lookup::Command(Command::SelectPrevious) => app.search.select_previous(),
+        Lookup::Command(Command::SelectNext) => app.search.select_next(),
+        Lookup::Command(Command::FilterModel) => app.search.next_model_filter(),
+        Lookup::Command(Command::FilterSender) => app.search.next_sender_filter(),
+        Lookup::Command(Command::FilterDate) => app.search.next_date_filter(),
+        Lookup::Command(Command::DeleteChar) => app.search_pop(),
+        Lookup::Command(_) | Lookup::Pending => {}
+        Lookup::Unbound => {
+            if let Some(c) = typed(&key) {
+                app.search_push(c);
+            }
         }
+    }
+    false
+}
+
+fn handle_selecting_mode_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
+    match lookup(app, Mode::Selecting, &key).command() {
+        Some(Command::SelectPrevious) => app.select_previous_message(),
+        Some(Command::SelectNext) => app.select_next_message(),
+        Some(Command::PreviousBranch) => app.switch_selected_branch(false),
+        Some(Command::NextBranch) => app.switch_selected_branch(true),
+        Some(Command::EditMessage) => app.edit_selected_message(),
+        Some(Command::Regenerate) => app.regenerate_selected(),
+        Some(Command::DeleteMessage) => app.delete_selected_message(),
+        Some(Command::TogglePin) => app.toggle_pin_selected(),
+        Some(Command::CopyMessage) => app.copy_selected_message(),
+        Some(Command::QuoteMessage) => app.quote_selected_message(),
+        Some(Command::TemperatureUp) => app.adjust_temperature(0.1),
+        Some(Command::TemperatureDown) => app.adjust_temperature(-0.1),
+        Some(Command::Cancel) => app.stop_selecting(),
         _ => {}
     }
     false
@@ -143,3 +441,67 @@ fn handle_help_event(key: crossterm::event::KeyEvent, app: &mut App) -> bool {
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{KeybindingsConfig, Preset};
+    use crate::keymap::Keymap;
+    use crossterm::event::KeyEvent;
+
+    fn press(app: &mut App, keys: &str) -> bool {
+        let mut quit = false;
+        for c in keys.chars() {
+            let code = match c {
+                '⎋' => KeyCode::Esc,
+                '⏎' => KeyCode::Enter,
+                c => KeyCode::Char(c),
+            };
+            quit = handle_key_event(KeyEvent::new(code, KeyModifiers::NONE), app);
+        }
+        quit
+    }
+
+    #[test]
+    fn test_vim_preset_motions_and_command_line() {
+        let mut app = App::new();
+        app.app_state = AppState::Chatting;
+        app.keymap = Keymap::from_config(&KeybindingsConfig {
+            preset: Preset::Vim,
+            ..KeybindingsConfig::default()
+        })
+        .unwrap();
+
+        press(&mut app, "ilet foo = 1⎋");
+        assert_eq!(app.input_mode, InputMode::EditingNormal);
+        assert_eq!(app.cursor(), 10);
+        press(&mut app, "0w");
+        assert_eq!(app.cursor(), 4);
+        press(&mut app, "ciwbar");
+        assert_eq!(app.input, "let bar = 1");
+        assert_eq!(app.input_mode, InputMode::Editing);
+        press(&mut app, "⎋bb");
+        assert_eq!(app.cursor(), 0);
+        press(&mut app, "$x");
+        assert_eq!(app.input, "let bar = ");
+        assert_eq!(app.cursor(), 9);
+        press(&mut app, "A2");
+        assert_eq!(app.input, "let bar = 2");
+        press(&mut app, "⎋⎋");
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(app.input.is_empty());
+
+        press(&mut app, ":temp 0.3⏎");
+        assert_eq!(app.session().temperature, Some(0.3));
+        assert_eq!(app.input_mode, InputMode::Normal);
+        press(&mut app, ":frobnicate⏎");
+        assert_eq!(
+            app.notification.as_deref(),
+            Some("Unknown command: frobnicate")
+        );
+        press(&mut app, "⎋:new_session⏎");
+        assert_eq!(app.sessions.len(), 2);
+        app.clear_notification();
+        assert!(press(&mut app, ":q⏎"));
+    }
+}
@@ -0,0 +1,338 @@
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use ratatui::style::Color;
+
+use crate::app::{Message, Sender};
+use crate::i18n::Language;
+use crate::storage::Conversation;
+use crate::theme::Theme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn all() -> [ExportFormat; 3] {
+        [
+            ExportFormat::Markdown,
+            ExportFormat::Html,
+            ExportFormat::Json,
+        ]
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn render(&self, conversation: &Conversation, language: Language, theme: &Theme) -> String {
+        match self {
+            ExportFormat::Markdown => to_markdown(conversation, language),
+            ExportFormat::Html => to_html(conversation, language, theme),
+            ExportFormat::Json => to_json(conversation),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "html" | "htm" => Ok(ExportFormat::Html),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!(
+                "Unknown export format: {} (expected md, html or json)",
+                name
+            )),
+        }
+    }
+}
+
+fn speaker(message: &Message, language: Language) -> String {
+    match &message.sender {
+        Sender::User => match language {
+            Language::Chinese => "👤 你".to_string(),
+            Language::English => "👤 You".to_string(),
+        },
+        Sender::AI(model) | Sender::Thinking(model) => format!("🤖 {}", model.name(language)),
+        Sender::Error(model) => format!("⚠️ {}", model.name(language)),
+    }
+}
+
+/// Counts the fence lines in `content`; an odd count means a reply was cut off inside
+/// a code block.
+fn has_open_fence(content: &str) -> bool {
+    content
+        .lines()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count()
+        % 2
+        == 1
+}
+
+/// One `##` header per message. Code fences in replies are kept as written and closed
+/// when a reply ends inside one; error messages become blockquotes.
+pub fn to_markdown(conversation: &Conversation, language: Language) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}\n", conversation.title);
+    let _ = writeln!(
+        out,
+        "_{} · {}_\n",
+        conversation.model.name(language),
+        conversation.created_at.format("%Y-%m-%d %H:%M")
+    );
+    for message in &conversation.messages {
+        let _ = writeln!(
+            out,
+            "## {} · {}\n",
+            speaker(message, language),
+            message.timestamp.format("%Y-%m-%d %H:%M")
+        );
+        let content = message.content.trim_end();
+        if let Sender::Error(_) = message.sender {
+            for line in content.lines() {
+                let _ = writeln!(out, "> {}", line);
+            }
+        } else {
+            out.push_str(content);
+            out.push('\n');
+            if has_open_fence(content) {
+                out.push_str("```\n");
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn to_json(conversation: &Conversation) -> String {
+    serde_json::to_string_pretty(conversation).unwrap_or_default()
+}
+
+fn css_color(color: Color) -> String {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Black => (0, 0, 0),
+        Color::Red => (205, 49, 49),
+        Color::Green => (13, 188, 121),
+        Color::Yellow => (229, 229, 16),
+        Color::Blue => (36, 114, 200),
+        Color::Magenta => (188, 63, 188),
+        Color::Cyan => (17, 168, 205),
+        Color::Gray => (204, 204, 204),
+        Color::DarkGray => (118, 118, 118),
+        Color::LightRed => (241, 76, 76),
+        Color::LightGreen => (35, 209, 139),
+        Color::LightYellow => (245, 245, 67),
+        Color::LightBlue => (59, 142, 234),
+        Color::LightMagenta => (214, 112, 214),
+        Color::LightCyan => (41, 184, 219),
+        _ => (229, 229, 229),
+    };
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn code_block_html(language: &str, lines: &[&str]) -> String {
+    let class = if language.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"language-{}\"", escape_html(language))
+    };
+    format!(
+        "<pre><code{}>{}</code></pre>\n",
+        class,
+        escape_html(&lines.join("\n"))
+    )
+}
+
+/// Paragraphs and fenced code blocks; everything else is shown as plain text. A block
+/// left open at the end of a reply is closed like in the Markdown export.
+fn content_to_html(content: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<(String, Vec<&str>)> = None;
+    let flush = |out: &mut String, paragraph: &mut Vec<&str>| {
+        if !paragraph.is_empty() {
+            let lines: Vec<String> = paragraph.iter().map(|line| escape_html(line)).collect();
+            let _ = writeln!(out, "<p>{}</p>", lines.join("<br>"));
+            paragraph.clear();
+        }
+    };
+    for line in content.lines() {
+        let fence = line.trim_start().strip_prefix("```");
+        match (&mut code, fence) {
+            (Some((language, lines)), Some(_)) => {
+                out.push_str(&code_block_html(language, lines));
+                code = None;
+            }
+            (Some((_, lines)), None) => lines.push(line),
+            (None, Some(language)) => {
+                flush(&mut out, &mut paragraph);
+                code = Some((language.trim().to_string(), Vec::new()));
+            }
+            (None, None) if line.trim().is_empty() => flush(&mut out, &mut paragraph),
+            (None, None) => paragraph.push(line),
+        }
+    }
+    if let Some((language, lines)) = code {
+        out.push_str(&code_block_html(&language, &lines));
+    }
+    flush(&mut out, &mut paragraph);
+    out
+}
+
+/// A single HTML file with inline CSS in the colors of `theme`.
+pub fn to_html(conversation: &Conversation, language: Language, theme: &Theme) -> String {
+    let mut out = String::new();
+    let lang = match language {
+        Language::Chinese => "zh-CN",
+        Language::English => "en",
+    };
+    let _ = write!(
+        out,
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ background: {background}; color: {text}; font-family: -apple-system, "Segoe UI", "PingFang SC", sans-serif; max-width: 860px; margin: 2rem auto; padding: 0 1rem; line-height: 1.6; }}
+h1 {{ color: {primary}; }}
+.meta {{ color: {secondary}; }}
+.message {{ border-left: 3px solid {primary}; padding: 0.25rem 1rem; margin: 1.25rem 0; }}
+.message.user {{ border-color: {accent}; }}
+.message.error {{ border-color: {error}; color: {error}; }}
+.speaker {{ color: {primary}; font-weight: bold; }}
+.message.user .speaker {{ color: {accent}; }}
+.message.error .speaker {{ color: {error}; }}
+.time {{ color: {secondary}; font-size: 0.85em; margin-left: 0.5rem; }}
+pre {{ background: rgba(127, 127, 127, 0.15); border: 1px solid {secondary}; border-radius: 6px; padding: 0.75rem; overflow-x: auto; }}
+code {{ font-family: "JetBrains Mono", Menlo, Consolas, monospace; color: {success}; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p class="meta">{model} · {created}</p>
+"#,
+        lang = lang,
+        title = escape_html(&conversation.title),
+        background = css_color(theme.background),
+        text = css_color(theme.text),
+        primary = css_color(theme.primary),
+        secondary = css_color(theme.secondary),
+        accent = css_color(theme.accent),
+        success = css_color(theme.success),
+        error = css_color(theme.error),
+        model = escape_html(&conversation.model.name(language)),
+        created = conversation.created_at.format("%Y-%m-%d %H:%M"),
+    );
+    for message in &conversation.messages {
+        let class = match message.sender {
+            Sender::User => "user",
+            Sender::Error(_) => "error",
+            Sender::AI(_) | Sender::Thinking(_) => "ai",
+        };
+        let _ = write!(
+            out,
+            "<div class=\"message {}\">\n<div><span class=\"speaker\">{}</span><span class=\"time\">{}</span></div>\n{}</div>\n",
+            class,
+            escape_html(&speaker(message, language)),
+            message.timestamp.format("%Y-%m-%d %H:%M"),
+            content_to_html(&message.content)
+        );
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_models::AIModel;
+    use chrono::Local;
+
+    fn conversation() -> Conversation {
+        let now = Local::now();
+        Conversation {
+            id: "20250101-000000-000".to_string(),
+            title: "Sorting <vec>".to_string(),
+            title_source: None,
+            model: AIModel::DeepSeek,
+            persona: None,
+            created_at: now,
+            updated_at: now,
+            messages: vec![
+                Message::new("How do I sort a vec?", Sender::User),
+                Message::new(
+                    "Use sort:\n\n```rust\nv.sort();\nlet a = 1 < 2;",
+                    Sender::AI(AIModel::DeepSeek),
+                ),
+                Message::new("🌐 Network error", Sender::Error(AIModel::DeepSeek)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_markdown_has_headers_and_closes_fences() {
+        let markdown = to_markdown(&conversation(), Language::English);
+        assert!(markdown.starts_with("# Sorting <vec>\n"));
+        assert!(markdown.contains("## 👤 You · "));
+        assert!(markdown.contains("## 🤖 DeepSeek · "));
+        assert!(markdown.contains("```rust\nv.sort();\nlet a = 1 < 2;\n```\n"));
+        assert!(markdown.contains("> 🌐 Network error"));
+    }
+
+    #[test]
+    fn test_html_is_escaped_and_themed() {
+        let html = to_html(&conversation(), Language::English, &Theme::neon());
+        assert!(html.contains("<title>Sorting &lt;vec&gt;</title>"));
+        assert!(html.contains("background: #000014"));
+        assert!(html.contains(
+            "<pre><code class=\"language-rust\">v.sort();\nlet a = 1 &lt; 2;</code></pre>"
+        ));
+        assert!(html.contains("<div class=\"message error\">"));
+        assert!(!html.contains("<vec>"));
+    }
+
+    #[test]
+    fn test_json_round_trips() {
+        let original = conversation();
+        let parsed: Conversation = serde_json::from_str(&to_json(&original)).unwrap();
+        assert_eq!(parsed.messages.len(), 3);
+        assert_eq!(parsed.messages[2].sender, original.messages[2].sender);
+        assert_eq!(parsed.messages[1].content, original.messages[1].content);
+        assert_eq!(parsed.created_at, original.created_at);
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("MD".parse(), Ok(ExportFormat::Markdown));
+        assert_eq!("html".parse(), Ok(ExportFormat::Html));
+        assert!("pdf".parse::<ExportFormat>().is_err());
+    }
+}
@@ -1,18 +1,13 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Language {
+    #[default]
     Chinese,
     English,
 }
 
-impl Default for Language {
-    fn default() -> Self {
-        Language::Chinese
-    }
-}
-
 impl Language {
     pub fn name(&self) -> &str {
         match self {
@@ -29,6 +24,33 @@ impl Language {
     }
 }
 
+impl std::str::FromStr for Language {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_lowercase().as_str() {
+            "zh" | "zh-cn" | "chinese" | "中文" => Ok(Language::Chinese),
+            "en" | "english" => Ok(Language::English),
+            _ => Err(format!("Unknown language: {} (expected en or zh)", name)),
+        }
+    }
+}
+
+/// Written as its code, `en` or `zh`.
+impl Serialize for Language {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+/// Read from the config file by code or name, like `FromStr`.
+impl<'de> Deserialize<'de> for Language {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Translations {
     pub strings: HashMap<String, String>,
@@ -53,10 +75,9 @@ impl Translations {
             "model_selector_title".to_string(),
             "🎯 AI 模型 (← →)".to_string(),
         );
-        strings.insert("theme_selector_title".to_string(), "主题 (1-4)".to_string());
         strings.insert(
-            "theme_names".to_string(),
-            "(蓝色/绿色/橙色/霓虹)".to_string(),
+            "theme_selector_title".to_string(),
+            "主题 (1-4, T)".to_string(),
         );
         strings.insert(
             "input_hint_normal".to_string(),
@@ -102,7 +123,7 @@ impl Translations {
         );
         strings.insert(
             "welcome_start_hint".to_string(),
-            "按 Enter 键开始聊天，按 F1 查看帮助".to_string(),
+            "按 Enter 键开始聊天，按 R 继续上次对话，按 F1 查看帮助".to_string(),
         );
         strings.insert(
             "welcome_press_enter".to_string(),
@@ -211,15 +232,361 @@ impl Translations {
             "notification_theme_changed".to_string(),
             "主题已更改".to_string(),
         );
+        strings.insert("inspector_title".to_string(), "🔎 请求检查器".to_string());
+        strings.insert(
+            "inspector_hint".to_string(),
+            "[ ] 选择  x 导出  F12 关闭".to_string(),
+        );
+        strings.insert("inspector_empty".to_string(), "还没有请求记录".to_string());
+        strings.insert("inspector_request".to_string(), "请求体：".to_string());
+        strings.insert("inspector_response".to_string(), "响应：".to_string());
+        strings.insert(
+            "notification_exchange_exported".to_string(),
+            "请求记录已导出到".to_string(),
+        );
+        strings.insert(
+            "notification_export_failed".to_string(),
+            "导出失败：".to_string(),
+        );
+        strings.insert(
+            "help_nav_line11".to_string(),
+            "  F12          显示/隐藏请求检查器".to_string(),
+        );
+        strings.insert(
+            "help_nav_line12".to_string(),
+            "  [ ] / x      选择/导出请求记录".to_string(),
+        );
+        strings.insert("error_auth_failed".to_string(), "🔑 认证失败".to_string());
+        strings.insert(
+            "error_rate_limited".to_string(),
+            "⏳ 请求过于频繁".to_string(),
+        );
+        strings.insert(
+            "error_quota_exhausted".to_string(),
+            "💳 额度已用尽".to_string(),
+        );
+        strings.insert(
+            "error_context_too_long".to_string(),
+            "📏 上下文过长".to_string(),
+        );
+        strings.insert(
+            "error_content_filtered".to_string(),
+            "🚫 内容被过滤".to_string(),
+        );
+        strings.insert("error_network".to_string(), "🌐 网络错误".to_string());
+        strings.insert("error_bad_response".to_string(), "❓ 响应异常".to_string());
+        strings.insert(
+            "error_hint_auth_failed".to_string(),
+            "请在 .env 文件或系统环境变量中设置有效的 {env}".to_string(),
+        );
+        strings.insert(
+            "error_hint_rate_limited".to_string(),
+            "请稍候片刻再重试，或切换到其他模型".to_string(),
+        );
+        strings.insert(
+            "error_hint_quota_exhausted".to_string(),
+            "请在服务商控制台充值或提升额度".to_string(),
+        );
+        strings.insert(
+            "error_hint_context_too_long".to_string(),
+            "请缩短消息，或选择支持长上下文的模型".to_string(),
+        );
+        strings.insert(
+            "error_hint_content_filtered".to_string(),
+            "请修改消息内容后重试".to_string(),
+        );
+        strings.insert(
+            "error_hint_network".to_string(),
+            "请检查网络连接或代理设置后重试".to_string(),
+        );
+        strings.insert(
+            "error_hint_bad_response".to_string(),
+            "服务暂时异常，请稍后重试；按 F12 可查看原始响应".to_string(),
+        );
+        strings.insert(
+            "notification_save_failed".to_string(),
+            "保存对话失败".to_string(),
+        );
+        strings.insert(
+            "notification_load_failed".to_string(),
+            "读取对话失败".to_string(),
+        );
+        strings.insert("notification_resumed".to_string(), "已恢复对话".to_string());
+        strings.insert(
+            "notification_no_saved_conversations".to_string(),
+            "没有已保存的对话".to_string(),
+        );
+        strings.insert("session_untitled".to_string(), "新对话".to_string());
+        strings.insert(
+            "input_hint_renaming".to_string(),
+            "🏷️ 重命名对话 (按 Enter 确认，Esc 取消)".to_string(),
+        );
+        strings.insert(
+            "notification_session_created".to_string(),
+            "已新建对话".to_string(),
+        );
+        strings.insert(
+            "notification_session_closed".to_string(),
+            "已关闭对话".to_string(),
+        );
+        strings.insert(
+            "help_nav_line13".to_string(),
+            "  t / w        新建/关闭对话".to_string(),
+        );
+        strings.insert(
+            "help_nav_line14".to_string(),
+            "  Tab / r      切换/重命名对话".to_string(),
+        );
+        strings.insert(
+            "notification_conversation_exported".to_string(),
+            "对话已导出到".to_string(),
+        );
+        strings.insert(
+            "notification_nothing_to_export".to_string(),
+            "当前对话为空，无可导出内容".to_string(),
+        );
+        strings.insert(
+            "help_nav_line15".to_string(),
+            "  X            导出对话 (Markdown/HTML/JSON)".to_string(),
+        );
+        strings.insert("search_title".to_string(), " 🔍 搜索全部对话 ".to_string());
+        strings.insert(
+            "search_hint".to_string(),
+            " ↑↓ 选择 · Enter 打开 · F2 模型 · F3 发送者 · F4 日期 · Esc 关闭 ".to_string(),
+        );
+        strings.insert("search_filter_model".to_string(), "模型:".to_string());
+        strings.insert("search_filter_sender".to_string(), "发送者:".to_string());
+        strings.insert("search_filter_date".to_string(), "日期:".to_string());
+        strings.insert("search_any".to_string(), "全部".to_string());
+        strings.insert("search_sender_user".to_string(), "你".to_string());
+        strings.insert("search_sender_ai".to_string(), "AI".to_string());
+        strings.insert("search_date_today".to_string(), "今天".to_string());
+        strings.insert("search_date_week".to_string(), "最近 7 天".to_string());
+        strings.insert("search_date_month".to_string(), "最近 30 天".to_string());
+        strings.insert("search_results".to_string(), "条结果".to_string());
+        strings.insert(
+            "search_prompt".to_string(),
+            "输入关键词搜索已保存的对话".to_string(),
+        );
+        strings.insert(
+            "search_no_results".to_string(),
+            "没有找到匹配的消息".to_string(),
+        );
+        strings.insert(
+            "input_hint_searching".to_string(),
+            "🔍 搜索 (Enter 打开结果，Esc 返回)".to_string(),
+        );
+        strings.insert(
+            "notification_search_unavailable".to_string(),
+            "没有可搜索的数据目录".to_string(),
+        );
+        strings.insert(
+            "help_nav_line16".to_string(),
+            "  /            搜索全部对话 (Esc 清除高亮)".to_string(),
+        );
+        strings.insert(
+            "input_hint_selecting".to_string(),
+            "👆 选择消息 (↑↓ 选择，←→ 切换分支，e 编辑重发，r 重新生成，d 删除，p 固定，y 复制，> 引用，+/- 温度，Esc 返回)"
+                .to_string(),
+        );
+        strings.insert(
+            "input_hint_editing_message".to_string(),
+            "✏️ 编辑消息 (Enter 作为新分支重新发送，Esc 取消)".to_string(),
+        );
+        strings.insert(
+            "notification_wait_for_reply".to_string(),
+            "请等待当前回复完成".to_string(),
+        );
+        strings.insert(
+            "notification_edit_user_only".to_string(),
+            "只能编辑你发送的消息".to_string(),
+        );
+        strings.insert(
+            "help_nav_line17".to_string(),
+            "  v            选择消息 (编辑、重新生成、删除、固定、复制、引用)".to_string(),
+        );
+        strings.insert(
+            "notification_regenerate_reply_only".to_string(),
+            "只能重新生成 AI 对你消息的回复".to_string(),
+        );
+        strings.insert(
+            "help_nav_line18".to_string(),
+            "  + / -        调高 / 调低回复温度".to_string(),
+        );
+        strings.insert(
+            "notification_delete_branched".to_string(),
+            "这条消息之后还有其他版本，请先切换到要保留的版本".to_string(),
+        );
+        strings.insert(
+            "notification_message_deleted".to_string(),
+            "消息已删除，不再作为上下文发送".to_string(),
+        );
+        strings.insert(
+            "notification_pin_unsupported".to_string(),
+            "只能固定你和 AI 的对话消息".to_string(),
+        );
+        strings.insert(
+            "notification_message_pinned".to_string(),
+            "📌 已固定，始终作为上下文发送".to_string(),
+        );
+        strings.insert(
+            "notification_message_unpinned".to_string(),
+            "已取消固定".to_string(),
+        );
+        strings.insert(
+            "notification_copied".to_string(),
+            "📋 已复制到剪贴板".to_string(),
+        );
+        strings.insert(
+            "notification_copy_failed".to_string(),
+            "复制失败".to_string(),
+        );
+        strings.insert(
+            "recovery_title".to_string(),
+            "⚠️ 发现未保存的对话".to_string(),
+        );
+        strings.insert(
+            "recovery_message".to_string(),
+            "上次运行意外退出。是否恢复当时打开的对话，包括尚未完成的回复？".to_string(),
+        );
+        strings.insert("recovery_count".to_string(), "可恢复的对话".to_string());
+        strings.insert(
+            "recovery_hint".to_string(),
+            "y / Enter 恢复  ·  n / Esc 放弃".to_string(),
+        );
+        strings.insert("reply_interrupted".to_string(), "回复被中断".to_string());
+        strings.insert(
+            "reply_thinking".to_string(),
+            "🤔 {model} 正在思考中...".to_string(),
+        );
+        strings.insert(
+            "reply_waiting".to_string(),
+            "⏳ {model} 正在等待限流...".to_string(),
+        );
+        strings.insert(
+            "notification_recovered".to_string(),
+            "已恢复的对话".to_string(),
+        );
+        strings.insert(
+            "notification_no_personas".to_string(),
+            "配置文件中没有定义人设 ([[personas]])".to_string(),
+        );
+        strings.insert("notification_persona".to_string(), "人设".to_string());
+        strings.insert("persona_none".to_string(), "无".to_string());
+        strings.insert(
+            "help_nav_line19".to_string(),
+            "  p            切换人设 (配置文件中定义)".to_string(),
+        );
+        strings.insert(
+            "input_hint_editing_normal".to_string(),
+            "-- NORMAL -- (h/l 移动，w/b 按词，0/$ 行首行尾，x 删除，ciw 改词，i/a 输入，Enter 发送，Esc 取消)".to_string(),
+        );
+        strings.insert(
+            "input_hint_command".to_string(),
+            ": 命令 (Enter 执行，Esc 取消)".to_string(),
+        );
+        strings.insert(
+            "notification_unknown_command".to_string(),
+            "未知命令".to_string(),
+        );
+        strings.insert(
+            "notification_invalid_argument".to_string(),
+            "参数无效".to_string(),
+        );
+        strings.insert(
+            "notification_no_reply".to_string(),
+            "还没有可复制的回复".to_string(),
+        );
+        strings.insert(
+            "help_nav_line20".to_string(),
+            "  K            API 密钥与档案".to_string(),
+        );
+        strings.insert("keys_title".to_string(), "🔑 API 密钥".to_string());
+        strings.insert("keys_profiles".to_string(), "档案:".to_string());
+        strings.insert(
+            "keys_from_env".to_string(),
+            "未设置，使用环境变量".to_string(),
+        );
+        strings.insert(
+            "keys_hint".to_string(),
+            "Enter 设置密钥 · c 使用命令 · d 删除 · ←/→ 切换档案 · u 使用此档案 · n 新建档案 · D 删除档案 · Esc 关闭".to_string(),
+        );
+        strings.insert(
+            "input_hint_keys".to_string(),
+            "API 密钥 (● 为使用中的档案)".to_string(),
+        );
+        strings.insert(
+            "input_hint_key_entry_key".to_string(),
+            "粘贴 API 密钥 (Enter 保存，Esc 取消)".to_string(),
+        );
+        strings.insert(
+            "input_hint_key_entry_command".to_string(),
+            "输出密钥的命令，如 pass show deepseek (Enter 保存，Esc 取消)".to_string(),
+        );
+        strings.insert(
+            "input_hint_key_entry_profile".to_string(),
+            "新档案名称 (Enter 创建，Esc 取消)".to_string(),
+        );
+        strings.insert(
+            "input_hint_key_entry_passphrase".to_string(),
+            "为密钥文件设置口令 (Enter 确认，Esc 取消)".to_string(),
+        );
+        strings.insert(
+            "notification_keys_saved".to_string(),
+            "密钥已保存".to_string(),
+        );
+        strings.insert(
+            "notification_key_invalid".to_string(),
+            "密钥已保存，但格式可能有误".to_string(),
+        );
+        strings.insert("key_problem_empty".to_string(), "密钥为空".to_string());
+        strings.insert(
+            "key_problem_whitespace".to_string(),
+            "密钥中有空格或换行".to_string(),
+        );
+        strings.insert(
+            "key_problem_prefix".to_string(),
+            "{provider} 的密钥以 {prefix} 开头".to_string(),
+        );
+        strings.insert(
+            "key_problem_length_exact".to_string(),
+            "{provider} 的密钥在 {prefix} 之后有 {len} 个字符，而不是 {found} 个".to_string(),
+        );
+        strings.insert(
+            "key_problem_length_min".to_string(),
+            "{provider} 的密钥在 {prefix} 之后至少有 {len} 个字符".to_string(),
+        );
+        strings.insert(
+            "key_problem_charset".to_string(),
+            "{provider} 的密钥在 {prefix} 之后只有{charset}".to_string(),
+        );
+        strings.insert("key_charset_hex".to_string(), "十六进制数字".to_string());
+        strings.insert(
+            "key_charset_alphanumeric".to_string(),
+            "字母和数字".to_string(),
+        );
+        strings.insert(
+            "notification_key_failed".to_string(),
+            "无法获取密钥".to_string(),
+        );
+        strings.insert(
+            "notification_keys_unavailable".to_string(),
+            "没有数据目录，无法保存密钥".to_string(),
+        );
+        strings.insert(
+            "notification_profile_exists".to_string(),
+            "档案已存在".to_string(),
+        );
+        strings.insert(
+            "notification_last_profile".to_string(),
+            "不能删除最后一个档案".to_string(),
+        );
         Self { strings }
     }
 
     fn english() -> Self {
         let mut strings = HashMap::new();
-        strings.insert(
-            "app_title".to_string(),
-            "✨ AI Chat Terminal".to_string(),
-        );
+        strings.insert("app_title".to_string(), "✨ AI Chat Terminal".to_string());
         strings.insert(
             "app_subtitle".to_string(),
             "Select AI Model and Start Intelligent Conversation".to_string(),
@@ -230,11 +597,7 @@ impl Translations {
         );
         strings.insert(
             "theme_selector_title".to_string(),
-            "Themes (1-4)".to_string(),
-        );
-        strings.insert(
-            "theme_names".to_string(),
-            "(Blue/Green/Orange/Neon)".to_string(),
+            "Themes (1-4, T)".to_string(),
         );
         strings.insert(
             "input_hint_normal".to_string(),
@@ -282,7 +645,7 @@ impl Translations {
         );
         strings.insert(
             "welcome_start_hint".to_string(),
-            "Press Enter to start chatting, F1 for help".to_string(),
+            "Press Enter to start chatting, R to resume the last chat, F1 for help".to_string(),
         );
         strings.insert(
             "welcome_press_enter".to_string(),
@@ -405,6 +768,382 @@ impl Translations {
             "notification_theme_changed".to_string(),
             "Theme changed".to_string(),
         );
+        strings.insert(
+            "inspector_title".to_string(),
+            "🔎 Request Inspector".to_string(),
+        );
+        strings.insert(
+            "inspector_hint".to_string(),
+            "[ ] select  x export  F12 close".to_string(),
+        );
+        strings.insert(
+            "inspector_empty".to_string(),
+            "No requests captured yet".to_string(),
+        );
+        strings.insert("inspector_request".to_string(), "Request body:".to_string());
+        strings.insert("inspector_response".to_string(), "Response:".to_string());
+        strings.insert(
+            "notification_exchange_exported".to_string(),
+            "Exchange exported to".to_string(),
+        );
+        strings.insert(
+            "notification_export_failed".to_string(),
+            "Export failed:".to_string(),
+        );
+        strings.insert(
+            "help_nav_line11".to_string(),
+            "  F12          Show/Hide request inspector".to_string(),
+        );
+        strings.insert(
+            "help_nav_line12".to_string(),
+            "  [ ] / x      Select/export exchange".to_string(),
+        );
+        strings.insert(
+            "error_auth_failed".to_string(),
+            "🔑 Authentication failed".to_string(),
+        );
+        strings.insert(
+            "error_rate_limited".to_string(),
+            "⏳ Rate limited".to_string(),
+        );
+        strings.insert(
+            "error_quota_exhausted".to_string(),
+            "💳 Quota exhausted".to_string(),
+        );
+        strings.insert(
+            "error_context_too_long".to_string(),
+            "📏 Context too long".to_string(),
+        );
+        strings.insert(
+            "error_content_filtered".to_string(),
+            "🚫 Content filtered".to_string(),
+        );
+        strings.insert("error_network".to_string(), "🌐 Network error".to_string());
+        strings.insert(
+            "error_bad_response".to_string(),
+            "❓ Bad response".to_string(),
+        );
+        strings.insert(
+            "error_hint_auth_failed".to_string(),
+            "Set a valid {env} in the .env file or your environment".to_string(),
+        );
+        strings.insert(
+            "error_hint_rate_limited".to_string(),
+            "Wait a moment before retrying, or switch to another model".to_string(),
+        );
+        strings.insert(
+            "error_hint_quota_exhausted".to_string(),
+            "Top up your balance or raise the quota in the provider console".to_string(),
+        );
+        strings.insert(
+            "error_hint_context_too_long".to_string(),
+            "Shorten the message or pick a long-context model".to_string(),
+        );
+        strings.insert(
+            "error_hint_content_filtered".to_string(),
+            "Rephrase the message and try again".to_string(),
+        );
+        strings.insert(
+            "error_hint_network".to_string(),
+            "Check your network connection or proxy and retry".to_string(),
+        );
+        strings.insert(
+            "error_hint_bad_response".to_string(),
+            "The provider returned an unexpected response; retry later or press F12 to inspect it"
+                .to_string(),
+        );
+        strings.insert(
+            "notification_save_failed".to_string(),
+            "Failed to save conversation".to_string(),
+        );
+        strings.insert(
+            "notification_load_failed".to_string(),
+            "Failed to load conversations".to_string(),
+        );
+        strings.insert(
+            "notification_resumed".to_string(),
+            "Resumed conversation".to_string(),
+        );
+        strings.insert(
+            "notification_no_saved_conversations".to_string(),
+            "No saved conversations".to_string(),
+        );
+        strings.insert("session_untitled".to_string(), "New chat".to_string());
+        strings.insert(
+            "input_hint_renaming".to_string(),
+            "🏷️ Rename chat (Press Enter to confirm, Esc to cancel)".to_string(),
+        );
+        strings.insert(
+            "notification_session_created".to_string(),
+            "New chat opened".to_string(),
+        );
+        strings.insert(
+            "notification_session_closed".to_string(),
+            "Chat closed".to_string(),
+        );
+        strings.insert(
+            "help_nav_line13".to_string(),
+            "  t / w        New/close chat".to_string(),
+        );
+        strings.insert(
+            "help_nav_line14".to_string(),
+            "  Tab / r      Switch/rename chat".to_string(),
+        );
+        strings.insert(
+            "notification_conversation_exported".to_string(),
+            "Conversation exported to".to_string(),
+        );
+        strings.insert(
+            "notification_nothing_to_export".to_string(),
+            "Nothing to export yet".to_string(),
+        );
+        strings.insert(
+            "help_nav_line15".to_string(),
+            "  X            Export chat (Markdown/HTML/JSON)".to_string(),
+        );
+        strings.insert(
+            "search_title".to_string(),
+            " 🔍 Search all conversations ".to_string(),
+        );
+        strings.insert(
+            "search_hint".to_string(),
+            " ↑↓ Select · Enter Open · F2 Model · F3 Sender · F4 Date · Esc Close ".to_string(),
+        );
+        strings.insert("search_filter_model".to_string(), "Model:".to_string());
+        strings.insert("search_filter_sender".to_string(), "Sender:".to_string());
+        strings.insert("search_filter_date".to_string(), "Date:".to_string());
+        strings.insert("search_any".to_string(), "Any".to_string());
+        strings.insert("search_sender_user".to_string(), "You".to_string());
+        strings.insert("search_sender_ai".to_string(), "AI".to_string());
+        strings.insert("search_date_today".to_string(), "Today".to_string());
+        strings.insert("search_date_week".to_string(), "Last 7 days".to_string());
+        strings.insert("search_date_month".to_string(), "Last 30 days".to_string());
+        strings.insert("search_results".to_string(), "results".to_string());
+        strings.insert(
+            "search_prompt".to_string(),
+            "Type to search saved conversations".to_string(),
+        );
+        strings.insert(
+            "search_no_results".to_string(),
+            "No matching messages".to_string(),
+        );
+        strings.insert(
+            "input_hint_searching".to_string(),
+            "🔍 Search (Press Enter to open result, Esc to go back)".to_string(),
+        );
+        strings.insert(
+            "notification_search_unavailable".to_string(),
+            "No data directory to search".to_string(),
+        );
+        strings.insert(
+            "help_nav_line16".to_string(),
+            "  /            Search all chats (Esc clears highlight)".to_string(),
+        );
+        strings.insert(
+            "input_hint_selecting".to_string(),
+            "👆 Select message (↑↓ Select, ←→ Branch, e Edit, r Regenerate, d Delete, p Pin, y Copy, > Quote, +/- Temperature, Esc Back)"
+                .to_string(),
+        );
+        strings.insert(
+            "input_hint_editing_message".to_string(),
+            "✏️ Edit message (Enter resends as a new branch, Esc to cancel)".to_string(),
+        );
+        strings.insert(
+            "notification_wait_for_reply".to_string(),
+            "Wait for the current reply to finish".to_string(),
+        );
+        strings.insert(
+            "notification_edit_user_only".to_string(),
+            "Only your own messages can be edited".to_string(),
+        );
+        strings.insert(
+            "help_nav_line17".to_string(),
+            "  v            Select messages (edit, regenerate, delete, pin, copy, quote)"
+                .to_string(),
+        );
+        strings.insert(
+            "notification_regenerate_reply_only".to_string(),
+            "Only replies to your messages can be regenerated".to_string(),
+        );
+        strings.insert(
+            "help_nav_line18".to_string(),
+            "  + / -        Raise / lower reply temperature".to_string(),
+        );
+        strings.insert(
+            "notification_delete_branched".to_string(),
+            "Other versions branch off here; switch to the one to keep first".to_string(),
+        );
+        strings.insert(
+            "notification_message_deleted".to_string(),
+            "Message deleted; it is no longer sent as context".to_string(),
+        );
+        strings.insert(
+            "notification_pin_unsupported".to_string(),
+            "Only your messages and AI replies can be pinned".to_string(),
+        );
+        strings.insert(
+            "notification_message_pinned".to_string(),
+            "📌 Pinned; always sent as context".to_string(),
+        );
+        strings.insert(
+            "notification_message_unpinned".to_string(),
+            "Unpinned".to_string(),
+        );
+        strings.insert(
+            "notification_copied".to_string(),
+            "📋 Copied to clipboard".to_string(),
+        );
+        strings.insert(
+            "notification_copy_failed".to_string(),
+            "Copy failed".to_string(),
+        );
+        strings.insert(
+            "recovery_title".to_string(),
+            "⚠️ Unsaved conversations found".to_string(),
+        );
+        strings.insert(
+            "recovery_message".to_string(),
+            "The last run ended unexpectedly. Recover the conversations that were open, including replies that were still streaming?".to_string(),
+        );
+        strings.insert(
+            "recovery_count".to_string(),
+            "Conversations to recover".to_string(),
+        );
+        strings.insert(
+            "recovery_hint".to_string(),
+            "y / Enter Recover  ·  n / Esc Discard".to_string(),
+        );
+        strings.insert(
+            "reply_interrupted".to_string(),
+            "Reply interrupted".to_string(),
+        );
+        strings.insert(
+            "reply_thinking".to_string(),
+            "🤔 {model} is thinking...".to_string(),
+        );
+        strings.insert(
+            "reply_waiting".to_string(),
+            "⏳ {model} is waiting for rate limit...".to_string(),
+        );
+        strings.insert(
+            "notification_recovered".to_string(),
+            "Recovered conversations".to_string(),
+        );
+        strings.insert(
+            "notification_no_personas".to_string(),
+            "No personas are defined in the config file ([[personas]])".to_string(),
+        );
+        strings.insert("notification_persona".to_string(), "Persona".to_string());
+        strings.insert("persona_none".to_string(), "none".to_string());
+        strings.insert(
+            "help_nav_line19".to_string(),
+            "  p            Cycle persona (from the config file)".to_string(),
+        );
+        strings.insert(
+            "input_hint_editing_normal".to_string(),
+            "-- NORMAL -- (h/l Move, w/b Word, 0/$ Line, x Delete, ciw Change word, i/a Insert, Enter Send, Esc Cancel)".to_string(),
+        );
+        strings.insert(
+            "input_hint_command".to_string(),
+            ": Command (Enter to run, Esc to cancel)".to_string(),
+        );
+        strings.insert(
+            "notification_unknown_command".to_string(),
+            "Unknown command".to_string(),
+        );
+        strings.insert(
+            "notification_invalid_argument".to_string(),
+            "Invalid argument".to_string(),
+        );
+        strings.insert(
+            "notification_no_reply".to_string(),
+            "No reply to copy yet".to_string(),
+        );
+        strings.insert(
+            "help_nav_line20".to_string(),
+            "  K            API keys and profiles".to_string(),
+        );
+        strings.insert("keys_title".to_string(), "🔑 API Keys".to_string());
+        strings.insert("keys_profiles".to_string(), "Profiles:".to_string());
+        strings.insert("keys_from_env".to_string(), "not set, using".to_string());
+        strings.insert(
+            "keys_hint".to_string(),
+            "Enter Set key · c Use a command · d Remove · ←/→ Profile · u Use this profile · n New profile · D Delete profile · Esc Close".to_string(),
+        );
+        strings.insert(
+            "input_hint_keys".to_string(),
+            "API keys (● marks the profile in use)".to_string(),
+        );
+        strings.insert(
+            "input_hint_key_entry_key".to_string(),
+            "Paste the API key (Enter Save, Esc Cancel)".to_string(),
+        );
+        strings.insert(
+            "input_hint_key_entry_command".to_string(),
+            "A command that prints the key, e.g. pass show deepseek (Enter Save, Esc Cancel)"
+                .to_string(),
+        );
+        strings.insert(
+            "input_hint_key_entry_profile".to_string(),
+            "Name of the new profile (Enter Create, Esc Cancel)".to_string(),
+        );
+        strings.insert(
+            "input_hint_key_entry_passphrase".to_string(),
+            "Choose a passphrase for the key file (Enter Confirm, Esc Cancel)".to_string(),
+        );
+        strings.insert(
+            "notification_keys_saved".to_string(),
+            "Keys saved".to_string(),
+        );
+        strings.insert(
+            "notification_key_invalid".to_string(),
+            "Key saved, but it looks wrong".to_string(),
+        );
+        strings.insert(
+            "key_problem_empty".to_string(),
+            "the key is empty".to_string(),
+        );
+        strings.insert(
+            "key_problem_whitespace".to_string(),
+            "the key contains spaces or line breaks".to_string(),
+        );
+        strings.insert(
+            "key_problem_prefix".to_string(),
+            "{provider} keys start with {prefix}".to_string(),
+        );
+        strings.insert(
+            "key_problem_length_exact".to_string(),
+            "{provider} keys have {len} characters after {prefix}, not {found}".to_string(),
+        );
+        strings.insert(
+            "key_problem_length_min".to_string(),
+            "{provider} keys have at least {len} characters after {prefix}".to_string(),
+        );
+        strings.insert(
+            "key_problem_charset".to_string(),
+            "{provider} keys only have {charset} after {prefix}".to_string(),
+        );
+        strings.insert("key_charset_hex".to_string(), "hex digits".to_string());
+        strings.insert(
+            "key_charset_alphanumeric".to_string(),
+            "letters and digits".to_string(),
+        );
+        strings.insert(
+            "notification_key_failed".to_string(),
+            "Could not get the key for".to_string(),
+        );
+        strings.insert(
+            "notification_keys_unavailable".to_string(),
+            "No data directory to keep keys in".to_string(),
+        );
+        strings.insert(
+            "notification_profile_exists".to_string(),
+            "A profile with that name already exists".to_string(),
+        );
+        strings.insert(
+            "notification_last_profile".to_string(),
+            "The last profile cannot be deleted".to_string(),
+        );
         Self { strings }
     }
 
@@ -0,0 +1,547 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, TimeZone};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::ai_models::AIModel;
+use crate::app::{Message, Sender};
+use crate::storage::{Conversation, TitleSource};
+
+const UNTITLED: &str = "Imported conversation";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// `conversations.json` from a ChatGPT data export.
+    ChatGpt,
+    /// One JSON object per line: either a whole chat-completions request with
+    /// `messages` (and optionally its `choices`), or a single `{role, content}` message.
+    Jsonl,
+}
+
+impl ImportFormat {
+    /// A ChatGPT export is one JSON array; message logs are one object per line.
+    pub fn detect(text: &str) -> Self {
+        if text.trim_start().starts_with('[') {
+            ImportFormat::ChatGpt
+        } else {
+            ImportFormat::Jsonl
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> io::Result<Vec<Conversation>> {
+        let mut conversations = match self {
+            ImportFormat::ChatGpt => from_chatgpt(text)?,
+            ImportFormat::Jsonl => from_jsonl(text)?,
+        };
+        assign_ids(&mut conversations);
+        Ok(conversations)
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "chatgpt" => Ok(ImportFormat::ChatGpt),
+            "jsonl" | "openai" => Ok(ImportFormat::Jsonl),
+            _ => Err(format!(
+                "Unknown import format: {} (expected chatgpt or jsonl)",
+                name
+            )),
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Unix seconds (possibly fractional), a numeric string or RFC 3339.
+fn parse_time(value: &Value) -> Option<DateTime<Local>> {
+    let seconds = match value {
+        Value::Number(number) => number.as_f64()?,
+        Value::String(text) => match text.parse::<f64>() {
+            Ok(seconds) => seconds,
+            Err(_) => {
+                return DateTime::parse_from_rfc3339(text)
+                    .ok()
+                    .map(|time| time.with_timezone(&Local));
+            }
+        },
+        _ => return None,
+    };
+    let millis = (seconds * 1000.0).round() as i64;
+    Local.timestamp_millis_opt(millis).single()
+}
+
+/// Plain strings, or arrays of text parts as used by multimodal messages. Images and
+/// other non-text parts are dropped.
+fn content_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                Value::String(text) => Some(text.as_str()),
+                Value::Object(object) => object.get("text").and_then(Value::as_str),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// `user` and `assistant` messages; system prompts and tool calls have no place in an
+/// aichat conversation and are skipped.
+fn sender_for(role: &str, model: &AIModel) -> Option<Sender> {
+    match role {
+        "user" => Some(Sender::User),
+        "assistant" => Some(Sender::AI(model.clone())),
+        _ => None,
+    }
+}
+
+/// Builds a conversation from imported messages, or `None` when nothing was said.
+fn conversation(
+    title: Option<String>,
+    model: Option<AIModel>,
+    created_at: Option<DateTime<Local>>,
+    messages: Vec<Message>,
+) -> Option<Conversation> {
+    let first = messages.first()?;
+    let created_at = created_at.unwrap_or(first.timestamp);
+    let updated_at = messages
+        .last()
+        .map(|msg| msg.timestamp)
+        .unwrap_or(created_at);
+    let model = model
+        .or_else(|| {
+            messages.iter().rev().find_map(|msg| match &msg.sender {
+                Sender::AI(model) => Some(model.clone()),
+                _ => None,
+            })
+        })
+        .unwrap_or_else(|| AIModel::Custom("unknown".to_string()));
+    let (title, title_source) = match title.filter(|title| !title.trim().is_empty()) {
+        Some(title) => (title, TitleSource::Generated),
+        None => (
+            Conversation::fallback_title(&messages).unwrap_or_else(|| UNTITLED.to_string()),
+            TitleSource::Fallback,
+        ),
+    };
+    Some(Conversation {
+        id: String::new(),
+        title,
+        title_source: Some(title_source),
+        model,
+        persona: None,
+        created_at,
+        updated_at,
+        messages,
+    })
+}
+
+/// Ids are `import-` and a hash of the messages, so they never clash with
+/// `Conversation::new_id` and importing the same file twice overwrites rather than
+/// duplicates, even when the source has no timestamps.
+fn assign_ids(conversations: &mut [Conversation]) {
+    let mut seen = HashSet::new();
+    for conversation in conversations.iter_mut() {
+        let base = format!("import-{:016x}", content_hash(conversation));
+        let mut id = base.clone();
+        let mut suffix = 1;
+        while !seen.insert(id.clone()) {
+            id = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        conversation.id = id;
+    }
+}
+
+/// FNV-1a over each message's role and content; unlike `DefaultHasher` it is the
+/// same on every build, which the ids of earlier imports depend on.
+fn content_hash(conversation: &Conversation) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for message in &conversation.messages {
+        let role: &[u8] = match message.sender {
+            Sender::User => b"user",
+            _ => b"assistant",
+        };
+        for byte in role
+            .iter()
+            .chain([0u8].iter())
+            .chain(message.content.as_bytes())
+            .chain([0u8].iter())
+        {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+#[derive(Deserialize)]
+struct ChatGptConversation {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    create_time: Option<Value>,
+    #[serde(default)]
+    mapping: HashMap<String, ChatGptNode>,
+    #[serde(default)]
+    current_node: Option<String>,
+    #[serde(default)]
+    default_model_slug: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptNode {
+    #[serde(default)]
+    message: Option<ChatGptMessage>,
+    #[serde(default)]
+    parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+    author: ChatGptAuthor,
+    #[serde(default)]
+    content: Option<ChatGptContent>,
+    #[serde(default)]
+    create_time: Option<Value>,
+    #[serde(default)]
+    metadata: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptAuthor {
+    role: String,
+}
+
+#[derive(Deserialize)]
+struct ChatGptContent {
+    #[serde(default)]
+    parts: Option<Value>,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+impl ChatGptConversation {
+    /// ChatGPT stores every edit and regeneration as a tree. The branch that was on
+    /// screen ends at `current_node`; without one, all messages are taken in time order.
+    fn branch(&self) -> Vec<&ChatGptMessage> {
+        let Some(mut id) = self.current_node.as_deref() else {
+            let mut messages: Vec<&ChatGptMessage> = self
+                .mapping
+                .values()
+                .filter_map(|node| node.message.as_ref())
+                .collect();
+            messages.sort_by(|a, b| {
+                let time = |msg: &ChatGptMessage| {
+                    msg.create_time
+                        .as_ref()
+                        .and_then(Value::as_f64)
+                        .unwrap_or(0.0)
+                };
+                time(a).total_cmp(&time(b))
+            });
+            return messages;
+        };
+        let mut branch = Vec::new();
+        // Bounded by the node count so a malformed export with a cycle still ends.
+        for _ in 0..self.mapping.len() {
+            let Some(node) = self.mapping.get(id) else {
+                break;
+            };
+            branch.extend(node.message.as_ref());
+            match node.parent.as_deref() {
+                Some(parent) => id = parent,
+                None => break,
+            }
+        }
+        branch.reverse();
+        branch
+    }
+
+    fn into_conversation(self) -> Option<Conversation> {
+        let default_model = self
+            .default_model_slug
+            .as_deref()
+            .map(AIModel::from_api_name);
+        let created_at = self.create_time.as_ref().and_then(parse_time);
+        let mut last_time = created_at.unwrap_or_else(Local::now);
+        let mut messages = Vec::new();
+        for message in self.branch() {
+            let model = message
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get("model_slug"))
+                .and_then(Value::as_str)
+                .map(AIModel::from_api_name)
+                .or_else(|| default_model.clone())
+                .unwrap_or_else(|| AIModel::Custom("chatgpt".to_string()));
+            let Some(sender) = sender_for(&message.author.role, &model) else {
+                continue;
+            };
+            let content = match &message.content {
+                Some(ChatGptContent {
+                    parts: Some(parts), ..
+                }) => content_text(parts),
+                Some(ChatGptContent {
+                    text: Some(text), ..
+                }) => text.clone(),
+                _ => String::new(),
+            };
+            if content.trim().is_empty() {
+                continue;
+            }
+            if let Some(time) = message.create_time.as_ref().and_then(parse_time) {
+                last_time = time;
+            }
+            messages.push(Message {
+                timestamp: last_time,
+                ..Message::new(content, sender)
+            });
+        }
+        conversation(self.title, default_model, created_at, messages)
+    }
+}
+
+pub fn from_chatgpt(json: &str) -> io::Result<Vec<Conversation>> {
+    let exported: Vec<ChatGptConversation> = serde_json::from_str(json)
+        .map_err(|e| invalid(format!("not a ChatGPT conversations.json: {}", e)))?;
+    Ok(exported
+        .into_iter()
+        .filter_map(ChatGptConversation::into_conversation)
+        .collect())
+}
+
+fn line_message(
+    object: &Value,
+    model: &AIModel,
+    fallback_time: DateTime<Local>,
+) -> Option<Message> {
+    let role = object.get("role").and_then(Value::as_str)?;
+    let sender = sender_for(role, model)?;
+    let content = object.get("content").map(content_text).unwrap_or_default();
+    if content.trim().is_empty() {
+        return None;
+    }
+    let timestamp = ["timestamp", "created_at", "created"]
+        .iter()
+        .find_map(|key| object.get(*key).and_then(parse_time))
+        .unwrap_or(fallback_time);
+    Some(Message {
+        timestamp,
+        ..Message::new(content, sender)
+    })
+}
+
+/// Consecutive single-message lines form one conversation until a line names a
+/// different `conversation_id` or a whole request line interrupts them.
+pub fn from_jsonl(text: &str) -> io::Result<Vec<Conversation>> {
+    let mut conversations = Vec::new();
+    let mut pending: Vec<Message> = Vec::new();
+    let mut pending_id: Option<String> = None;
+    let mut pending_model: Option<AIModel> = None;
+    let flush = |pending: &mut Vec<Message>,
+                 model: &mut Option<AIModel>,
+                 conversations: &mut Vec<Conversation>| {
+        conversations.extend(conversation(
+            None,
+            model.take(),
+            None,
+            std::mem::take(pending),
+        ));
+    };
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object: Value = serde_json::from_str(line)
+            .map_err(|e| invalid(format!("line {}: {}", index + 1, e)))?;
+        let model = object
+            .get("model")
+            .and_then(Value::as_str)
+            .map(AIModel::from_api_name);
+        let time = ["created", "timestamp", "created_at"]
+            .iter()
+            .find_map(|key| object.get(*key).and_then(parse_time));
+        if let Some(request) = object.get("messages").and_then(Value::as_array) {
+            flush(&mut pending, &mut pending_model, &mut conversations);
+            pending_id = None;
+            let reply_model = model
+                .clone()
+                .unwrap_or_else(|| AIModel::Custom("unknown".to_string()));
+            let fallback = time.unwrap_or_else(Local::now);
+            let mut messages: Vec<Message> = request
+                .iter()
+                .filter_map(|msg| line_message(msg, &reply_model, fallback))
+                .collect();
+            let reply = object
+                .pointer("/choices/0/message")
+                .or_else(|| object.get("response"));
+            if let Some(reply) = reply {
+                let reply = match reply {
+                    Value::String(text) => {
+                        serde_json::json!({ "role": "assistant", "content": text })
+                    }
+                    other => other.clone(),
+                };
+                messages.extend(line_message(&reply, &reply_model, fallback));
+            }
+            conversations.extend(conversation(None, model, time, messages));
+            continue;
+        }
+        if object.get("role").is_none() {
+            return Err(invalid(format!(
+                "line {}: expected a message with `role` or a request with `messages`",
+                index + 1
+            )));
+        }
+        let id = ["conversation_id", "session_id"]
+            .iter()
+            .find_map(|key| object.get(*key).and_then(Value::as_str))
+            .map(str::to_string);
+        if id.is_some() && pending_id.is_some() && id != pending_id {
+            flush(&mut pending, &mut pending_model, &mut conversations);
+        }
+        if id.is_some() {
+            pending_id = id;
+        }
+        if model.is_some() {
+            pending_model = model;
+        }
+        let reply_model = pending_model
+            .clone()
+            .unwrap_or_else(|| AIModel::Custom("unknown".to_string()));
+        let fallback = pending
+            .last()
+            .map(|msg| msg.timestamp)
+            .unwrap_or_else(Local::now);
+        pending.extend(line_message(&object, &reply_model, fallback));
+    }
+    flush(&mut pending, &mut pending_model, &mut conversations);
+    Ok(conversations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::aliyun::AliYunModelType;
+
+    const CHATGPT: &str = r#"[{
+        "title": "Borrow checker",
+        "create_time": 1700000000.5,
+        "update_time": 1700000100.0,
+        "current_node": "c",
+        "default_model_slug": "gpt-4o",
+        "mapping": {
+            "root": {"id": "root", "message": null, "parent": null, "children": ["s"]},
+            "s": {"id": "s", "parent": "root", "children": ["a"], "message": {
+                "author": {"role": "system"}, "content": {"content_type": "text", "parts": [""]}}},
+            "a": {"id": "a", "parent": "s", "children": ["b", "old"], "message": {
+                "author": {"role": "user"}, "create_time": 1700000010.0,
+                "content": {"content_type": "text", "parts": ["Why does this not compile?"]}}},
+            "old": {"id": "old", "parent": "a", "children": [], "message": {
+                "author": {"role": "assistant"}, "create_time": 1700000015.0,
+                "content": {"content_type": "text", "parts": ["Discarded answer"]}}},
+            "b": {"id": "b", "parent": "a", "children": ["c"], "message": {
+                "author": {"role": "assistant"}, "create_time": 1700000020.0,
+                "metadata": {"model_slug": "o1-preview"},
+                "content": {"content_type": "text", "parts": ["Two mutable borrows."]}}},
+            "c": {"id": "c", "parent": "b", "children": [], "message": {
+                "author": {"role": "user"}, "create_time": 1700000030.0,
+                "content": {"content_type": "multimodal_text",
+                            "parts": [{"content_type": "image_asset_pointer"}, "Like this?"]}}}
+        }
+    }]"#;
+
+    #[test]
+    fn test_chatgpt_follows_current_branch() {
+        let conversations = ImportFormat::detect(CHATGPT).parse(CHATGPT).unwrap();
+        assert_eq!(conversations.len(), 1);
+        let conversation = &conversations[0];
+        assert_eq!(conversation.title, "Borrow checker");
+        assert_eq!(conversation.model, AIModel::Custom("gpt-4o".to_string()));
+        let contents: Vec<&str> = conversation
+            .messages
+            .iter()
+            .map(|msg| msg.content.as_str())
+            .collect();
+        assert_eq!(
+            contents,
+            vec![
+                "Why does this not compile?",
+                "Two mutable borrows.",
+                "Like this?"
+            ]
+        );
+        assert_eq!(
+            conversation.messages[1].sender,
+            Sender::AI(AIModel::Custom("o1-preview".to_string()))
+        );
+        assert_eq!(conversation.messages[2].timestamp.timestamp(), 1700000030);
+        assert_eq!(conversation.created_at.timestamp_millis(), 1700000000500);
+        assert!(conversation.id.starts_with("import-"));
+    }
+
+    #[test]
+    fn test_jsonl_requests_and_message_logs() {
+        let log = r#"{"model": "qwen-plus", "created": 1700000000, "messages": [{"role": "system", "content": "Be brief."}, {"role": "user", "content": "Hi"}], "choices": [{"message": {"role": "assistant", "content": "Hello!"}}]}
+
+{"conversation_id": "x", "role": "user", "content": [{"type": "text", "text": "First"}], "timestamp": "2024-05-01T10:00:00Z"}
+{"conversation_id": "x", "role": "assistant", "model": "deepseek-chat", "content": "Reply", "timestamp": "2024-05-01T10:00:05Z"}
+{"conversation_id": "y", "role": "user", "content": "Other chat"}"#;
+        let conversations = ImportFormat::detect(log).parse(log).unwrap();
+        assert_eq!(conversations.len(), 3);
+        assert_eq!(
+            conversations[0].model,
+            AIModel::AliYun(AliYunModelType::QwenPlus)
+        );
+        assert_eq!(conversations[0].messages.len(), 2);
+        assert_eq!(
+            conversations[0].messages[1].sender,
+            Sender::AI(AIModel::AliYun(AliYunModelType::QwenPlus))
+        );
+        assert_eq!(conversations[1].title, "First");
+        assert_eq!(
+            conversations[1].messages[1].sender,
+            Sender::AI(AIModel::DeepSeek)
+        );
+        assert_eq!(
+            conversations[1].updated_at,
+            DateTime::parse_from_rfc3339("2024-05-01T10:00:05Z").unwrap()
+        );
+        assert_eq!(conversations[2].messages[0].content, "Other chat");
+    }
+
+    #[test]
+    fn test_reimporting_without_timestamps_keeps_the_ids() {
+        let log = r#"{"conversation_id": "x", "role": "user", "content": "First"}
+{"conversation_id": "x", "role": "assistant", "content": "Reply"}
+{"conversation_id": "y", "role": "user", "content": "First"}"#;
+        let ids = |text: &str| -> Vec<String> {
+            let conversations = ImportFormat::Jsonl.parse(text).unwrap();
+            conversations.into_iter().map(|c| c.id).collect()
+        };
+        let first = ids(log);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(ids(log), first);
+        assert_eq!(first.len(), 2);
+        assert_ne!(first[0], first[1]);
+        assert!(first.iter().all(|id| id.starts_with("import-")));
+    }
+
+    #[test]
+    fn test_bad_input_reports_the_line() {
+        let error = from_jsonl("{\"role\": \"user\", \"content\": \"ok\"}\nnot json").unwrap_err();
+        assert!(error.to_string().starts_with("line 2:"));
+        assert!(from_chatgpt("{}").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{Conversation, ConversationStore};
+
+/// How often the journal is rewritten at most while a reply streams in.
+const WRITE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Every open conversation as it is on screen, replies still streaming included.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Journal {
+    /// The id of the conversation that was on screen.
+    pub active: Option<String>,
+    pub sessions: Vec<Conversation>,
+}
+
+/// `journal.json` next to the conversations directory. It is rewritten while the app
+/// runs and removed on a clean exit, so finding one at startup means the last run
+/// crashed or was killed.
+#[derive(Debug)]
+pub struct JournalFile {
+    path: PathBuf,
+    last_write: Option<(u64, Instant)>,
+}
+
+impl JournalFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            last_write: None,
+        }
+    }
+
+    pub fn open_default() -> Option<Self> {
+        ConversationStore::data_dir().map(|dir| Self::new(dir.join("journal.json")))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The journal a previous run left behind, if it holds any conversation.
+    pub fn load(&self) -> io::Result<Option<Journal>> {
+        let json = match fs::read_to_string(&self.path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let journal: Journal = serde_json::from_str(&json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok((!journal.sessions.is_empty()).then_some(journal))
+    }
+
+    /// Writes the journal built by `journal` when `state` differs from the last write
+    /// and that write is old enough, so calling it every tick stays cheap.
+    pub fn write_if_changed(
+        &mut self,
+        state: impl Hash,
+        journal: impl FnOnce() -> Journal,
+    ) -> io::Result<()> {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        let fingerprint = hasher.finish();
+        if let Some((last, at)) = self.last_write
+            && (last == fingerprint || at.elapsed() < WRITE_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_write = Some((fingerprint, Instant::now()));
+        self.write(&journal())
+    }
+
+    fn write(&self, journal: &Journal) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(journal)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(tmp, &self.path)
+    }
+
+    pub fn remove(&mut self) -> io::Result<()> {
+        self.last_write = None;
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_models::AIModel;
+    use crate::testing::temp_path;
+    use chrono::Local;
+
+    #[test]
+    fn test_write_is_throttled_and_removed_on_exit() {
+        let path = temp_path("journal.json");
+        let mut file = JournalFile::new(&path);
+        assert!(file.load().unwrap().is_none());
+        let now = Local::now();
+        let journal = |title: &str| Journal {
+            active: Some("a".to_string()),
+            sessions: vec![Conversation {
+                id: "a".to_string(),
+                title: title.to_string(),
+                title_source: None,
+                model: AIModel::DeepSeek,
+                persona: None,
+                created_at: now,
+                updated_at: now,
+                messages: Vec::new(),
+            }],
+        };
+        file.write_if_changed(1, || journal("first")).unwrap();
+        file.write_if_changed(2, || journal("second")).unwrap();
+        assert_eq!(file.load().unwrap().unwrap().sessions[0].title, "first");
+        file.remove().unwrap();
+        assert!(file.load().unwrap().is_none());
+        file.remove().unwrap();
+    }
+}
@@ -0,0 +1,983 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::{KeybindingsConfig, Keys, Preset};
+
+/// The screens and input modes that have their own bindings, each a section of
+/// `[keybindings]` in the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Mode {
+    Welcome,
+    Normal,
+    Editing,
+    Renaming,
+    Searching,
+    Selecting,
+    /// Moving around the input without typing, in the vim preset.
+    EditingNormal,
+    /// The `:` command line.
+    Command,
+    /// The API key screen.
+    Keys,
+    /// Typing a key, key command, profile name or passphrase on the key screen.
+    KeyEntry,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 10] = [
+        Mode::Welcome,
+        Mode::Normal,
+        Mode::Editing,
+        Mode::Renaming,
+        Mode::Searching,
+        Mode::Selecting,
+        Mode::EditingNormal,
+        Mode::Command,
+        Mode::Keys,
+        Mode::KeyEntry,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Welcome => "welcome",
+            Mode::Normal => "normal",
+            Mode::Editing => "editing",
+            Mode::Renaming => "renaming",
+            Mode::Searching => "searching",
+            Mode::Selecting => "selecting",
+            Mode::EditingNormal => "editing_normal",
+            Mode::Command => "command",
+            Mode::Keys => "keys",
+            Mode::KeyEntry => "key_entry",
+        }
+    }
+
+    /// Modes where unbound keys type text, so plain characters cannot be bound.
+    fn takes_text(&self) -> bool {
+        matches!(
+            self,
+            Mode::Editing | Mode::Renaming | Mode::Searching | Mode::Command | Mode::KeyEntry
+        )
+    }
+
+    fn config<'a>(&self, config: &'a KeybindingsConfig) -> &'a BTreeMap<String, Keys> {
+        match self {
+            Mode::Welcome => &config.welcome,
+            Mode::Normal => &config.normal,
+            Mode::Editing => &config.editing,
+            Mode::Renaming => &config.renaming,
+            Mode::Searching => &config.searching,
+            Mode::Selecting => &config.selecting,
+            Mode::EditingNormal => &config.editing_normal,
+            Mode::Command => &config.command,
+            Mode::Keys => &config.keys,
+            Mode::KeyEntry => &config.key_entry,
+        }
+    }
+}
+
+/// Something a key can be bound to. What `Cancel`, `SelectPrevious` and the like do
+/// depends on the mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Quit,
+    Help,
+    Inspector,
+    InspectorPrevious,
+    InspectorNext,
+    InspectorExport,
+    Start,
+    Resume,
+    Edit,
+    Send,
+    Confirm,
+    Cancel,
+    DeleteChar,
+    ClearInput,
+    NewSession,
+    NextSession,
+    PreviousSession,
+    Rename,
+    CloseSession,
+    Export,
+    Search,
+    SelectMessages,
+    SelectPrevious,
+    SelectNext,
+    FilterModel,
+    FilterSender,
+    FilterDate,
+    PreviousBranch,
+    NextBranch,
+    EditMessage,
+    Regenerate,
+    DeleteMessage,
+    TogglePin,
+    CopyMessage,
+    QuoteMessage,
+    TemperatureUp,
+    TemperatureDown,
+    ClearHighlight,
+    NextPersona,
+    Chinese,
+    English,
+    Theme(usize),
+    NextTheme,
+    PreviousTheme,
+    PreviousModel,
+    NextModel,
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    ScrollHome,
+    ScrollEnd,
+    CopyReply,
+    CommandLine,
+    NormalMode,
+    Insert,
+    Append,
+    InsertLineStart,
+    AppendLineEnd,
+    CursorLeft,
+    CursorRight,
+    WordForward,
+    WordBackward,
+    LineStart,
+    LineEnd,
+    ChangeInnerWord,
+    Keys,
+    PreviousProfile,
+    NextProfile,
+    SetKey,
+    SetKeyCommand,
+    RemoveKey,
+    UseProfile,
+    NewProfile,
+    DeleteProfile,
+}
+
+/// Every command by mode and config name, with its default keys.
+const COMMANDS: &[(Mode, &str, Command, &[&str])] = &[
+    (Mode::Welcome, "start", Command::Start, &["enter"]),
+    (Mode::Welcome, "resume", Command::Resume, &["r", "R"]),
+    (Mode::Welcome, "chinese", Command::Chinese, &["c", "C"]),
+    (Mode::Welcome, "english", Command::English, &["e", "E"]),
+    (Mode::Welcome, "theme_1", Command::Theme(0), &["1"]),
+    (Mode::Welcome, "theme_2", Command::Theme(1), &["2"]),
+    (Mode::Welcome, "theme_3", Command::Theme(2), &["3"]),
+    (Mode::Welcome, "theme_4", Command::Theme(3), &["4"]),
+    (Mode::Welcome, "help", Command::Help, &["f1"]),
+    (Mode::Welcome, "quit", Command::Quit, &["q"]),
+    (Mode::Normal, "quit", Command::Quit, &["q"]),
+    (Mode::Normal, "help", Command::Help, &["f1"]),
+    (Mode::Normal, "inspector", Command::Inspector, &["f12"]),
+    (
+        Mode::Normal,
+        "inspector_previous",
+        Command::InspectorPrevious,
+        &["["],
+    ),
+    (
+        Mode::Normal,
+        "inspector_next",
+        Command::InspectorNext,
+        &["]"],
+    ),
+    (
+        Mode::Normal,
+        "inspector_export",
+        Command::InspectorExport,
+        &["x"],
+    ),
+    (Mode::Normal, "edit", Command::Edit, &["i"]),
+    (Mode::Normal, "send", Command::Send, &["enter"]),
+    (Mode::Normal, "new_session", Command::NewSession, &["t"]),
+    (Mode::Normal, "next_session", Command::NextSession, &["tab"]),
+    (
+        Mode::Normal,
+        "previous_session",
+        Command::PreviousSession,
+        &["shift+tab"],
+    ),
+    (Mode::Normal, "rename", Command::Rename, &["r"]),
+    (Mode::Normal, "close_session", Command::CloseSession, &["w"]),
+    (Mode::Normal, "export", Command::Export, &["X"]),
+    (Mode::Normal, "search", Command::Search, &["/"]),
+    (
+        Mode::Normal,
+        "select_messages",
+        Command::SelectMessages,
+        &["v"],
+    ),
+    (
+        Mode::Normal,
+        "temperature_up",
+        Command::TemperatureUp,
+        &["+", "="],
+    ),
+    (
+        Mode::Normal,
+        "temperature_down",
+        Command::TemperatureDown,
+        &["-"],
+    ),
+    (
+        Mode::Normal,
+        "clear_highlight",
+        Command::ClearHighlight,
+        &["esc"],
+    ),
+    (Mode::Normal, "next_persona", Command::NextPersona, &["p"]),
+    (Mode::Normal, "chinese", Command::Chinese, &["c", "C"]),
+    (Mode::Normal, "english", Command::English, &["e", "E"]),
+    (Mode::Normal, "theme_1", Command::Theme(0), &["1"]),
+    (Mode::Normal, "theme_2", Command::Theme(1), &["2"]),
+    (Mode::Normal, "theme_3", Command::Theme(2), &["3"]),
+    (Mode::Normal, "theme_4", Command::Theme(3), &["4"]),
+    (Mode::Normal, "next_theme", Command::NextTheme, &["T"]),
+    (Mode::Normal, "previous_theme", Command::PreviousTheme, &[]),
+    (
+        Mode::Normal,
+        "previous_model",
+        Command::PreviousModel,
+        &["left"],
+    ),
+    (Mode::Normal, "next_model", Command::NextModel, &["right"]),
+    (Mode::Normal, "scroll_up", Command::ScrollUp, &["up"]),
+    (Mode::Normal, "scroll_down", Command::ScrollDown, &["down"]),
+    (Mode::Normal, "page_up", Command::PageUp, &["pageup"]),
+    (Mode::Normal, "page_down", Command::PageDown, &["pagedown"]),
+    (Mode::Normal, "scroll_home", Command::ScrollHome, &["home"]),
+    (Mode::Normal, "scroll_end", Command::ScrollEnd, &["end"]),
+    (Mode::Normal, "copy_reply", Command::CopyReply, &[]),
+    (Mode::Normal, "command_line", Command::CommandLine, &[]),
+    (Mode::Normal, "keys", Command::Keys, &["K"]),
+    (Mode::Editing, "send", Command::Send, &["enter"]),
+    (Mode::Editing, "cancel", Command::Cancel, &["esc"]),
+    (Mode::Editing, "normal_mode", Command::NormalMode, &[]),
+    (
+        Mode::Editing,
+        "delete_char",
+        Command::DeleteChar,
+        &["backspace"],
+    ),
+    (Mode::Editing, "clear", Command::ClearInput, &["delete"]),
+    (Mode::Renaming, "confirm", Command::Confirm, &["enter"]),
+    (Mode::Renaming, "cancel", Command::Cancel, &["esc"]),
+    (
+        Mode::Renaming,
+        "delete_char",
+        Command::DeleteChar,
+        &["backspace"],
+    ),
+    (Mode::Searching, "open", Command::Confirm, &["enter"]),
+    (Mode::Searching, "close", Command::Cancel, &["esc"]),
+    (
+        Mode::Searching,
+        "previous",
+        Command::SelectPrevious,
+        &["up"],
+    ),
+    (Mode::Searching, "next", Command::SelectNext, &["down"]),
+    (
+        Mode::Searching,
+        "filter_model",
+        Command::FilterModel,
+        &["f2"],
+    ),
+    (
+        Mode::Searching,
+        "filter_sender",
+        Command::FilterSender,
+        &["f3"],
+    ),
+    (Mode::Searching, "filter_date", Command::FilterDate, &["f4"]),
+    (
+        Mode::Searching,
+        "delete_char",
+        Command::DeleteChar,
+        &["backspace"],
+    ),
+    (
+        Mode::Selecting,
+        "previous",
+        Command::SelectPrevious,
+        &["up"],
+    ),
+    (Mode::Selecting, "next", Command::SelectNext, &["down"]),
+    (
+        Mode::Selecting,
+        "previous_branch",
+        Command::PreviousBranch,
+        &["left"],
+    ),
+    (
+        Mode::Selecting,
+        "next_branch",
+        Command::NextBranch,
+        &["right"],
+    ),
+    (
+        Mode::Selecting,
+        "edit",
+        Command::EditMessage,
+        &["enter", "e"],
+    ),
+    (Mode::Selecting, "regenerate", Command::Regenerate, &["r"]),
+    (
+        Mode::Selecting,
+        "delete",
+        Command::DeleteMessage,
+        &["d", "delete"],
+    ),
+    (Mode::Selecting, "pin", Command::TogglePin, &["p"]),
+    (Mode::Selecting, "copy", Command::CopyMessage, &["y"]),
+    (Mode::Selecting, "quote", Command::QuoteMessage, &[">"]),
+    (
+        Mode::Selecting,
+        "temperature_up",
+        Command::TemperatureUp,
+        &["+", "="],
+    ),
+    (
+        Mode::Selecting,
+        "temperature_down",
+        Command::TemperatureDown,
+        &["-"],
+    ),
+    (Mode::Selecting, "done", Command::Cancel, &["esc", "v"]),
+    (Mode::EditingNormal, "insert", Command::Insert, &["i"]),
+    (Mode::EditingNormal, "append", Command::Append, &["a"]),
+    (
+        Mode::EditingNormal,
+        "insert_line_start",
+        Command::InsertLineStart,
+        &["I"],
+    ),
+    (
+        Mode::EditingNormal,
+        "append_line_end",
+        Command::AppendLineEnd,
+        &["A"],
+    ),
+    (
+        Mode::EditingNormal,
+        "left",
+        Command::CursorLeft,
+        &["h", "left"],
+    ),
+    (
+        Mode::EditingNormal,
+        "right",
+        Command::CursorRight,
+        &["l", "right"],
+    ),
+    (
+        Mode::EditingNormal,
+        "word_forward",
+        Command::WordForward,
+        &["w"],
+    ),
+    (
+        Mode::EditingNormal,
+        "word_backward",
+        Command::WordBackward,
+        &["b"],
+    ),
+    (
+        Mode::EditingNormal,
+        "line_start",
+        Command::LineStart,
+        &["0", "home"],
+    ),
+    (
+        Mode::EditingNormal,
+        "line_end",
+        Command::LineEnd,
+        &["$", "end"],
+    ),
+    (
+        Mode::EditingNormal,
+        "delete_char",
+        Command::DeleteChar,
+        &["x", "delete"],
+    ),
+    (
+        Mode::EditingNormal,
+        "change_inner_word",
+        Command::ChangeInnerWord,
+        &["c i w"],
+    ),
+    (Mode::EditingNormal, "send", Command::Send, &["enter"]),
+    (Mode::EditingNormal, "cancel", Command::Cancel, &["esc"]),
+    (Mode::Command, "run", Command::Confirm, &["enter"]),
+    (Mode::Command, "cancel", Command::Cancel, &["esc"]),
+    (
+        Mode::Command,
+        "delete_char",
+        Command::DeleteChar,
+        &["backspace"],
+    ),
+    (Mode::Keys, "previous", Command::SelectPrevious, &["up"]),
+    (Mode::Keys, "next", Command::SelectNext, &["down"]),
+    (
+        Mode::Keys,
+        "previous_profile",
+        Command::PreviousProfile,
+        &["left"],
+    ),
+    (Mode::Keys, "next_profile", Command::NextProfile, &["right"]),
+    (Mode::Keys, "set_key", Command::SetKey, &["enter"]),
+    (
+        Mode::Keys,
+        "set_key_command",
+        Command::SetKeyCommand,
+        &["c"],
+    ),
+    (
+        Mode::Keys,
+        "remove_key",
+        Command::RemoveKey,
+        &["d", "delete"],
+    ),
+    (Mode::Keys, "use_profile", Command::UseProfile, &["u"]),
+    (Mode::Keys, "new_profile", Command::NewProfile, &["n"]),
+    (Mode::Keys, "delete_profile", Command::DeleteProfile, &["D"]),
+    (Mode::Keys, "close", Command::Cancel, &["esc", "K"]),
+    (Mode::KeyEntry, "confirm", Command::Confirm, &["enter"]),
+    (Mode::KeyEntry, "cancel", Command::Cancel, &["esc"]),
+    (
+        Mode::KeyEntry,
+        "delete_char",
+        Command::DeleteChar,
+        &["backspace"],
+    ),
+];
+
+/// The keys the vim preset gives commands in place of their defaults.
+const VIM: &[(Mode, &str, &[&str])] = &[
+    (Mode::Normal, "scroll_up", &["k", "up"]),
+    (Mode::Normal, "scroll_down", &["j", "down"]),
+    (Mode::Normal, "page_up", &["ctrl+u", "pageup"]),
+    (Mode::Normal, "page_down", &["ctrl+d", "pagedown"]),
+    (Mode::Normal, "scroll_home", &["g g", "home"]),
+    (Mode::Normal, "scroll_end", &["G", "end"]),
+    (Mode::Normal, "edit", &["i", "a"]),
+    (Mode::Normal, "copy_reply", &["y y"]),
+    (Mode::Normal, "command_line", &[":"]),
+    (Mode::Editing, "cancel", &["ctrl+c"]),
+    (Mode::Editing, "normal_mode", &["esc"]),
+    (Mode::Selecting, "previous", &["k", "up"]),
+    (Mode::Selecting, "next", &["j", "down"]),
+    (Mode::Selecting, "previous_branch", &["h", "left"]),
+    (Mode::Selecting, "next_branch", &["l", "right"]),
+    (Mode::Selecting, "delete", &["d d", "delete"]),
+    (Mode::Selecting, "copy", &["y y"]),
+    (Mode::Keys, "previous", &["k", "up"]),
+    (Mode::Keys, "next", &["j", "down"]),
+    (Mode::Keys, "previous_profile", &["h", "left"]),
+    (Mode::Keys, "next_profile", &["l", "right"]),
+];
+
+impl Preset {
+    fn keys(&self, mode: Mode, name: &str) -> Option<&'static [&'static str]> {
+        match self {
+            Preset::Default => None,
+            Preset::Vim => VIM
+                .iter()
+                .find(|(preset_mode, preset_name, _)| *preset_mode == mode && *preset_name == name)
+                .map(|(_, _, keys)| *keys),
+        }
+    }
+}
+
+/// The command called `name` in `mode`, as in the config file.
+pub fn find_command(mode: Mode, name: &str) -> Result<Command, String> {
+    COMMANDS
+        .iter()
+        .find(|(command_mode, command_name, _, _)| *command_mode == mode && *command_name == name)
+        .map(|(_, _, command, _)| *command)
+        .ok_or_else(|| format!("unknown command: {}", name))
+}
+
+/// A key with its modifiers, written like `ctrl+q`, `alt+enter`, `shift+tab` or `X`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Shift is already part of the character for letters and symbols, and of BackTab,
+    /// so it is dropped there; terminals do not agree on reporting it.
+    fn normalized(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+
+    pub fn from_event(key: &KeyEvent) -> Self {
+        Self::normalized(key.code, key.modifiers)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (modifier_names, key) = match text.strip_suffix("++") {
+            Some(rest) => (rest, "+"),
+            None if text == "+" => ("", "+"),
+            None => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            modifiers |= match name.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "option" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier {} in {}", name, text)),
+            };
+        }
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "space" => KeyCode::Char(' '),
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key: {}", text)),
+                },
+            },
+        };
+        Ok(Self::normalized(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl+"),
+            (KeyModifiers::ALT, "alt+"),
+            (KeyModifiers::SHIFT, "shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::BackTab => f.write_str("shift+tab"),
+            KeyCode::PageUp => f.write_str("pageup"),
+            KeyCode::PageDown => f.write_str("pagedown"),
+            code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+        }
+    }
+}
+
+/// Keys pressed one after the other, written like `g g`.
+fn parse_keys(text: &str) -> Result<Vec<KeyBinding>, String> {
+    let keys = text
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<KeyBinding>, _>>()?;
+    if keys.is_empty() {
+        return Err("empty key name".to_string());
+    }
+    Ok(keys)
+}
+
+fn describe(keys: &[KeyBinding]) -> String {
+    keys.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What a key press amounts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    Command(Command),
+    /// The start of a longer binding such as `g g`; the next key decides.
+    Pending,
+    Unbound,
+}
+
+impl Lookup {
+    pub fn command(self) -> Option<Command> {
+        match self {
+            Lookup::Command(command) => Some(command),
+            Lookup::Pending | Lookup::Unbound => None,
+        }
+    }
+}
+
+/// The keys pressed so far towards a longer binding.
+#[derive(Debug, Clone, Default)]
+pub struct PendingKeys {
+    mode: Option<Mode>,
+    keys: Vec<KeyBinding>,
+}
+
+impl PendingKeys {
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl fmt::Display for PendingKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&describe(&self.keys))
+    }
+}
+
+/// Which command each key, or sequence of keys, runs per mode.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<(Mode, Vec<KeyBinding>), Command>,
+    /// Every sequence that starts a longer binding.
+    prefixes: HashSet<(Mode, Vec<KeyBinding>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_config(&KeybindingsConfig::default()).expect("default keys do not conflict")
+    }
+}
+
+impl Keymap {
+    /// The preset's bindings with `config` on top. A command listed in the config loses
+    /// its default keys; an empty list leaves it unbound. Binding a key twice in a
+    /// mode, taking a key from a command the config does not rebind, binding a plain
+    /// character where it would be typed, or binding a key that starts another
+    /// binding is a conflict.
+    pub fn from_config(config: &KeybindingsConfig) -> Result<Self, String> {
+        let mut bindings = HashMap::new();
+        for (mode, name, command, keys) in COMMANDS {
+            if mode.config(config).contains_key(*name) {
+                continue;
+            }
+            for key in config.preset.keys(*mode, name).unwrap_or(keys) {
+                bindings.insert((*mode, parse_keys(key)?), (*command, *name, false));
+            }
+        }
+        for mode in Mode::ALL {
+            for (name, keys) in mode.config(config) {
+                let section = format!("[keybindings.{}]", mode.name());
+                let command =
+                    find_command(mode, name).map_err(|e| format!("{}: {}", section, e))?;
+                for key in keys.names() {
+                    let binding =
+                        parse_keys(key).map_err(|e| format!("{} {}: {}", section, name, e))?;
+                    let first = binding[0];
+                    if mode.takes_text()
+                        && matches!(first.code, KeyCode::Char(_))
+                        && !first
+                            .modifiers
+                            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                    {
+                        return Err(format!(
+                            "{} {}: {} is typed as text in this mode; add ctrl or alt",
+                            section,
+                            name,
+                            describe(&binding)
+                        ));
+                    }
+                    let keys = describe(&binding);
+                    if let Some((_, other, from_config)) =
+                        bindings.insert((mode, binding), (command, name.as_str(), true))
+                        && other != name
+                    {
+                        return Err(if from_config {
+                            format!("{} {}: {} is also bound to {}", section, name, keys, other)
+                        } else {
+                            format!(
+                                "{} {}: {} is already bound to {}; rebind {} too",
+                                section, name, keys, other, other
+                            )
+                        });
+                    }
+                }
+            }
+        }
+        let mut sequences: Vec<_> = bindings
+            .iter()
+            .filter(|((_, keys), _)| keys.len() > 1)
+            .collect();
+        sequences.sort_by_key(|((mode, _), (_, name, _))| (*mode, *name));
+        for ((mode, keys), (_, name, from_config)) in sequences {
+            for length in 1..keys.len() {
+                let prefix = &keys[..length];
+                let Some((_, other, prefix_from_config)) = bindings.get(&(*mode, prefix.to_vec()))
+                else {
+                    continue;
+                };
+                let section = format!("[keybindings.{}]", mode.name());
+                return Err(if *prefix_from_config && !from_config {
+                    format!(
+                        "{} {}: {} would hide {}, which is bound to {}; rebind {} too",
+                        section,
+                        other,
+                        describe(prefix),
+                        describe(keys),
+                        name,
+                        name
+                    )
+                } else {
+                    format!(
+                        "{} {}: {} starts with {}, which is bound to {}",
+                        section,
+                        name,
+                        describe(keys),
+                        describe(prefix),
+                        other
+                    )
+                });
+            }
+        }
+        let prefixes = bindings
+            .keys()
+            .flat_map(|(mode, keys)| (1..keys.len()).map(|length| (*mode, keys[..length].to_vec())))
+            .collect();
+        Ok(Self {
+            bindings: bindings
+                .into_iter()
+                .map(|(key, (command, _, _))| (key, command))
+                .collect(),
+            prefixes,
+        })
+    }
+
+    /// The command bound to `key` on its own.
+    pub fn get(&self, mode: Mode, key: &KeyEvent) -> Option<Command> {
+        self.bindings
+            .get(&(mode, vec![KeyBinding::from_event(key)]))
+            .copied()
+    }
+
+    /// What `key` runs in `mode` after the keys in `pending`, which it updates. A key
+    /// that does not continue the pending keys is looked up on its own.
+    pub fn lookup(&self, mode: Mode, pending: &mut PendingKeys, key: &KeyEvent) -> Lookup {
+        let mut keys = match pending.mode {
+            Some(pending_mode) if pending_mode == mode => std::mem::take(&mut pending.keys),
+            _ => Vec::new(),
+        };
+        pending.mode = Some(mode);
+        pending.keys.clear();
+        let started = !keys.is_empty();
+        keys.push(KeyBinding::from_event(key));
+        let sequence = (mode, keys);
+        if let Some(command) = self.bindings.get(&sequence) {
+            return Lookup::Command(*command);
+        }
+        if self.prefixes.contains(&sequence) {
+            pending.keys = sequence.1;
+            return Lookup::Pending;
+        }
+        if started {
+            return self.lookup(mode, pending, key);
+        }
+        Lookup::Unbound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_parse_and_display_keys() {
+        for text in [
+            "ctrl+q",
+            "alt+enter",
+            "shift+tab",
+            "X",
+            "f5",
+            "+",
+            "ctrl++",
+            "space",
+        ] {
+            let binding: KeyBinding = text.parse().unwrap();
+            assert_eq!(binding.to_string(), text);
+        }
+        assert_eq!("shift+x".parse::<KeyBinding>().unwrap().to_string(), "X");
+        assert_eq!(
+            "Ctrl+PageUp".parse::<KeyBinding>().unwrap().to_string(),
+            "ctrl+pageup"
+        );
+        assert!("hyper+q".parse::<KeyBinding>().is_err());
+        assert!("f13".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn test_config_rebinds_normal_mode() {
+        let mut config = KeybindingsConfig::default();
+        config
+            .normal
+            .insert("quit".to_string(), Keys::One("ctrl+q".to_string()));
+        config
+            .normal
+            .insert("new_session".to_string(), Keys::Many(vec!["q".to_string()]));
+        let keymap = Keymap::from_config(&config).unwrap();
+        let q = press(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert_eq!(keymap.get(Mode::Normal, &q), Some(Command::NewSession));
+        let ctrl_q = press(KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert_eq!(keymap.get(Mode::Normal, &ctrl_q), Some(Command::Quit));
+        assert_eq!(
+            keymap.get(Mode::Normal, &press(KeyCode::Char('t'), KeyModifiers::NONE)),
+            None
+        );
+        let shifted = press(KeyCode::Char('X'), KeyModifiers::SHIFT);
+        assert_eq!(keymap.get(Mode::Normal, &shifted), Some(Command::Export));
+
+        config
+            .normal
+            .insert("teleport".to_string(), Keys::One("z".to_string()));
+        assert_eq!(
+            Keymap::from_config(&config).unwrap_err(),
+            "[keybindings.normal]: unknown command: teleport"
+        );
+    }
+
+    #[test]
+    fn test_conflicts_are_reported() {
+        let conflict = |section: &str, pairs: &[(&str, &str)]| {
+            let mut config = KeybindingsConfig::default();
+            let map = match section {
+                "normal" => &mut config.normal,
+                "editing" => &mut config.editing,
+                _ => &mut config.selecting,
+            };
+            for (name, key) in pairs {
+                map.insert(name.to_string(), Keys::One(key.to_string()));
+            }
+            Keymap::from_config(&config).err()
+        };
+        assert_eq!(
+            conflict("normal", &[("new_session", "q")]).unwrap(),
+            "[keybindings.normal] new_session: q is already bound to quit; rebind quit too"
+        );
+        assert_eq!(
+            conflict("normal", &[("export", "ctrl+e"), ("search", "ctrl+e")]).unwrap(),
+            "[keybindings.normal] search: ctrl+e is also bound to export"
+        );
+        assert_eq!(
+            conflict("editing", &[("send", "s")]).unwrap(),
+            "[keybindings.editing] send: s is typed as text in this mode; add ctrl or alt"
+        );
+        assert_eq!(conflict("editing", &[("send", "ctrl+s")]), None);
+        assert_eq!(conflict("selecting", &[("copy", "c")]), None);
+        assert_eq!(
+            conflict("selecting", &[("quit", "q")]).unwrap(),
+            "[keybindings.selecting]: unknown command: quit"
+        );
+
+        let mut config = KeybindingsConfig::default();
+        config
+            .editing
+            .insert("send".to_string(), Keys::One("ctrl+s".to_string()));
+        config
+            .normal
+            .insert("quit".to_string(), Keys::Many(Vec::new()));
+        let keymap = Keymap::from_config(&config).unwrap();
+        let enter = press(KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(keymap.get(Mode::Editing, &enter), None);
+        assert_eq!(keymap.get(Mode::Normal, &enter), Some(Command::Send));
+        let ctrl_s = press(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        assert_eq!(keymap.get(Mode::Editing, &ctrl_s), Some(Command::Send));
+        let q = press(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert_eq!(keymap.get(Mode::Normal, &q), None);
+        assert_eq!(keymap.get(Mode::Welcome, &q), Some(Command::Quit));
+    }
+
+    #[test]
+    fn test_vim_preset_and_key_sequences() {
+        let mut config = KeybindingsConfig {
+            preset: Preset::Vim,
+            ..KeybindingsConfig::default()
+        };
+        let keymap = Keymap::from_config(&config).unwrap();
+        let mut pending = PendingKeys::default();
+        let mut press_in = |mode, c| {
+            let key = press(KeyCode::Char(c), KeyModifiers::NONE);
+            keymap.lookup(mode, &mut pending, &key)
+        };
+        assert_eq!(
+            press_in(Mode::Normal, 'j'),
+            Lookup::Command(Command::ScrollDown)
+        );
+        assert_eq!(press_in(Mode::Normal, 'g'), Lookup::Pending);
+        assert_eq!(
+            press_in(Mode::Normal, 'g'),
+            Lookup::Command(Command::ScrollHome)
+        );
+        assert_eq!(press_in(Mode::Selecting, 'd'), Lookup::Pending);
+        assert_eq!(
+            press_in(Mode::Selecting, 'j'),
+            Lookup::Command(Command::SelectNext)
+        );
+        assert_eq!(press_in(Mode::Selecting, 'y'), Lookup::Pending);
+        assert_eq!(press_in(Mode::Normal, 'g'), Lookup::Pending);
+        assert_eq!(press_in(Mode::Normal, 'z'), Lookup::Unbound);
+        assert_eq!(press_in(Mode::EditingNormal, 'c'), Lookup::Pending);
+        assert_eq!(press_in(Mode::EditingNormal, 'i'), Lookup::Pending);
+        assert_eq!(
+            press_in(Mode::EditingNormal, 'w'),
+            Lookup::Command(Command::ChangeInnerWord)
+        );
+        let esc = press(KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(keymap.get(Mode::Editing, &esc), Some(Command::NormalMode));
+        assert_eq!(
+            Keymap::default().get(Mode::Editing, &esc),
+            Some(Command::Cancel)
+        );
+
+        config
+            .normal
+            .insert("new_session".to_string(), Keys::One("g".to_string()));
+        assert_eq!(
+            Keymap::from_config(&config).unwrap_err(),
+            "[keybindings.normal] new_session: g would hide g g, which is bound to scroll_home; \
+             rebind scroll_home too"
+        );
+        config.normal.clear();
+        config
+            .selecting
+            .insert("pin".to_string(), Keys::One("y y p".to_string()));
+        assert_eq!(
+            Keymap::from_config(&config).unwrap_err(),
+            "[keybindings.selecting] pin: y y p starts with y y, which is bound to copy"
+        );
+    }
+}
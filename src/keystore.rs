@@ -0,0 +1,492 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+use crate::ai::{Provider, Providers};
+use crate::storage::ConversationStore;
+
+/// Unlocks the key file without asking, for scripts.
+pub const PASSPHRASE_ENV_VAR: &str = "AICHAT_PASSPHRASE";
+const FILE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+
+/// Where a profile gets one provider's API key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    /// The key itself, kept in the encrypted file.
+    Stored(String),
+    /// A command that prints the key, such as `pass show deepseek`, run when the
+    /// profile is put to use.
+    Command(String),
+}
+
+impl KeySource {
+    pub fn resolve(&self) -> Result<String, String> {
+        match self {
+            KeySource::Stored(key) => Ok(key.clone()),
+            KeySource::Command(command) => run_key_command(command),
+        }
+    }
+}
+
+/// The first line the command prints, trimmed.
+fn run_key_command(command: &str) -> Result<String, String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .map_err(|e| format!("cannot run {}: {}", command, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.lines().find(|line| !line.trim().is_empty()) {
+            Some(line) => format!("{} failed: {}", command, line.trim()),
+            None => format!("{} failed with {}", command, output.status),
+        });
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| format!("{} printed no key", command))
+}
+
+/// `sk-••••abcd`: enough of a key to tell it apart from another. Keys shorter than
+/// 16 characters would show most of themselves that way, so they are all bullets.
+pub fn mask(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() < 16 {
+        return "••••".to_string();
+    }
+    let start: String = chars[..3].iter().collect();
+    let end: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}••••{}", start, end)
+}
+
+/// The keys of one profile by provider.
+pub type Profile = BTreeMap<Provider, KeySource>;
+
+/// What the key file holds once decrypted: named profiles, such as `personal` and
+/// `work`, and the one in use.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keyring {
+    pub active: String,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Default for Keyring {
+    fn default() -> Self {
+        Self {
+            active: "default".to_string(),
+            profiles: BTreeMap::from([("default".to_string(), Profile::new())]),
+        }
+    }
+}
+
+impl Keyring {
+    pub fn active_profile(&self) -> Option<&Profile> {
+        self.profiles.get(&self.active)
+    }
+
+    /// Gives each provider the active profile's key for it, running key commands
+    /// now; providers without one go back to their environment variable. Returns
+    /// the keys that could not be had.
+    pub fn apply(&self, providers: &mut Providers) -> BTreeMap<Provider, String> {
+        let mut errors = BTreeMap::new();
+        for provider in Provider::ALL {
+            let settings = providers.get_mut(provider);
+            settings.api_key = None;
+            let Some(source) = self.active_profile().and_then(|p| p.get(&provider)) else {
+                continue;
+            };
+            match source.resolve() {
+                Ok(key) => settings.api_key = Some(key),
+                Err(e) => {
+                    errors.insert(provider, e);
+                }
+            }
+        }
+        errors
+    }
+}
+
+#[derive(Debug)]
+pub enum KeyStoreError {
+    Io(PathBuf, io::Error),
+    /// The passphrase does not open the file, or the file was tampered with.
+    WrongPassphrase(PathBuf),
+    Damaged(PathBuf, String),
+    /// Saving before a passphrase was chosen.
+    NoPassphrase,
+}
+
+impl fmt::Display for KeyStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyStoreError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            KeyStoreError::WrongPassphrase(path) => {
+                write!(f, "wrong passphrase for {}", path.display())
+            }
+            KeyStoreError::Damaged(path, message) => {
+                write!(f, "damaged key file {}: {}", path.display(), message)
+            }
+            KeyStoreError::NoPassphrase => f.write_str("no passphrase for the key file"),
+        }
+    }
+}
+
+impl std::error::Error for KeyStoreError {}
+
+impl From<KeyStoreError> for io::Error {
+    fn from(e: KeyStoreError) -> Self {
+        match e {
+            KeyStoreError::Io(_, inner) => inner,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// The key file on disk; everything but the version is base64.
+#[derive(Debug, Serialize, Deserialize)]
+struct SealedFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305, String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+/// `keys.json` next to the conversations directory: the keyring encrypted with
+/// XChaCha20-Poly1305 under a key derived from the passphrase with Argon2id.
+#[derive(Debug)]
+pub struct KeyStore {
+    path: PathBuf,
+    passphrase: Option<String>,
+    pub keyring: Keyring,
+}
+
+impl KeyStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            passphrase: None,
+            keyring: Keyring::default(),
+        }
+    }
+
+    pub fn open_default() -> Option<Self> {
+        ConversationStore::data_dir().map(|dir| Self::new(dir.join("keys.json")))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Whether saving can go ahead without asking for a passphrase first.
+    pub fn has_passphrase(&self) -> bool {
+        self.passphrase.is_some()
+    }
+
+    /// The passphrase later saves encrypt with, for a new file.
+    pub fn set_passphrase(&mut self, passphrase: String) {
+        self.passphrase = Some(passphrase);
+    }
+
+    /// Decrypts the file with `passphrase`, which is kept for saving.
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), KeyStoreError> {
+        let damaged = |message: String| KeyStoreError::Damaged(self.path.clone(), message);
+        let text =
+            fs::read_to_string(&self.path).map_err(|e| KeyStoreError::Io(self.path.clone(), e))?;
+        let file: SealedFile = serde_json::from_str(&text).map_err(|e| damaged(e.to_string()))?;
+        if file.version != FILE_VERSION {
+            return Err(damaged(format!("unknown version {}", file.version)));
+        }
+        let decode = |field: &str, value: &str| {
+            STANDARD
+                .decode(value)
+                .map_err(|e| damaged(format!("{}: {}", field, e)))
+        };
+        let salt = decode("salt", &file.salt)?;
+        let nonce = decode("nonce", &file.nonce)?;
+        if nonce.len() != 24 {
+            return Err(damaged("nonce: wrong length".to_string()));
+        }
+        let plaintext = cipher(passphrase, &salt)
+            .map_err(damaged)?
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                &*decode("ciphertext", &file.ciphertext)?,
+            )
+            .map_err(|_| KeyStoreError::WrongPassphrase(self.path.clone()))?;
+        self.keyring = serde_json::from_slice(&plaintext).map_err(|e| damaged(e.to_string()))?;
+        self.passphrase = Some(passphrase.to_string());
+        Ok(())
+    }
+
+    /// Encrypts the keyring with a fresh salt and nonce and replaces the file, which
+    /// only its owner can read.
+    pub fn save(&self) -> Result<(), KeyStoreError> {
+        let passphrase = self
+            .passphrase
+            .as_ref()
+            .ok_or(KeyStoreError::NoPassphrase)?;
+        let io_error = |e: io::Error| KeyStoreError::Io(self.path.clone(), e);
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(&self.keyring)
+            .map_err(|e| io_error(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        let ciphertext = cipher(passphrase, &salt)
+            .and_then(|cipher| {
+                cipher
+                    .encrypt(&nonce, plaintext.as_slice())
+                    .map_err(|e| e.to_string())
+            })
+            .map_err(|e| KeyStoreError::Damaged(self.path.clone(), e))?;
+        let file = SealedFile {
+            version: FILE_VERSION,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        let json = serde_json::to_string_pretty(&file)
+            .map_err(|e| io_error(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut out = options.open(&tmp).map_err(io_error)?;
+        out.write_all(json.as_bytes()).map_err(io_error)?;
+        drop(out);
+        fs::rename(&tmp, &self.path).map_err(io_error)
+    }
+}
+
+/// What is being typed on the key screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEntry {
+    Key,
+    Command,
+    Profile,
+    Passphrase,
+}
+
+/// The key screen: the profile and provider looked at, and what is being typed.
+#[derive(Debug, Clone, Default)]
+pub struct KeysScreen {
+    /// The profile shown, which need not be the one in use.
+    pub profile: String,
+    pub selected: usize,
+    pub entry: Option<KeyEntry>,
+    pub input: String,
+    /// Why the profile in use gave no key for a provider, as of when it was applied.
+    pub errors: BTreeMap<Provider, String>,
+}
+
+impl KeysScreen {
+    pub fn provider(&self) -> Provider {
+        Provider::ALL[self.selected.min(Provider::ALL.len() - 1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::KeyProblem;
+    use crate::i18n::{Language, Translations};
+    use crate::testing::temp_path;
+
+    const KEY: &str = "sk-0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_key_file_is_encrypted_and_needs_the_passphrase() {
+        let path = temp_path("keys.json");
+        let mut store = KeyStore::new(&path);
+        assert!(matches!(store.save(), Err(KeyStoreError::NoPassphrase)));
+        store.set_passphrase("correct horse".to_string());
+        store
+            .keyring
+            .profiles
+            .get_mut("default")
+            .unwrap()
+            .insert(Provider::DeepSeek, KeySource::Stored(KEY.to_string()));
+        store.keyring.profiles.insert(
+            "work".to_string(),
+            Profile::from([(
+                Provider::AliYun,
+                KeySource::Command("pass show aliyun".to_string()),
+            )]),
+        );
+        store.save().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(
+            !text.contains(KEY) && !text.contains("pass show"),
+            "{}",
+            text
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let mut reopened = KeyStore::new(&path);
+        let error = reopened.unlock("wrong horse").unwrap_err();
+        assert!(
+            matches!(error, KeyStoreError::WrongPassphrase(_)),
+            "{}",
+            error
+        );
+        reopened.unlock("correct horse").unwrap();
+        assert_eq!(reopened.keyring, store.keyring);
+        assert!(reopened.has_passphrase());
+
+        fs::write(&path, text.replacen("\"version\": 1", "\"version\": 9", 1)).unwrap();
+        assert_eq!(
+            KeyStore::new(&path)
+                .unlock("correct horse")
+                .unwrap_err()
+                .to_string(),
+            format!("damaged key file {}: unknown version 9", path.display())
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_profiles_apply_keys_and_commands() {
+        let mut keyring = Keyring::default();
+        keyring.profiles.insert(
+            "work".to_string(),
+            Profile::from([
+                (
+                    Provider::DeepSeek,
+                    KeySource::Command(format!("echo {}", KEY)),
+                ),
+                (Provider::AliYun, KeySource::Command("exit 3".to_string())),
+            ]),
+        );
+        keyring.active = "work".to_string();
+        let mut providers = Providers::default();
+        providers.aliyun.api_key = Some("sk-old".to_string());
+        let errors = keyring.apply(&mut providers);
+        assert_eq!(providers.deepseek.api_key.as_deref(), Some(KEY));
+        assert_eq!(providers.aliyun.api_key, None);
+        assert_eq!(
+            errors.get(&Provider::AliYun).map(String::as_str),
+            Some("exit 3 failed with exit status: 3")
+        );
+        keyring.active = "default".to_string();
+        assert!(keyring.apply(&mut providers).is_empty());
+        assert_eq!(providers.deepseek.api_key, None);
+    }
+
+    #[test]
+    fn test_deepseek_key_check() {
+        let deepseek = Provider::DeepSeek;
+        assert_eq!(deepseek.check_key(KEY), Ok(()));
+        assert_eq!(
+            deepseek.check_key("0123456789abcdef0123456789abcdef"),
+            Err(KeyProblem::Prefix(deepseek))
+        );
+        assert_eq!(
+            deepseek.check_key("sk-123"),
+            Err(KeyProblem::Length(deepseek, 3))
+        );
+        assert_eq!(
+            deepseek.check_key(&format!("{}0", KEY)),
+            Err(KeyProblem::Length(deepseek, 33))
+        );
+        assert_eq!(
+            deepseek.check_key("sk-0123456789abcdef0123456789abcdeg"),
+            Err(KeyProblem::Charset(deepseek))
+        );
+        assert_eq!(
+            deepseek.check_key("sk-0123456789 abcdef0123"),
+            Err(KeyProblem::Whitespace)
+        );
+    }
+
+    #[test]
+    fn test_aliyun_key_check() {
+        let aliyun = Provider::AliYun;
+        assert_eq!(aliyun.check_key(KEY), Ok(()));
+        assert_eq!(
+            aliyun.check_key("sk-0123456789abcdefABCDEF0123456789abcdef"),
+            Ok(())
+        );
+        assert_eq!(
+            aliyun.check_key("0123456789abcdef0123456789abcdef"),
+            Err(KeyProblem::Prefix(aliyun))
+        );
+        assert_eq!(
+            aliyun.check_key("sk-0123456789abcdef"),
+            Err(KeyProblem::Length(aliyun, 16))
+        );
+        assert_eq!(
+            aliyun.check_key("sk-0123456789abcdef0123456789abcde_"),
+            Err(KeyProblem::Charset(aliyun))
+        );
+        assert_eq!(aliyun.check_key(""), Err(KeyProblem::Empty));
+    }
+
+    #[test]
+    fn test_key_problems_are_localized() {
+        let english = Translations::new(Language::English);
+        let chinese = Translations::new(Language::Chinese);
+        let problem = KeyProblem::Length(Provider::DeepSeek, 3);
+        assert_eq!(
+            problem.localized(&english),
+            "deepseek keys have 32 characters after sk-, not 3"
+        );
+        assert_eq!(
+            problem.localized(&chinese),
+            "deepseek 的密钥在 sk- 之后有 32 个字符，而不是 3 个"
+        );
+        assert_eq!(
+            KeyProblem::Length(Provider::AliYun, 16).localized(&english),
+            "aliyun keys have at least 32 characters after sk-"
+        );
+        assert_eq!(
+            KeyProblem::Charset(Provider::AliYun).localized(&chinese),
+            "aliyun 的密钥在 sk- 之后只有字母和数字"
+        );
+    }
+
+    #[test]
+    fn test_key_masking() {
+        assert_eq!(mask(KEY), "sk-••••cdef");
+        assert_eq!(mask("sk-0123456789a"), "••••");
+        assert_eq!(mask("short"), "••••");
+        assert_eq!(mask(""), "••••");
+        assert_eq!(mask("sk-0123456789abc"), "sk-••••9abc");
+    }
+}
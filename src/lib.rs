@@ -0,0 +1,25 @@
+pub mod ai;
+pub mod ai_models;
+pub mod app;
+pub mod batch;
+pub mod cli;
+pub mod clipboard;
+pub mod config;
+pub mod events;
+pub mod export;
+pub mod i18n;
+pub mod import;
+pub mod journal;
+pub mod keymap;
+pub mod keystore;
+pub mod motion;
+pub mod oneshot;
+pub mod prefs;
+pub mod search;
+pub mod session;
+pub mod storage;
+#[cfg(test)]
+mod testing;
+pub mod theme;
+pub mod title;
+pub mod ui;
@@ -1,31 +1,118 @@
-mod ai;
-mod ai_models;
-mod app;
-mod events;
-mod i18n;
-mod ui;
-
-use app::App;
+use aichat::ai::cassette;
+use aichat::app::App;
+use aichat::cli::{self, Cli, Command};
+use aichat::journal::JournalFile;
+use aichat::prefs::PreferencesFile;
+use aichat::storage::ConversationStore;
+use aichat::theme::{ColorDepth, ThemeRegistry};
+use aichat::{events, ui};
+use clap::Parser;
 use crossterm::{
+    cursor,
     event::{self, DisableMouseCapture, EnableMouseCapture, Event},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{Terminal, backend::CrosstermBackend};
 use std::{
-    io,
+    io::{self, IsTerminal},
     time::{Duration, Instant},
 };
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
+    // Keys in a .env file still work, read once, behind the key store.
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    let store = ConversationStore::open_default();
+    if cli.list {
+        return cli::list_conversations(store.as_ref());
+    }
+    if let Some(command) = &cli.command {
+        let result = match command {
+            Command::Export(args) => cli::export(store.as_ref(), args),
+            Command::Import(args) => cli::import(store.as_ref(), args),
+            Command::Batch(args) => cli::batch(&cli, args).await,
+        };
+        if let Err(e) = result {
+            eprintln!("aichat: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let config = cli::load_config(&cli).unwrap_or_else(|e| {
+        eprintln!("aichat: {}", e);
+        std::process::exit(1);
+    });
+    let piped = if cli.reads_stdin(io::stdin().is_terminal()) {
+        Some(io::read_to_string(io::stdin())?)
+    } else {
+        None
+    };
+    if let Some(question) = cli.question(piped.as_deref()) {
+        if let Err(e) = cli::ask(&cli, question, &config, store.as_ref()).await {
+            eprintln!("aichat: {}", e);
+            std::process::exit(e.exit_code());
+        }
+        return Ok(());
+    }
+    if piped.is_some() {
+        eprintln!("aichat: nothing to ask: stdin is empty and no prompt was given");
+        std::process::exit(2);
+    }
+    let mut app = App::new();
+    app.themes = ThemeRegistry::load_default()
+        .unwrap_or_else(|e| {
+            eprintln!("aichat: {}", e);
+            std::process::exit(1);
+        })
+        .with_color_depth(ColorDepth::detect());
+    if let Some(preferences) = PreferencesFile::open_default() {
+        app.restore_preferences(preferences);
+    }
+    if let Err(e) = app.apply_config(&config) {
+        eprintln!("aichat: {}", e);
+        std::process::exit(1);
+    }
+    match cli::open_keys(cli.profile.as_deref()) {
+        Ok(Some(keys)) => app.open_key_store(keys),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("aichat: {}", e);
+            std::process::exit(1);
+        }
+    }
+    app.store = store;
+    if let Some(journal) = JournalFile::open_default() {
+        app.open_journal(journal);
+    }
+    match cli.resume_id() {
+        Some("last") => app.resume_latest(),
+        Some(id) => match app.store.as_ref().map(|store| store.load(id)) {
+            Some(Ok(conversation)) => app.resume_conversation(conversation),
+            Some(Err(e)) => {
+                eprintln!("aichat: cannot resume conversation {}: {}", id, e);
+                std::process::exit(1);
+            }
+            None => {
+                eprintln!("aichat: no data directory to resume conversations from");
+                std::process::exit(1);
+            }
+        },
+        None => {}
+    }
+    let _cassette = cassette::attach_from_env(&mut app.providers).await?;
+    install_panic_hook();
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
-    let mut app = App::new();
     let res = run_app(&mut terminal, &mut app);
+    app.autosave();
+    if res.is_ok() {
+        app.close_journal();
+    }
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
@@ -36,6 +123,23 @@ async fn main() -> io::Result<()> {
     res
 }
 
+/// Puts the terminal back before the panic message is printed, then exits: a panic in
+/// a reply task can poison the locks the UI needs. The journal keeps the sessions.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            cursor::Show
+        );
+        default_hook(info);
+        std::process::exit(101);
+    }));
+}
+
 fn run_app<B: ratatui::backend::Backend>(
     terminal: &mut Terminal<B>,
     app: &mut App,
@@ -46,13 +150,15 @@ fn run_app<B: ratatui::backend::Backend>(
             app.update_cursor_blink();
             last_blink_update = Instant::now();
         }
+        app.generate_titles();
+        app.autosave();
+        app.reload_themes();
         terminal.draw(|f| ui::render(app, f))?;
-        if event::poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                if events::handle_key_event(key, app) {
-                    return Ok(());
-                }
-            }
+        if event::poll(Duration::from_millis(100))?
+            && let Event::Key(key) = event::read()?
+            && events::handle_key_event(key, app)
+        {
+            return Ok(());
         }
     }
 }
@@ -0,0 +1,151 @@
+//! Cursor motions over the input buffer for the vim preset. Positions are byte
+//! offsets on character boundaries; in normal mode the cursor sits on a character,
+//! so motions stop at the last character of a line rather than after it.
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> Class {
+    if c.is_whitespace() {
+        Class::Space
+    } else if c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else {
+        Class::Punctuation
+    }
+}
+
+fn char_at(text: &str, at: usize) -> Option<char> {
+    text[at..].chars().next()
+}
+
+pub fn next_char(text: &str, at: usize) -> usize {
+    char_at(text, at).map_or(at, |c| at + c.len_utf8())
+}
+
+pub fn previous_char(text: &str, at: usize) -> usize {
+    text[..at]
+        .chars()
+        .next_back()
+        .map_or(at, |c| at - c.len_utf8())
+}
+
+pub fn line_start(text: &str, at: usize) -> usize {
+    text[..at].rfind('\n').map_or(0, |newline| newline + 1)
+}
+
+/// The end of the line: its newline, or the end of the text.
+pub fn line_end(text: &str, at: usize) -> usize {
+    text[at..]
+        .find('\n')
+        .map_or(text.len(), |newline| at + newline)
+}
+
+/// `at` moved back onto the last character of its line if it is past it, as normal
+/// mode needs.
+pub fn on_char(text: &str, at: usize) -> usize {
+    let at = at.min(text.len());
+    let end = line_end(text, at);
+    if at < end || at == line_start(text, at) {
+        at
+    } else {
+        previous_char(text, end)
+    }
+}
+
+/// `w`: the start of the next word, where a word is a run of letters, digits and
+/// underscores or a run of other non-blank characters.
+pub fn word_forward(text: &str, at: usize) -> usize {
+    let mut chars = text[at..]
+        .char_indices()
+        .map(|(i, c)| (at + i, c))
+        .peekable();
+    if let Some(&(_, first)) = chars.peek() {
+        let start = class(first);
+        if start != Class::Space {
+            while chars.next_if(|&(_, c)| class(c) == start).is_some() {}
+        }
+    }
+    while chars.next_if(|&(_, c)| class(c) == Class::Space).is_some() {}
+    chars.peek().map_or(text.len(), |&(i, _)| i)
+}
+
+/// `b`: the start of this word, or of the previous one when already at a start.
+pub fn word_backward(text: &str, at: usize) -> usize {
+    let mut chars = text[..at].char_indices().rev().peekable();
+    while chars.next_if(|&(_, c)| class(c) == Class::Space).is_some() {}
+    let Some(&(mut start, first)) = chars.peek() else {
+        return 0;
+    };
+    let word = class(first);
+    while let Some((i, _)) = chars.next_if(|&(_, c)| class(c) == word) {
+        start = i;
+    }
+    start
+}
+
+/// `iw`: the word, run of punctuation or run of blanks under the cursor, within
+/// its line.
+pub fn inner_word(text: &str, at: usize) -> Range<usize> {
+    let at = on_char(text, at);
+    let Some(under) = char_at(text, at).filter(|c| *c != '\n') else {
+        return at..at;
+    };
+    let same = |c: char| c != '\n' && class(c) == class(under);
+    let start = text[..at]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| same(c))
+        .last()
+        .map_or(at, |(i, _)| i);
+    let end = text[at..]
+        .char_indices()
+        .find(|&(_, c)| !same(c))
+        .map_or(text.len(), |(i, _)| at + i);
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_motions() {
+        let text = "let x = foo.bar(1);\n  next";
+        assert_eq!(word_forward(text, 0), 4);
+        assert_eq!(word_forward(text, 4), 6);
+        assert_eq!(word_forward(text, 8), 11);
+        assert_eq!(word_forward(text, 11), 12);
+        assert_eq!(word_forward(text, 16), 17);
+        assert_eq!(word_forward(text, 17), 22);
+        assert_eq!(word_forward(text, 22), text.len());
+        assert_eq!(word_backward(text, 22), 17);
+        assert_eq!(word_backward(text, 14), 12);
+        assert_eq!(word_backward(text, 12), 11);
+        assert_eq!(word_backward(text, 2), 0);
+        assert_eq!(word_forward("你好 世界", 0), "你好 ".len());
+    }
+
+    #[test]
+    fn test_line_motions_and_inner_word() {
+        let text = "one two\nthree";
+        assert_eq!(line_start(text, 10), 8);
+        assert_eq!(line_end(text, 2), 7);
+        assert_eq!(line_end(text, 10), text.len());
+        assert_eq!(on_char(text, 7), 6);
+        assert_eq!(on_char(text, text.len()), text.len() - 1);
+        assert_eq!(on_char("a\n\nb", 2), 2);
+        assert_eq!(on_char("", 0), 0);
+        assert_eq!(inner_word(text, 5), 4..7);
+        assert_eq!(inner_word(text, 3), 3..4);
+        assert_eq!(inner_word(text, 9), 8..13);
+        assert_eq!(inner_word("f(x)", 1), 1..2);
+        assert_eq!(inner_word("", 0), 0..0);
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+use std::io;
+
+use chrono::Local;
+
+use crate::ai::deepseek::create_system_message;
+use crate::ai::error::ProviderError;
+use crate::ai::traffic::TrafficLog;
+use crate::ai::{Prompt, Providers, stream_reply};
+use crate::ai_models::AIModel;
+use crate::app::{Message, Sender};
+use crate::i18n::Language;
+use crate::session::context;
+use crate::storage::{Conversation, TitleSource};
+
+/// One question asked from the command line, answered on stdout without the TUI.
+#[derive(Debug, Clone)]
+pub struct Ask {
+    pub model: AIModel,
+    pub input: String,
+    /// Sent as a system message before the history.
+    pub system: Option<String>,
+    pub temperature: Option<f32>,
+    /// The language Qwen models are told to answer in; none unless one was chosen.
+    pub language: Option<Language>,
+}
+
+#[derive(Debug)]
+pub enum AskError {
+    /// The model only has a simulated reply in the chat screen.
+    NoApi(AIModel),
+    Provider(ProviderError),
+    /// Reading or saving the conversation failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for AskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AskError::NoApi(model) => write!(
+                f,
+                "{} has no API to call; use deepseek or a qwen model",
+                model
+            ),
+            AskError::Provider(e) => write!(f, "{}", e),
+            AskError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for AskError {}
+
+impl AskError {
+    /// The process exit code, so scripts can tell failures apart: 2 for a model that
+    /// cannot be asked, 3-9 for the kinds of provider error, 1 for anything else.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AskError::NoApi(_) => 2,
+            AskError::Provider(ProviderError::AuthFailed(_)) => 3,
+            AskError::Provider(ProviderError::RateLimited(_)) => 4,
+            AskError::Provider(ProviderError::QuotaExhausted(_)) => 5,
+            AskError::Provider(ProviderError::ContextTooLong(_)) => 6,
+            AskError::Provider(ProviderError::ContentFiltered(_)) => 7,
+            AskError::Provider(ProviderError::Network(_)) => 8,
+            AskError::Provider(ProviderError::BadResponse(_)) => 9,
+            AskError::Io(_) => 1,
+        }
+    }
+}
+
+impl From<io::Error> for AskError {
+    fn from(e: io::Error) -> Self {
+        AskError::Io(e)
+    }
+}
+
+/// `instruction` with `attachment`, e.g. piped input, below it in a fenced block
+/// longer than any fence inside the attachment, so the model sees where it ends.
+pub fn attach(instruction: &str, attachment: &str) -> String {
+    let mut fence = "```".to_string();
+    while attachment.contains(&fence) {
+        fence.push('`');
+    }
+    format!(
+        "{}\n\n{}\n{}\n{}",
+        instruction,
+        fence,
+        attachment.trim_end_matches('\n'),
+        fence
+    )
+}
+
+impl Ask {
+    /// The request for the reply: the system prompt, if any, then the context of
+    /// `messages` as the chat screen would send it.
+    pub fn prompt(&self, messages: &[Message]) -> Prompt {
+        let mut history: Vec<_> = self
+            .system
+            .as_deref()
+            .map(create_system_message)
+            .into_iter()
+            .collect();
+        history.extend(context(messages));
+        Prompt {
+            messages: history,
+            temperature: self.temperature,
+        }
+    }
+
+    /// Adds the question to `conversation`, or to a new one, streams the reply to
+    /// `on_chunk` and returns the conversation with the reply added.
+    pub async fn run<F>(
+        &self,
+        conversation: Option<Conversation>,
+        providers: &Providers,
+        traffic: Option<&TrafficLog>,
+        on_chunk: F,
+    ) -> Result<Conversation, AskError>
+    where
+        F: FnMut(String) + Send + 'static,
+    {
+        let now = Local::now();
+        let mut conversation = conversation.unwrap_or_else(|| Conversation {
+            id: Conversation::new_id(),
+            title: String::new(),
+            title_source: None,
+            model: self.model.clone(),
+            persona: None,
+            created_at: now,
+            updated_at: now,
+            messages: Vec::new(),
+        });
+        conversation.messages.push(Message {
+            timestamp: now,
+            ..Message::new(self.input.clone(), Sender::User)
+        });
+        let prompt = self.prompt(&conversation.messages);
+        let reply = stream_reply(
+            &self.model,
+            &prompt,
+            self.language,
+            providers,
+            traffic,
+            |_| {},
+            on_chunk,
+        )
+        .await
+        .ok_or_else(|| AskError::NoApi(self.model.clone()))?
+        .map_err(AskError::Provider)?;
+        conversation
+            .messages
+            .push(Message::new(reply, Sender::AI(self.model.clone())));
+        if conversation.title.is_empty() {
+            conversation.title = Conversation::fallback_title(&conversation.messages)
+                .unwrap_or_else(|| Conversation::shorten_title(&self.input));
+            conversation.title_source = Some(TitleSource::Fallback);
+        }
+        conversation.model = self.model.clone();
+        conversation.updated_at = Local::now();
+        Ok(conversation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::mock::{MockReply, MockServer};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_attachment_fence_outlasts_inner_fences() {
+        assert_eq!(
+            attach("write a commit message", "+fn main() {}\n"),
+            "write a commit message\n\n```\n+fn main() {}\n```"
+        );
+        assert_eq!(
+            attach("review", "```rust\nx\n```"),
+            "review\n\n````\n```rust\nx\n```\n````"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_continues_conversation_and_streams_reply() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["Second ", "answer"]));
+        let ask = Ask {
+            model: AIModel::DeepSeek,
+            input: "And in Go?".to_string(),
+            system: Some("Be brief.".to_string()),
+            temperature: Some(0.5),
+            language: Some(Language::English),
+        };
+        let now = Local::now();
+        let earlier = Conversation {
+            id: "a".to_string(),
+            title: "Hello world".to_string(),
+            title_source: Some(TitleSource::Manual),
+            model: AIModel::DeepSeek,
+            persona: None,
+            created_at: now,
+            updated_at: now,
+            messages: vec![
+                Message::new("Hello world in Rust?", Sender::User),
+                Message::new("println!", Sender::AI(AIModel::DeepSeek)),
+            ],
+        };
+        let streamed = Arc::new(Mutex::new(Vec::new()));
+        let chunks = Arc::clone(&streamed);
+        let conversation = ask
+            .run(Some(earlier), &server.providers(), None, move |chunk| {
+                chunks.lock().unwrap().push(chunk)
+            })
+            .await
+            .unwrap();
+        assert_eq!(*streamed.lock().unwrap(), vec!["Second ", "answer"]);
+        assert_eq!(conversation.title, "Hello world");
+        assert_eq!(conversation.messages.len(), 4);
+        assert_eq!(conversation.messages[3].content, "Second answer");
+        let request = server.requests()[0].json();
+        assert_eq!(request["temperature"], 0.5);
+        let messages = request["messages"].as_array().unwrap();
+        let contents: Vec<_> = messages.iter().map(|m| m["content"].clone()).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "Be brief.",
+                "Hello world in Rust?",
+                "println!",
+                "And in Go?"
+            ]
+        );
+
+        let ask = Ask {
+            model: AIModel::OpenAI,
+            ..ask
+        };
+        let error = ask
+            .run(None, &server.providers(), None, |_| {})
+            .await
+            .unwrap_err();
+        assert!(matches!(error, AskError::NoApi(AIModel::OpenAI)));
+    }
+
+    #[tokio::test]
+    async fn test_qwen_gets_no_language_instruction_unless_chosen() {
+        let server = MockServer::start().await.unwrap();
+        server.enqueue(MockReply::stream(&["4"]));
+        server.enqueue(MockReply::stream(&["四"]));
+        let ask = Ask {
+            model: AIModel::AliYun(crate::ai::aliyun::AliYunModelType::QwenPlus),
+            input: "2 + 2?".to_string(),
+            system: None,
+            temperature: None,
+            language: None,
+        };
+        ask.run(None, &server.providers(), None, |_| {})
+            .await
+            .unwrap();
+        let chinese = Ask {
+            language: Some(Language::Chinese),
+            ..ask
+        };
+        chinese
+            .run(None, &server.providers(), None, |_| {})
+            .await
+            .unwrap();
+        let requests = server.requests();
+        let messages = requests[0].json()["messages"].clone();
+        assert_eq!(
+            messages,
+            serde_json::json!([{ "role": "user", "content": "2 + 2?" }])
+        );
+        let messages = requests[1].json()["messages"].clone();
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[0]["content"], "请使用中文回答。");
+    }
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ai_models::AIModel;
+use crate::i18n::Language;
+use crate::storage::ConversationStore;
+
+/// What the app remembers between runs on its own, as opposed to the config the user
+/// edits: the choices made in the UI last time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub language: Option<Language>,
+    /// The theme by name, so adding theme files does not change it.
+    pub theme: Option<String>,
+    pub model: Option<AIModel>,
+    /// Whether the help has been shown once, so it opens by itself only on the first
+    /// run.
+    pub help_seen: bool,
+}
+
+/// `preferences.json` next to the conversations directory, rewritten whenever the
+/// preferences change.
+#[derive(Debug)]
+pub struct PreferencesFile {
+    path: PathBuf,
+    saved: Option<Preferences>,
+}
+
+impl PreferencesFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            saved: None,
+        }
+    }
+
+    pub fn open_default() -> Option<Self> {
+        ConversationStore::data_dir().map(|dir| Self::new(dir.join("preferences.json")))
+    }
+
+    /// The saved preferences, or the defaults before the first save.
+    pub fn load(&mut self) -> io::Result<Preferences> {
+        let preferences = match fs::read_to_string(&self.path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Preferences::default(),
+            Err(e) => return Err(e),
+        };
+        self.saved = Some(preferences.clone());
+        Ok(preferences)
+    }
+
+    /// Writes `preferences` unless they are what was loaded or last saved. A failed
+    /// write is not retried until they change again, so it is reported once.
+    pub fn save_if_changed(&mut self, preferences: &Preferences) -> io::Result<()> {
+        if self.saved.as_ref() == Some(preferences) {
+            return Ok(());
+        }
+        self.saved = Some(preferences.clone());
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(preferences)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    #[test]
+    fn test_preferences_round_trip() {
+        let path = temp_path("preferences.json");
+        let mut file = PreferencesFile::new(&path);
+        assert_eq!(file.load().unwrap(), Preferences::default());
+        file.save_if_changed(&Preferences::default()).unwrap();
+        assert!(!path.exists());
+
+        let preferences = Preferences {
+            language: Some(Language::English),
+            theme: Some("solarized-light".to_string()),
+            model: Some(AIModel::Claude),
+            help_seen: true,
+        };
+        file.save_if_changed(&preferences).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        assert!(json.contains("\"language\": \"en\""), "{}", json);
+        assert_eq!(PreferencesFile::new(&path).load().unwrap(), preferences);
+
+        fs::write(&path, "{\"theme\": \"neon\", \"unknown\": 1}").unwrap();
+        assert_eq!(
+            PreferencesFile::new(&path).load().unwrap(),
+            Preferences {
+                theme: Some("neon".to_string()),
+                ..Preferences::default()
+            }
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
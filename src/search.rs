@@ -0,0 +1,466 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use chrono::{DateTime, Duration, Local};
+
+use crate::ai_models::AIModel;
+use crate::app::Sender;
+use crate::storage::{Conversation, ConversationStore};
+
+const SNIPPET_CONTEXT_CHARS: usize = 30;
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF)
+}
+
+/// Lowercased words; CJK text has no spaces, so every CJK character is a term of its own.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                terms.push(std::mem::take(&mut word));
+            }
+            terms.push(c.to_string());
+        } else if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        } else if !word.is_empty() {
+            terms.push(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() {
+        terms.push(word);
+    }
+    terms
+}
+
+fn chars_equal_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Byte ranges in `text` where any of `terms` occurs, case-insensitively, sorted and
+/// merged so they can be styled in one pass.
+pub fn match_ranges(text: &str, terms: &[String]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (start, _) in text.char_indices() {
+        for term in terms {
+            let mut rest = text[start..].char_indices();
+            let mut end = start;
+            let matched = term.chars().all(|t| match rest.next() {
+                Some((offset, c)) if chars_equal_ignore_case(c, t) => {
+                    end = start + offset + c.len_utf8();
+                    true
+                }
+                _ => false,
+            });
+            if matched && end > start {
+                ranges.push(start..end);
+            }
+        }
+    }
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SenderFilter {
+    Any,
+    User,
+    AI,
+}
+
+impl SenderFilter {
+    pub fn next(self) -> Self {
+        match self {
+            SenderFilter::Any => SenderFilter::User,
+            SenderFilter::User => SenderFilter::AI,
+            SenderFilter::AI => SenderFilter::Any,
+        }
+    }
+
+    fn matches(self, sender: &Sender) -> bool {
+        match self {
+            SenderFilter::Any => true,
+            SenderFilter::User => *sender == Sender::User,
+            SenderFilter::AI => matches!(sender, Sender::AI(_)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFilter {
+    Any,
+    Today,
+    Week,
+    Month,
+}
+
+impl DateFilter {
+    pub fn next(self) -> Self {
+        match self {
+            DateFilter::Any => DateFilter::Today,
+            DateFilter::Today => DateFilter::Week,
+            DateFilter::Week => DateFilter::Month,
+            DateFilter::Month => DateFilter::Any,
+        }
+    }
+
+    fn since(self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            DateFilter::Any => None,
+            DateFilter::Today => now
+                .date_naive()
+                .and_hms_opt(0, 0, 0)
+                .and_then(|midnight| midnight.and_local_timezone(Local).earliest()),
+            DateFilter::Week => Some(now - Duration::days(7)),
+            DateFilter::Month => Some(now - Duration::days(30)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchFilter {
+    pub model: Option<AIModel>,
+    pub sender: SenderFilter,
+    pub date: DateFilter,
+}
+
+impl Default for SearchFilter {
+    fn default() -> Self {
+        Self {
+            model: None,
+            sender: SenderFilter::Any,
+            date: DateFilter::Any,
+        }
+    }
+}
+
+/// One searchable message. User messages are attributed to the conversation's model so
+/// the model filter keeps both sides of a conversation.
+#[derive(Debug, Clone)]
+struct Document {
+    conversation_id: String,
+    title: String,
+    message_index: usize,
+    sender: Sender,
+    model: AIModel,
+    timestamp: DateTime<Local>,
+    content: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub conversation_id: String,
+    pub title: String,
+    pub message_index: usize,
+    pub sender: Sender,
+    pub timestamp: DateTime<Local>,
+    pub snippet: String,
+}
+
+/// Inverted index from term to the messages containing it. Query terms match as
+/// prefixes, so results appear while a word is still being typed.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    documents: Vec<Document>,
+    postings: BTreeMap<String, BTreeSet<usize>>,
+    /// `(id, updated_at)` of every indexed conversation, to skip rebuilding when
+    /// nothing was saved since.
+    version: Vec<(String, DateTime<Local>)>,
+}
+
+impl SearchIndex {
+    pub fn build(conversations: &[Conversation]) -> Self {
+        let mut index = SearchIndex::default();
+        for conversation in conversations {
+            index
+                .version
+                .push((conversation.id.clone(), conversation.updated_at));
+            for (message_index, message) in conversation.messages.iter().enumerate() {
+                let model = match &message.sender {
+                    Sender::User => conversation.model.clone(),
+                    Sender::AI(model) => model.clone(),
+                    Sender::Thinking(_) | Sender::Error(_) => continue,
+                };
+                let id = index.documents.len();
+                for term in tokenize(&message.content) {
+                    index.postings.entry(term).or_default().insert(id);
+                }
+                index.documents.push(Document {
+                    conversation_id: conversation.id.clone(),
+                    title: conversation.title.clone(),
+                    message_index,
+                    sender: message.sender.clone(),
+                    model,
+                    timestamp: message.timestamp,
+                    content: message.content.clone(),
+                });
+            }
+        }
+        index
+    }
+
+    /// Re-reads the store and rebuilds when any conversation was added, removed or saved.
+    pub fn refresh(&mut self, store: &ConversationStore) -> std::io::Result<()> {
+        let conversations = store.list()?;
+        let version: Vec<(String, DateTime<Local>)> = conversations
+            .iter()
+            .map(|c| (c.id.clone(), c.updated_at))
+            .collect();
+        if version != self.version {
+            *self = SearchIndex::build(&conversations);
+        }
+        Ok(())
+    }
+
+    /// Every model that appears in the index, for cycling through the model filter.
+    pub fn models(&self) -> Vec<AIModel> {
+        let mut models: Vec<AIModel> = Vec::new();
+        for document in &self.documents {
+            if !models.contains(&document.model) {
+                models.push(document.model.clone());
+            }
+        }
+        models.sort_by_key(|model| model.to_string());
+        models
+    }
+
+    fn lookup(&self, term: &str) -> BTreeSet<usize> {
+        self.postings
+            .range(term.to_string()..)
+            .take_while(|(key, _)| key.starts_with(term))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+
+    /// Messages containing every term of `query` that pass `filter`, newest first.
+    pub fn search(&self, query: &str, filter: &SearchFilter) -> Vec<Hit> {
+        let terms = tokenize(query);
+        let Some((first, rest)) = terms.split_first() else {
+            return Vec::new();
+        };
+        let mut ids = self.lookup(first);
+        for term in rest {
+            let matching = self.lookup(term);
+            ids.retain(|id| matching.contains(id));
+        }
+        let since = filter.date.since(Local::now());
+        let mut hits: Vec<Hit> = ids
+            .into_iter()
+            .map(|id| &self.documents[id])
+            .filter(|doc| {
+                filter
+                    .model
+                    .as_ref()
+                    .is_none_or(|model| doc.model == *model)
+            })
+            .filter(|doc| filter.sender.matches(&doc.sender))
+            .filter(|doc| since.is_none_or(|since| doc.timestamp >= since))
+            .map(|doc| Hit {
+                conversation_id: doc.conversation_id.clone(),
+                title: doc.title.clone(),
+                message_index: doc.message_index,
+                sender: doc.sender.clone(),
+                timestamp: doc.timestamp,
+                snippet: snippet(&doc.content, &terms),
+            })
+            .collect();
+        hits.sort_by_key(|hit| std::cmp::Reverse(hit.timestamp));
+        hits
+    }
+}
+
+/// One line of context around the first match.
+fn snippet(content: &str, terms: &[String]) -> String {
+    let flat = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    let start = match_ranges(&flat, terms)
+        .first()
+        .map(|range| flat[..range.start].chars().count())
+        .unwrap_or(0);
+    let skip = start.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let text: String = flat
+        .chars()
+        .skip(skip)
+        .take(SNIPPET_CONTEXT_CHARS * 3)
+        .collect();
+    let mut snippet = String::new();
+    if skip > 0 {
+        snippet.push('…');
+    }
+    snippet.push_str(&text);
+    if flat.chars().count() > skip + SNIPPET_CONTEXT_CHARS * 3 {
+        snippet.push('…');
+    }
+    snippet
+}
+
+/// The search behind the results view: query, filters, current results and selection.
+#[derive(Debug, Default)]
+pub struct SearchState {
+    pub query: String,
+    pub filter: SearchFilter,
+    pub results: Vec<Hit>,
+    pub selected: usize,
+    pub index: SearchIndex,
+}
+
+impl SearchState {
+    pub fn update(&mut self) {
+        self.results = self.index.search(&self.query, &self.filter);
+        self.selected = self.selected.min(self.results.len().saturating_sub(1));
+    }
+
+    pub fn next_model_filter(&mut self) {
+        let models = self.index.models();
+        self.filter.model = match &self.filter.model {
+            None => models.first().cloned(),
+            Some(current) => models
+                .iter()
+                .position(|model| model == current)
+                .and_then(|i| models.get(i + 1))
+                .cloned(),
+        };
+        self.update();
+    }
+
+    pub fn next_sender_filter(&mut self) {
+        self.filter.sender = self.filter.sender.next();
+        self.update();
+    }
+
+    pub fn next_date_filter(&mut self) {
+        self.filter.date = self.filter.date.next();
+        self.update();
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.results.len().saturating_sub(1));
+    }
+
+    pub fn selected_hit(&self) -> Option<&Hit> {
+        self.results.get(self.selected)
+    }
+}
+
+/// A search result being shown in its session: which message, and the terms to mark.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHighlight {
+    pub message_index: usize,
+    pub terms: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::aliyun::AliYunModelType;
+    use crate::app::Message;
+
+    fn conversation(id: &str, model: AIModel, messages: Vec<(&str, Sender, i64)>) -> Conversation {
+        let now = Local::now();
+        let messages: Vec<Message> = messages
+            .into_iter()
+            .map(|(content, sender, days_ago)| Message {
+                timestamp: now - Duration::days(days_ago),
+                ..Message::new(content, sender)
+            })
+            .collect();
+        Conversation {
+            id: id.to_string(),
+            title: id.to_string(),
+            title_source: None,
+            model,
+            persona: None,
+            created_at: messages[0].timestamp,
+            updated_at: messages.last().unwrap().timestamp,
+            messages,
+        }
+    }
+
+    fn index() -> SearchIndex {
+        let qwen = AIModel::AliYun(AliYunModelType::QwenPlus);
+        SearchIndex::build(&[
+            conversation(
+                "tokio",
+                AIModel::DeepSeek,
+                vec![
+                    ("How do I spawn a Tokio task?", Sender::User, 8),
+                    (
+                        "Use tokio::spawn with an async block.",
+                        Sender::AI(AIModel::DeepSeek),
+                        8,
+                    ),
+                ],
+            ),
+            conversation(
+                "zh",
+                qwen.clone(),
+                vec![
+                    ("什么是异步运行时？", Sender::User, 0),
+                    ("Tokio 是一个异步运行时。", Sender::AI(qwen), 0),
+                ],
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_terms_match_as_prefixes_and_all_must_match() {
+        let index = index();
+        let filter = SearchFilter::default();
+        let hits = index.search("TOK", &filter);
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].conversation_id, "zh");
+        let hits = index.search("tokio spawn async", &filter);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message_index, 1);
+        assert_eq!(index.search("异步", &filter).len(), 2);
+        assert!(index.search("  ", &filter).is_empty());
+    }
+
+    #[test]
+    fn test_filters() {
+        let index = index();
+        let filter = SearchFilter {
+            sender: SenderFilter::User,
+            ..SearchFilter::default()
+        };
+        assert_eq!(index.search("tokio", &filter).len(), 1);
+        let filter = SearchFilter {
+            date: DateFilter::Week,
+            ..SearchFilter::default()
+        };
+        assert_eq!(index.search("tokio", &filter).len(), 1);
+        let filter = SearchFilter {
+            model: Some(AIModel::DeepSeek),
+            ..SearchFilter::default()
+        };
+        let hits = index.search("tokio", &filter);
+        assert!(hits.iter().all(|hit| hit.conversation_id == "tokio"));
+        assert_eq!(hits.len(), 2);
+    }
+
+    #[test]
+    fn test_match_ranges_and_snippet() {
+        let terms = tokenize("tokio SPA");
+        let text = "Use Tokio::spawn";
+        let ranges = match_ranges(text, &terms);
+        assert_eq!(ranges, vec![4..9, 11..14]);
+        assert_eq!(&text[ranges[0].clone()], "Tokio");
+        let long = format!("{} needle {}", "a ".repeat(50), "b ".repeat(50));
+        let snippet = snippet(&long, &tokenize("needle"));
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("needle"));
+    }
+}
@@ -0,0 +1,529 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::Local;
+use ratatui::widgets::{ListState, ScrollbarState};
+use serde::{Deserialize, Serialize};
+
+use crate::ai::deepseek::{ChatMessage, create_assistant_message, create_user_message};
+use crate::ai::limiter::estimate_tokens;
+use crate::ai_models::AIModel;
+use crate::app::{Message, Sender};
+use crate::search::SearchHighlight;
+use crate::storage::{Conversation, ConversationStore, TitleSource};
+
+/// The other versions of a conversation from one message on. Editing a message or
+/// regenerating a reply starts a new version; the message list holds the one on
+/// screen, and this keeps the rest on its first message, in the order they were made.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Branches {
+    pub before: Vec<Vec<Message>>,
+    pub after: Vec<Vec<Message>>,
+}
+
+impl Branches {
+    /// 1-based, as shown next to the message.
+    pub fn position(&self) -> usize {
+        self.before.len() + 1
+    }
+
+    pub fn count(&self) -> usize {
+        self.before.len() + self.after.len() + 1
+    }
+}
+
+/// Cuts `messages` at `index` to make room for a new version and returns the branches
+/// the new `messages[index]` should carry: every existing version, the new one last.
+pub fn branch_off(messages: &mut Vec<Message>, index: usize) -> Option<Box<Branches>> {
+    if index >= messages.len() {
+        return None;
+    }
+    let mut tail = messages.split_off(index);
+    let mut branches = tail[0].branches.take().unwrap_or_default();
+    let after = std::mem::take(&mut branches.after);
+    branches.before.push(tail);
+    branches.before.extend(after);
+    Some(branches)
+}
+
+/// Puts the previous or next version from `index` on in place of the current one.
+/// Returns false when there is no version in that direction.
+pub fn switch_branch(messages: &mut Vec<Message>, index: usize, forward: bool) -> bool {
+    let Some(mut branches) = messages
+        .get_mut(index)
+        .and_then(|message| message.branches.take())
+    else {
+        return false;
+    };
+    let replacement = if forward {
+        (!branches.after.is_empty()).then(|| branches.after.remove(0))
+    } else {
+        branches.before.pop()
+    };
+    let Some(mut replacement) = replacement.filter(|tail| !tail.is_empty()) else {
+        messages[index].branches = Some(branches);
+        return false;
+    };
+    let current = messages.split_off(index);
+    if forward {
+        branches.before.push(current);
+    } else {
+        branches.after.insert(0, current);
+    }
+    replacement[0].branches = Some(branches);
+    messages.extend(replacement);
+    true
+}
+
+/// Removes message `index`. Versions branching off there move to the message that
+/// takes its place; returns false, leaving `messages` alone, when that would lose them.
+pub fn remove_message(messages: &mut Vec<Message>, index: usize) -> bool {
+    let Some(message) = messages.get(index) else {
+        return false;
+    };
+    if message.branches.is_some()
+        && !matches!(messages.get(index + 1), Some(next) if next.branches.is_none())
+    {
+        return false;
+    }
+    let removed = messages.remove(index);
+    if removed.branches.is_some() {
+        messages[index].branches = removed.branches;
+    }
+    true
+}
+
+/// Estimated prompt tokens of history sent with a request.
+pub const CONTEXT_TOKEN_BUDGET: u32 = 8000;
+
+/// What is sent as history with the next request: the conversation on screen from
+/// the first user message on, leaving out the welcome text, errors and placeholders.
+pub fn context(messages: &[Message]) -> Vec<ChatMessage> {
+    context_within(messages, CONTEXT_TOKEN_BUDGET)
+}
+
+/// Like `context`, but once the history outgrows `budget` only the newest messages
+/// that fit are kept, along with every pinned one and the user's turn.
+pub fn context_within(messages: &[Message], budget: u32) -> Vec<ChatMessage> {
+    let history: Vec<&Message> = messages
+        .iter()
+        .skip_while(|msg| msg.sender != Sender::User)
+        .filter(|msg| matches!(msg.sender, Sender::User | Sender::AI(_)))
+        .collect();
+    let mut used: u32 = history
+        .iter()
+        .filter(|msg| msg.pinned)
+        .map(|msg| estimate_tokens(&msg.content))
+        .sum();
+    let mut full = false;
+    let mut kept = vec![false; history.len()];
+    for (index, msg) in history.iter().enumerate().rev() {
+        let tokens = estimate_tokens(&msg.content);
+        if msg.pinned {
+            kept[index] = true;
+        } else if index + 1 == history.len() || (!full && used + tokens <= budget) {
+            kept[index] = true;
+            used += tokens;
+        } else {
+            full = true;
+        }
+    }
+    history
+        .iter()
+        .zip(kept)
+        .filter(|(_, kept)| *kept)
+        .map(|(msg, _)| match msg.sender {
+            Sender::User => create_user_message(&msg.content),
+            _ => create_assistant_message(&msg.content),
+        })
+        .collect()
+}
+
+/// One open conversation: its messages, the model it talks to and its scroll state.
+/// Replies stream into `messages` from background tasks, so a session keeps receiving
+/// while another one is on screen.
+pub struct Session {
+    pub id: String,
+    /// Set by the user; `None` leaves naming to the title model.
+    pub title: Option<String>,
+    pub created_at: chrono::DateTime<Local>,
+    pub persona: Option<String>,
+    pub messages: Arc<Mutex<Vec<Message>>>,
+    pub selected_model_index: usize,
+    pub model_display_offset: usize,
+    pub ai_list_state: ListState,
+    pub user_list_state: ListState,
+    pub ai_scrollbar_state: ScrollbarState,
+    pub user_scrollbar_state: ScrollbarState,
+    pub auto_scroll: bool,
+    /// Set when a reply finishes; cleared once the session is on screen.
+    pub unread: Arc<AtomicBool>,
+    /// The search result this session was opened for, marked until the next message.
+    pub highlight: Option<SearchHighlight>,
+    /// Sampling temperature for replies in this session; `None` uses the provider's.
+    pub temperature: Option<f32>,
+    /// Written by the title model in the background; `title` takes precedence.
+    pub auto_title: Arc<Mutex<Option<String>>>,
+    pub title_requested: bool,
+    /// Bumped by edits that can keep the message count and length, like switching
+    /// branches, so autosave notices them.
+    revision: usize,
+    saved_fingerprint: Option<(usize, usize, usize, usize, bool)>,
+}
+
+impl Session {
+    pub fn new(selected_model_index: usize) -> Self {
+        Self {
+            id: Conversation::new_id(),
+            title: None,
+            created_at: Local::now(),
+            persona: None,
+            messages: Arc::new(Mutex::new(Vec::new())),
+            selected_model_index,
+            model_display_offset: 0,
+            ai_list_state: ListState::default(),
+            user_list_state: ListState::default(),
+            ai_scrollbar_state: ScrollbarState::new(0),
+            user_scrollbar_state: ScrollbarState::new(0),
+            auto_scroll: true,
+            unread: Arc::new(AtomicBool::new(false)),
+            highlight: None,
+            temperature: None,
+            auto_title: Arc::new(Mutex::new(None)),
+            title_requested: false,
+            revision: 0,
+            saved_fingerprint: None,
+        }
+    }
+
+    /// Restores a saved conversation. Only a manual title is kept as the session's own;
+    /// a generated one is restored as such, and a fallback one is left to be replaced.
+    pub fn from_conversation(conversation: Conversation, selected_model_index: usize) -> Self {
+        let count = conversation.messages.len();
+        let (title, auto_title) = match conversation.title_source() {
+            TitleSource::Manual => (Some(conversation.title), None),
+            TitleSource::Generated => (None, Some(conversation.title)),
+            TitleSource::Fallback => (None, None),
+        };
+        Self {
+            id: conversation.id,
+            title,
+            auto_title: Arc::new(Mutex::new(auto_title)),
+            created_at: conversation.created_at,
+            persona: conversation.persona,
+            messages: Arc::new(Mutex::new(conversation.messages)),
+            ai_scrollbar_state: ScrollbarState::new(count),
+            ..Self::new(selected_model_index)
+        }
+    }
+
+    /// The manual title, else the generated one, else one derived from the first user
+    /// message.
+    pub fn title(&self) -> Option<String> {
+        self.title
+            .clone()
+            .or_else(|| self.auto_title.lock().unwrap().clone())
+            .or_else(|| Conversation::fallback_title(&self.messages.lock().unwrap()))
+    }
+
+    /// Whether the user named the session, which keeps the title model away.
+    pub fn has_manual_title(&self) -> bool {
+        self.title.is_some()
+    }
+
+    /// Whether the title model has no title for the session yet.
+    pub fn needs_auto_title(&self) -> bool {
+        !self.title_requested && self.auto_title.lock().unwrap().is_none()
+    }
+
+    pub fn rename(&mut self, title: String) {
+        self.title = Some(title);
+        self.revision += 1;
+    }
+
+    /// Drops the manual title, going back to the generated or fallback one.
+    pub fn clear_title(&mut self) {
+        self.title = None;
+        self.revision += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.lock().unwrap().is_empty()
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.messages
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|msg| matches!(msg.sender, Sender::Thinking(_)))
+    }
+
+    pub fn is_unread(&self) -> bool {
+        self.unread.load(Ordering::Relaxed)
+    }
+
+    pub fn mark_read(&self) {
+        self.unread.store(false, Ordering::Relaxed);
+    }
+
+    /// Switches the branch starting at message `index`. Refused while a reply is
+    /// streaming, since the reply is written to a fixed position in the list.
+    pub fn switch_branch(&mut self, index: usize, forward: bool) -> bool {
+        if self.has_pending() {
+            return false;
+        }
+        let switched = switch_branch(&mut self.messages.lock().unwrap(), index, forward);
+        if switched {
+            self.revision += 1;
+        }
+        switched
+    }
+
+    /// Deletes message `index`, so it is neither shown nor sent as context any more.
+    /// Refused while a reply is streaming, and where other versions would be lost.
+    pub fn delete_message(&mut self, index: usize) -> bool {
+        if self.has_pending() {
+            return false;
+        }
+        let removed = remove_message(&mut self.messages.lock().unwrap(), index);
+        if removed {
+            self.revision += 1;
+        }
+        removed
+    }
+
+    /// Pins or unpins message `index` and returns whether it is pinned now.
+    pub fn toggle_pin(&mut self, index: usize) -> Option<bool> {
+        let pinned = {
+            let mut messages = self.messages.lock().unwrap();
+            let message = messages.get_mut(index)?;
+            message.pinned = !message.pinned;
+            message.pinned
+        };
+        self.revision += 1;
+        Some(pinned)
+    }
+
+    /// Scrolls so that message `index` is near the top of the chat area. The chat view
+    /// scrolls three lines per step and gives each message a header and a blank line.
+    pub fn scroll_to_message(&mut self, index: usize) {
+        let line: usize = self
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .take(index)
+            .map(|msg| msg.content.lines().count() + 2)
+            .sum();
+        self.ai_list_state.select(Some(line / 3));
+        self.auto_scroll = false;
+    }
+
+    /// The conversation without in-flight placeholders, or `None` before the user has
+    /// said anything.
+    pub fn snapshot(&self, model: AIModel) -> Option<Conversation> {
+        self.conversation(model, false)
+    }
+
+    /// Like `snapshot`, but replies still streaming are kept with what has arrived, for
+    /// the crash journal.
+    pub fn journal_entry(&self, model: AIModel) -> Option<Conversation> {
+        self.conversation(model, true)
+    }
+
+    fn conversation(&self, model: AIModel, keep_pending: bool) -> Option<Conversation> {
+        let messages: Vec<Message> = self
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|msg| keep_pending || !matches!(msg.sender, Sender::Thinking(_)))
+            .cloned()
+            .collect();
+        let auto_title = self.auto_title.lock().unwrap().clone();
+        let (title, title_source) = match (&self.title, auto_title) {
+            (Some(title), _) => (title.clone(), TitleSource::Manual),
+            (None, Some(title)) => (title, TitleSource::Generated),
+            (None, None) => (
+                Conversation::fallback_title(&messages)?,
+                TitleSource::Fallback,
+            ),
+        };
+        Some(Conversation {
+            id: self.id.clone(),
+            title,
+            title_source: Some(title_source),
+            model,
+            persona: self.persona.clone(),
+            created_at: self.created_at,
+            updated_at: messages
+                .last()
+                .map(|msg| msg.timestamp)
+                .unwrap_or(self.created_at),
+            messages,
+        })
+    }
+
+    /// Changes whenever the messages, including a streaming reply and its completion,
+    /// or the manual or generated title change.
+    pub fn fingerprint(&self) -> (usize, usize, usize, usize, bool) {
+        let messages = self.messages.lock().unwrap();
+        (
+            messages.len(),
+            messages.iter().map(|msg| msg.content.len()).sum(),
+            messages
+                .iter()
+                .filter(|msg| matches!(msg.sender, Sender::Thinking(_)))
+                .count(),
+            self.revision,
+            self.auto_title.lock().unwrap().is_some(),
+        )
+    }
+
+    /// Saves when the messages changed or the session got a title, unless a reply is
+    /// still streaming. Cheap when nothing changed, so it can run every tick.
+    pub fn save_if_changed(&mut self, store: &ConversationStore, model: AIModel) -> io::Result<()> {
+        if self.has_pending() {
+            return Ok(());
+        }
+        let fingerprint = self.fingerprint();
+        if self.saved_fingerprint == Some(fingerprint) {
+            return Ok(());
+        }
+        self.saved_fingerprint = Some(fingerprint);
+        match self.snapshot(model) {
+            Some(conversation) => store.save(&conversation),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|msg| msg.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_edits_become_navigable_branches() {
+        let ai = || Sender::AI(AIModel::DeepSeek);
+        let mut messages = vec![
+            Message::new("q1", Sender::User),
+            Message::new("a1", ai()),
+            Message::new("q2", Sender::User),
+            Message::new("a2", ai()),
+        ];
+        // Edit q2 twice, then q1 once.
+        for edit in ["q2'", "q2''"] {
+            let branches = branch_off(&mut messages, 2);
+            let mut edited = Message::new(edit, Sender::User);
+            edited.branches = branches;
+            messages.push(edited);
+            messages.push(Message::new("reply", ai()));
+        }
+        let branches = messages[2].branches.as_ref().unwrap();
+        assert_eq!((branches.position(), branches.count()), (3, 3));
+        assert!(switch_branch(&mut messages, 2, false));
+        assert_eq!(contents(&messages), vec!["q1", "a1", "q2'", "reply"]);
+        assert!(switch_branch(&mut messages, 2, false));
+        assert_eq!(contents(&messages), vec!["q1", "a1", "q2", "a2"]);
+        assert!(!switch_branch(&mut messages, 2, false));
+        assert_eq!(messages[2].branches.as_ref().unwrap().position(), 1);
+
+        let branches = branch_off(&mut messages, 0);
+        let mut edited = Message::new("q1'", Sender::User);
+        edited.branches = branches;
+        messages.push(edited);
+        assert!(switch_branch(&mut messages, 0, false));
+        // The nested branches of the old version came back with it.
+        assert_eq!(contents(&messages), vec!["q1", "a1", "q2", "a2"]);
+        assert_eq!(messages[2].branches.as_ref().unwrap().count(), 3);
+        assert!(switch_branch(&mut messages, 0, true));
+        assert_eq!(contents(&messages), vec!["q1'"]);
+        assert!(!switch_branch(&mut messages, 0, true));
+    }
+
+    #[test]
+    fn test_context_keeps_pinned_and_newest_within_budget() {
+        let ai = || Sender::AI(AIModel::DeepSeek);
+        let long = "x".repeat(400);
+        let mut messages = vec![
+            Message::new("Welcome", ai()),
+            Message::new("setup", Sender::User),
+            Message::new(&long, ai()),
+            Message::new("q2", Sender::User),
+            Message::new(&long, ai()),
+            Message::new("q3", Sender::User),
+        ];
+        messages[1].pinned = true;
+        let sent: Vec<String> = context_within(&messages, 120)
+            .into_iter()
+            .map(|msg| msg.content)
+            .collect();
+        assert_eq!(sent, vec!["setup", "q2", &long, "q3"]);
+        assert_eq!(context(&messages).len(), 5);
+    }
+
+    #[test]
+    fn test_remove_message_moves_branches_along() {
+        let ai = || Sender::AI(AIModel::DeepSeek);
+        let mut messages = vec![Message::new("q1", Sender::User), Message::new("a1", ai())];
+        let branches = branch_off(&mut messages, 1);
+        let mut regenerated = Message::new("a1'", ai());
+        regenerated.branches = branches;
+        messages.push(regenerated);
+        assert!(!remove_message(&mut messages, 1));
+
+        messages.push(Message::new("q2", Sender::User));
+        assert!(remove_message(&mut messages, 1));
+        assert_eq!(contents(&messages), vec!["q1", "q2"]);
+        assert!(switch_branch(&mut messages, 1, false));
+        assert_eq!(contents(&messages), vec!["q1", "a1"]);
+    }
+
+    #[test]
+    fn test_only_manual_titles_are_restored_as_manual() {
+        let session = Session::new(0);
+        session
+            .messages
+            .lock()
+            .unwrap()
+            .extend([Message::new("How do lifetimes work?", Sender::User)]);
+        let fallback = session.snapshot(AIModel::DeepSeek).unwrap();
+        assert_eq!(fallback.title_source, Some(TitleSource::Fallback));
+        let restored = Session::from_conversation(fallback.clone(), 0);
+        assert!(restored.title.is_none());
+        assert!(restored.auto_title.lock().unwrap().is_none());
+        assert_eq!(restored.title().as_deref(), Some("How do lifetimes work?"));
+
+        *session.auto_title.lock().unwrap() = Some("Rust Lifetimes".to_string());
+        let generated = session.snapshot(AIModel::DeepSeek).unwrap();
+        assert_eq!(generated.title_source, Some(TitleSource::Generated));
+        let restored = Session::from_conversation(generated, 0);
+        assert!(restored.title.is_none());
+        assert_eq!(restored.title().as_deref(), Some("Rust Lifetimes"));
+
+        let mut session = session;
+        session.rename("Borrowing".to_string());
+        let manual = session.snapshot(AIModel::DeepSeek).unwrap();
+        assert_eq!(manual.title_source, Some(TitleSource::Manual));
+        let restored = Session::from_conversation(manual, 0);
+        assert_eq!(restored.title.as_deref(), Some("Borrowing"));
+
+        // Files saved before the source was recorded.
+        let legacy = Conversation {
+            title_source: None,
+            ..fallback
+        };
+        assert_eq!(legacy.title_source(), TitleSource::Fallback);
+        let legacy = Conversation {
+            title: "Renamed".to_string(),
+            ..legacy
+        };
+        assert_eq!(legacy.title_source(), TitleSource::Manual);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::ai_models::AIModel;
+use crate::app::{Message, Sender};
+
+pub const DATA_DIR_ENV_VAR: &str = "AICHAT_DATA_DIR";
+const TITLE_MAX_CHARS: usize = 40;
+
+/// A saved conversation, one JSON file per conversation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    /// Where `title` came from; missing in files saved before it was recorded.
+    #[serde(default)]
+    pub title_source: Option<TitleSource>,
+    pub model: AIModel,
+    #[serde(default)]
+    pub persona: Option<String>,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+    pub messages: Vec<Message>,
+}
+
+/// How a conversation got its title, so a resumed session knows whether to keep it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleSource {
+    /// Named by the user.
+    Manual,
+    /// Written by the title model, or taken from an imported export.
+    Generated,
+    /// Taken from the first user message until a better one exists.
+    Fallback,
+}
+
+impl Conversation {
+    /// Where the title came from. Older files count as manual unless the title is just
+    /// the fallback taken from the first user message.
+    pub fn title_source(&self) -> TitleSource {
+        self.title_source.unwrap_or_else(|| {
+            if Self::fallback_title(&self.messages).as_ref() == Some(&self.title) {
+                TitleSource::Fallback
+            } else {
+                TitleSource::Manual
+            }
+        })
+    }
+
+    /// Sortable and unique enough for one user: creation time down to milliseconds.
+    pub fn new_id() -> String {
+        Local::now().format("%Y%m%d-%H%M%S-%3f").to_string()
+    }
+
+    /// The first user message, shortened to fit a title bar.
+    pub fn fallback_title(messages: &[Message]) -> Option<String> {
+        let first = messages.iter().find(|msg| msg.sender == Sender::User)?;
+        Some(Self::shorten_title(
+            first.content.lines().next().unwrap_or_default(),
+        ))
+    }
+
+    /// `line` trimmed and cut with an ellipsis to fit a title bar.
+    pub fn shorten_title(line: &str) -> String {
+        let line = line.trim();
+        if line.chars().count() > TITLE_MAX_CHARS {
+            let short: String = line.chars().take(TITLE_MAX_CHARS - 1).collect();
+            format!("{}…", short.trim_end())
+        } else {
+            line.to_string()
+        }
+    }
+}
+
+/// Conversations stored as `<id>.json` under the XDG data dir
+/// (`~/.local/share/aichat/conversations` on Linux), or under `AICHAT_DATA_DIR`.
+#[derive(Debug, Clone)]
+pub struct ConversationStore {
+    dir: PathBuf,
+}
+
+impl ConversationStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn data_dir() -> Option<PathBuf> {
+        match std::env::var(DATA_DIR_ENV_VAR) {
+            Ok(dir) if !dir.trim().is_empty() => Some(PathBuf::from(dir)),
+            _ => dirs::data_dir().map(|dir| dir.join("aichat")),
+        }
+    }
+
+    pub fn open_default() -> Option<Self> {
+        Self::data_dir().map(|dir| Self::new(dir.join("conversations")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Writes through a temporary file so a crash never leaves a truncated conversation.
+    pub fn save(&self, conversation: &Conversation) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_string_pretty(conversation)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = self.dir.join(format!(".{}.json.tmp", conversation.id));
+        fs::write(&tmp, json)?;
+        fs::rename(tmp, self.path(&conversation.id))
+    }
+
+    pub fn load(&self, id: &str) -> io::Result<Conversation> {
+        let json = fs::read_to_string(self.path(id))?;
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn delete(&self, id: &str) -> io::Result<()> {
+        fs::remove_file(self.path(id))
+    }
+
+    /// Every readable conversation, most recently updated first. Unreadable files are
+    /// skipped rather than failing the whole listing.
+    pub fn list(&self) -> io::Result<Vec<Conversation>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut conversations: Vec<Conversation> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .filter_map(|json| serde_json::from_str(&json).ok())
+            .collect();
+        conversations.sort_by_key(|c| std::cmp::Reverse(c.updated_at));
+        Ok(conversations)
+    }
+
+    pub fn latest(&self) -> io::Result<Option<Conversation>> {
+        Ok(self.list()?.into_iter().next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    fn temp_store(name: &str) -> ConversationStore {
+        ConversationStore::new(temp_path(name))
+    }
+
+    fn conversation(id: &str, updated_at: DateTime<Local>) -> Conversation {
+        Conversation {
+            id: id.to_string(),
+            title: "Rust".to_string(),
+            title_source: Some(TitleSource::Manual),
+            model: AIModel::Custom("my-model".to_string()),
+            persona: None,
+            created_at: updated_at,
+            updated_at,
+            messages: vec![
+                Message::new("What is Rust?", Sender::User),
+                Message::new("A language.", Sender::AI(AIModel::DeepSeek)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let store = temp_store("round-trip");
+        let saved = conversation("a", Local::now());
+        store.save(&saved).unwrap();
+        let loaded = store.load("a").unwrap();
+        assert_eq!(loaded.title, "Rust");
+        assert_eq!(loaded.model, AIModel::Custom("my-model".to_string()));
+        assert_eq!(loaded.messages[1].sender, Sender::AI(AIModel::DeepSeek));
+        assert_eq!(loaded.messages[0].timestamp, saved.messages[0].timestamp);
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_list_is_newest_first_and_skips_garbage() {
+        let store = temp_store("list");
+        let now = Local::now();
+        store
+            .save(&conversation("old", now - chrono::Duration::hours(1)))
+            .unwrap();
+        store.save(&conversation("new", now)).unwrap();
+        fs::write(store.dir().join("broken.json"), "{").unwrap();
+        let ids: Vec<String> = store.list().unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec!["new", "old"]);
+        assert_eq!(store.latest().unwrap().unwrap().id, "new");
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_fallback_title_truncates_first_user_line() {
+        let long = "x".repeat(60);
+        let messages = vec![
+            Message::new("Welcome", Sender::AI(AIModel::DeepSeek)),
+            Message::new(format!("{}\nsecond line", long), Sender::User),
+        ];
+        let title = Conversation::fallback_title(&messages).unwrap();
+        assert_eq!(title.chars().count(), TITLE_MAX_CHARS);
+        assert!(title.ends_with('…'));
+    }
+}
@@ -0,0 +1,822 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+pub const THEMES_DIR_ENV_VAR: &str = "AICHAT_THEMES_DIR";
+/// How often the themes directory is checked for edits.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub primary: Color,
+    pub secondary: Color,
+    pub accent: Color,
+    pub background: Color,
+    pub text: Color,
+    pub success: Color,
+    pub warning: Color,
+    pub error: Color,
+    pub styles: Styles,
+    /// The terminal's colors this theme was fitted to.
+    pub depth: ColorDepth,
+}
+
+/// Styles a theme file may set; unset ones follow from the theme's colors.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Styles {
+    /// Lines of fenced code blocks in messages.
+    pub code: Option<Style>,
+    /// Markdown blockquotes in messages.
+    pub quote: Option<Style>,
+    /// Failed replies.
+    pub error: Option<Style>,
+}
+
+impl Theme {
+    pub fn deep_blue() -> Self {
+        Theme {
+            name: "blue".to_string(),
+            primary: Color::Rgb(0, 119, 190),
+            secondary: Color::Rgb(0, 180, 216),
+            accent: Color::Rgb(144, 224, 239),
+            background: Color::Rgb(12, 20, 31),
+            text: Color::Rgb(230, 240, 255),
+            success: Color::Rgb(46, 204, 113),
+            warning: Color::Rgb(241, 196, 15),
+            error: Color::Rgb(231, 76, 60),
+            styles: Styles::default(),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+
+    pub fn forest_green() -> Self {
+        Theme {
+            name: "green".to_string(),
+            primary: Color::Rgb(46, 125, 50),
+            secondary: Color::Rgb(76, 175, 80),
+            accent: Color::Rgb(165, 214, 167),
+            background: Color::Rgb(24, 30, 24),
+            text: Color::Rgb(240, 255, 240),
+            success: Color::Rgb(56, 142, 60),
+            warning: Color::Rgb(255, 193, 7),
+            error: Color::Rgb(244, 67, 54),
+            styles: Styles::default(),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+
+    pub fn sunset() -> Self {
+        Theme {
+            name: "orange".to_string(),
+            primary: Color::Rgb(233, 69, 96),
+            secondary: Color::Rgb(255, 119, 34),
+            accent: Color::Rgb(255, 190, 11),
+            background: Color::Rgb(29, 23, 40),
+            text: Color::Rgb(255, 240, 230),
+            success: Color::Rgb(46, 204, 113),
+            warning: Color::Rgb(241, 196, 15),
+            error: Color::Rgb(231, 76, 60),
+            styles: Styles::default(),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+
+    pub fn neon() -> Self {
+        Theme {
+            name: "neon".to_string(),
+            primary: Color::Rgb(255, 0, 255),
+            secondary: Color::Rgb(0, 255, 255),
+            accent: Color::Rgb(255, 255, 0),
+            background: Color::Rgb(0, 0, 20),
+            text: Color::Rgb(255, 255, 255),
+            success: Color::Rgb(0, 255, 128),
+            warning: Color::Rgb(255, 128, 0),
+            error: Color::Rgb(255, 0, 128),
+            styles: Styles::default(),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+
+    pub fn light() -> Self {
+        Theme {
+            name: "light".to_string(),
+            primary: Color::Rgb(9, 105, 218),
+            secondary: Color::Rgb(130, 80, 223),
+            accent: Color::Rgb(27, 124, 131),
+            background: Color::Rgb(250, 250, 250),
+            text: Color::Rgb(36, 41, 47),
+            success: Color::Rgb(26, 127, 55),
+            warning: Color::Rgb(188, 76, 0),
+            error: Color::Rgb(207, 34, 46),
+            styles: Styles::default(),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+
+    pub fn solarized_light() -> Self {
+        Theme {
+            name: "solarized-light".to_string(),
+            primary: Color::Rgb(38, 139, 210),
+            secondary: Color::Rgb(42, 161, 152),
+            accent: Color::Rgb(181, 137, 0),
+            background: Color::Rgb(253, 246, 227),
+            text: Color::Rgb(88, 110, 117),
+            success: Color::Rgb(133, 153, 0),
+            warning: Color::Rgb(203, 75, 22),
+            error: Color::Rgb(220, 50, 47),
+            styles: Styles::default(),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+
+    /// The themes that ship with aichat; the first four are on the 1-4 keys.
+    pub fn builtin() -> Vec<Theme> {
+        vec![
+            Theme::deep_blue(),
+            Theme::forest_green(),
+            Theme::sunset(),
+            Theme::neon(),
+            Theme::light(),
+            Theme::solarized_light(),
+        ]
+    }
+
+    /// This theme with every color replaced by the nearest one `depth` can show.
+    pub fn fit(&self, depth: ColorDepth) -> Theme {
+        let style = |style: Option<Style>| {
+            style.map(|style| Style {
+                fg: style.fg.map(|color| depth.fit(color)),
+                bg: style.bg.map(|color| depth.fit(color)),
+                ..style
+            })
+        };
+        Theme {
+            name: self.name.clone(),
+            primary: depth.fit(self.primary),
+            secondary: depth.fit(self.secondary),
+            accent: depth.fit(self.accent),
+            background: depth.fit(self.background),
+            text: depth.fit(self.text),
+            success: depth.fit(self.success),
+            warning: depth.fit(self.warning),
+            error: depth.fit(self.error),
+            styles: Styles {
+                code: style(self.styles.code),
+                quote: style(self.styles.quote),
+                error: style(self.styles.error),
+            },
+            depth,
+        }
+    }
+
+    /// A color from outside the theme, such as a model's, fitted like the theme's.
+    pub fn paint(&self, color: Color) -> Color {
+        self.depth.fit(color)
+    }
+
+    /// Marks a selected item or search match with `color` behind it, or in reverse
+    /// video when colors are off.
+    pub fn highlight(&self, color: Color) -> Style {
+        if self.depth == ColorDepth::Monochrome {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default().fg(self.background).bg(self.paint(color))
+        }
+    }
+
+    pub fn code_style(&self) -> Style {
+        self.styles
+            .code
+            .unwrap_or_else(|| Style::default().fg(self.accent))
+    }
+
+    pub fn quote_style(&self) -> Style {
+        self.styles.quote.unwrap_or_else(|| {
+            Style::default()
+                .fg(self.secondary)
+                .add_modifier(Modifier::ITALIC)
+        })
+    }
+
+    pub fn error_style(&self) -> Style {
+        self.styles
+            .error
+            .unwrap_or_else(|| Style::default().fg(self.error))
+    }
+
+    /// Reads a theme file. Colors are names (`yellow`, `light-blue`), `#rrggbb` or
+    /// 0-255 palette indexes; the name defaults to the file name.
+    pub fn from_file(path: &Path) -> Result<Theme, ThemeError> {
+        let error = |message: String| ThemeError {
+            path: path.to_path_buf(),
+            message,
+        };
+        let text = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let file: ThemeFile = toml::from_str(&text).map_err(|e| error(e.to_string()))?;
+        let name = match file.name {
+            Some(name) => name,
+            None => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        if name.trim().is_empty() {
+            return Err(error("the theme has no name".to_string()));
+        }
+        let color = |field: &str, value: &str| {
+            Color::from_str(value).map_err(|_| error(format!("{}: unknown color {}", field, value)))
+        };
+        let style = |field: &str, file: Option<StyleFile>| -> Result<Option<Style>, ThemeError> {
+            let Some(file) = file else {
+                return Ok(None);
+            };
+            let mut style = Style::default();
+            if let Some(fg) = &file.fg {
+                style = style.fg(color(field, fg)?);
+            }
+            if let Some(bg) = &file.bg {
+                style = style.bg(color(field, bg)?);
+            }
+            for (set, modifier) in [
+                (file.bold, Modifier::BOLD),
+                (file.italic, Modifier::ITALIC),
+                (file.underlined, Modifier::UNDERLINED),
+            ] {
+                if set {
+                    style = style.add_modifier(modifier);
+                }
+            }
+            Ok(Some(style))
+        };
+        Ok(Theme {
+            name,
+            primary: color("primary", &file.primary)?,
+            secondary: color("secondary", &file.secondary)?,
+            accent: color("accent", &file.accent)?,
+            background: color("background", &file.background)?,
+            text: color("text", &file.text)?,
+            success: color("success", &file.success)?,
+            warning: color("warning", &file.warning)?,
+            error: color("error", &file.error)?,
+            styles: Styles {
+                code: style("styles.code", file.styles.code)?,
+                quote: style("styles.quote", file.styles.quote)?,
+                error: style("styles.error", file.styles.error)?,
+            },
+            depth: ColorDepth::TrueColor,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    name: Option<String>,
+    primary: String,
+    secondary: String,
+    accent: String,
+    background: String,
+    text: String,
+    success: String,
+    warning: String,
+    error: String,
+    #[serde(default)]
+    styles: StylesFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StylesFile {
+    code: Option<StyleFile>,
+    quote: Option<StyleFile>,
+    error: Option<StyleFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StyleFile {
+    fg: Option<String>,
+    bg: Option<String>,
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThemeError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid theme file {}: {}",
+            self.path.display(),
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+impl From<ThemeError> for io::Error {
+    fn from(e: ThemeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// 24-bit colors, used as they are.
+    #[default]
+    TrueColor,
+    /// The xterm 256-color palette.
+    Palette256,
+    /// The 16 ANSI colors.
+    Palette16,
+    /// `NO_COLOR` is set: the terminal's own colors, with bold, italic and reverse
+    /// video only.
+    Monochrome,
+}
+
+/// The 16 ANSI colors as xterm shows them by default.
+const ANSI: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// The channel values of the 6×6×6 color cube at indexes 16-231.
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// The RGB value of a palette color past the 16 ANSI ones.
+fn palette_rgb(index: u8) -> (u8, u8, u8) {
+    if index >= 232 {
+        let level = 8 + 10 * (index - 232);
+        (level, level, level)
+    } else {
+        let i = (index - 16) as usize;
+        (CUBE[i / 36], CUBE[i / 6 % 6], CUBE[i % 6])
+    }
+}
+
+/// The closest of palette colors 16-255; the first 16 are left out because
+/// terminals recolor them.
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    (16..=255)
+        .min_by_key(|&index| distance(rgb, palette_rgb(index)))
+        .unwrap_or(16)
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> Color {
+    ANSI.iter()
+        .min_by_key(|(_, ansi)| distance(rgb, *ansi))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+impl ColorDepth {
+    /// `NO_COLOR` turns colors off; `COLORTERM=truecolor` or `24bit` means 24-bit
+    /// colors, and a `TERM` ending in `256color` the 256-color palette. Anything
+    /// else gets the 16 ANSI colors.
+    pub fn detect() -> Self {
+        Self::from_env(|name| std::env::var(name).ok())
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        if var("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return ColorDepth::Monochrome;
+        }
+        let colorterm = var("COLORTERM").unwrap_or_default().to_ascii_lowercase();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        if var("TERM").is_some_and(|term| term.ends_with("256color")) {
+            ColorDepth::Palette256
+        } else {
+            ColorDepth::Palette16
+        }
+    }
+
+    /// The nearest color to `color` this depth can show.
+    pub fn fit(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, color) => color,
+            (ColorDepth::Monochrome, _) => Color::Reset,
+            (ColorDepth::Palette256, Color::Rgb(r, g, b)) => Color::Indexed(nearest_256((r, g, b))),
+            (ColorDepth::Palette16, Color::Rgb(r, g, b)) => nearest_16((r, g, b)),
+            (ColorDepth::Palette16, Color::Indexed(index)) if index < 16 => ANSI[index as usize].0,
+            (ColorDepth::Palette16, Color::Indexed(index)) => nearest_16(palette_rgb(index)),
+            (_, color) => color,
+        }
+    }
+}
+
+/// A theme picked in the config or on the command line: its number, counting from 1,
+/// or its name.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ThemeChoice {
+    Number(usize),
+    Name(String),
+}
+
+impl FromStr for ThemeChoice {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("empty theme name".to_string());
+        }
+        Ok(match text.parse() {
+            Ok(number) => ThemeChoice::Number(number),
+            Err(_) => ThemeChoice::Name(text.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for ThemeChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThemeChoice::Number(number) => write!(f, "{}", number),
+            ThemeChoice::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+/// The built-in themes followed by the `*.toml` files in the themes directory, in
+/// file name order. A file with a built-in theme's name replaces it.
+#[derive(Debug, Clone)]
+pub struct ThemeRegistry {
+    themes: Vec<Theme>,
+    /// `themes` fitted to the terminal's colors.
+    fitted: Vec<Theme>,
+    depth: ColorDepth,
+    dir: Option<PathBuf>,
+    /// The theme files and when they were last modified, as of the last load.
+    stamp: Vec<(PathBuf, Option<SystemTime>)>,
+    checked: Instant,
+}
+
+impl Default for ThemeRegistry {
+    fn default() -> Self {
+        Self {
+            themes: Theme::builtin(),
+            fitted: Theme::builtin(),
+            depth: ColorDepth::TrueColor,
+            dir: None,
+            stamp: Vec::new(),
+            checked: Instant::now(),
+        }
+    }
+}
+
+fn theme_files(dir: &Path) -> io::Result<Vec<(PathBuf, Option<SystemTime>)>> {
+    let mut files = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            files.push((path, modified));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+impl ThemeRegistry {
+    /// `AICHAT_THEMES_DIR`, else `themes` in the user's aichat config dir.
+    pub fn default_dir() -> Option<PathBuf> {
+        match std::env::var(THEMES_DIR_ENV_VAR) {
+            Ok(dir) if !dir.trim().is_empty() => Some(PathBuf::from(dir)),
+            _ => dirs::config_dir().map(|dir| dir.join("aichat").join("themes")),
+        }
+    }
+
+    pub fn load_default() -> Result<Self, ThemeError> {
+        match Self::default_dir() {
+            Some(dir) => Self::load(&dir),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The built-in themes and those in `dir`, which need not exist.
+    pub fn load(dir: &Path) -> Result<Self, ThemeError> {
+        let mut registry = Self {
+            dir: Some(dir.to_path_buf()),
+            ..Self::default()
+        };
+        registry.reload()?;
+        Ok(registry)
+    }
+
+    /// Shows the themes in the colors of a terminal with `depth`.
+    pub fn with_color_depth(mut self, depth: ColorDepth) -> Self {
+        self.depth = depth;
+        self.fit();
+        self
+    }
+
+    fn fit(&mut self) {
+        self.fitted = self
+            .themes
+            .iter()
+            .map(|theme| theme.fit(self.depth))
+            .collect();
+    }
+
+    fn reload(&mut self) -> Result<(), ThemeError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        self.checked = Instant::now();
+        let files = theme_files(dir).map_err(|e| ThemeError {
+            path: dir.clone(),
+            message: e.to_string(),
+        })?;
+        // Remember the files even if one is broken, so the error is reported once.
+        self.stamp = files.clone();
+        let mut themes = Theme::builtin();
+        for (path, _) in &files {
+            let theme = Theme::from_file(path)?;
+            match themes.iter_mut().find(|other| other.name == theme.name) {
+                Some(other) => *other = theme,
+                None => themes.push(theme),
+            }
+        }
+        self.themes = themes;
+        self.fit();
+        Ok(())
+    }
+
+    /// Loads the themes again if a file in the directory was added, removed or
+    /// edited since the last look, at most once a second. `None` when nothing
+    /// changed; on an error the themes stay as they were.
+    pub fn reload_if_changed(&mut self) -> Option<Result<(), ThemeError>> {
+        let dir = self.dir.as_ref()?;
+        if self.checked.elapsed() < RELOAD_INTERVAL {
+            return None;
+        }
+        self.checked = Instant::now();
+        let files = theme_files(dir).ok()?;
+        if files == self.stamp {
+            return None;
+        }
+        Some(self.reload())
+    }
+
+    pub fn len(&self) -> usize {
+        self.themes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.themes.is_empty()
+    }
+
+    /// The theme at `index` as the terminal shows it, or the first one when out of
+    /// range.
+    pub fn get(&self, index: usize) -> &Theme {
+        self.fitted.get(index).unwrap_or(&self.fitted[0])
+    }
+
+    /// The theme at `index` in its own colors, for exports.
+    pub fn source(&self, index: usize) -> &Theme {
+        self.themes.get(index).unwrap_or(&self.themes[0])
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.themes
+            .iter()
+            .position(|theme| theme.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.themes.iter().map(|theme| theme.name.as_str())
+    }
+
+    /// The index of the theme `choice` picks.
+    pub fn find(&self, choice: &ThemeChoice) -> Result<usize, String> {
+        match choice {
+            ThemeChoice::Number(number) if (1..=self.len()).contains(number) => Ok(number - 1),
+            ThemeChoice::Number(number) => Err(format!(
+                "theme must be between 1 and {}, not {}",
+                self.len(),
+                number
+            )),
+            ThemeChoice::Name(name) => self.position(name).ok_or_else(|| {
+                format!(
+                    "unknown theme {}; the themes are {}",
+                    name,
+                    self.names().collect::<Vec<_>>().join(", ")
+                )
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    fn temp_dir() -> PathBuf {
+        let dir = temp_path("themes");
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    const SOLARIZED: &str = r##"
+primary = "#268bd2"
+secondary = "#2aa198"
+accent = "#b58900"
+background = "#002b36"
+text = "#eee8d5"
+success = "#859900"
+warning = "#cb4b16"
+error = "#dc322f"
+
+[styles.code]
+fg = "light-cyan"
+bg = "236"
+
+[styles.quote]
+fg = "gray"
+italic = true
+"##;
+
+    #[test]
+    fn test_theme_files_add_and_replace_themes() {
+        let dir = temp_dir();
+        fs::write(dir.join("solarized.toml"), SOLARIZED).unwrap();
+        fs::write(
+            dir.join("my-neon.toml"),
+            SOLARIZED.replacen("primary", "name = \"neon\"\nprimary", 1),
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not a theme").unwrap();
+        let registry = ThemeRegistry::load(&dir).unwrap();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            vec![
+                "blue",
+                "green",
+                "orange",
+                "neon",
+                "light",
+                "solarized-light",
+                "solarized"
+            ]
+        );
+        let solarized = registry.get(6);
+        assert_eq!(solarized.background, Color::Rgb(0, 43, 54));
+        assert_eq!(
+            solarized.code_style(),
+            Style::default()
+                .fg(Color::LightCyan)
+                .bg(Color::Indexed(236))
+        );
+        assert_eq!(
+            solarized.quote_style(),
+            Style::default()
+                .fg(Color::Gray)
+                .add_modifier(Modifier::ITALIC)
+        );
+        assert_eq!(
+            solarized.error_style(),
+            Style::default().fg(solarized.error)
+        );
+        assert_eq!(registry.get(3).primary, Color::Rgb(38, 139, 210));
+        assert_eq!(registry.find(&"Solarized".parse().unwrap()), Ok(6));
+        assert_eq!(registry.find(&ThemeChoice::Number(2)), Ok(1));
+        assert_eq!(
+            registry.find(&ThemeChoice::Number(8)).unwrap_err(),
+            "theme must be between 1 and 7, not 8"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_errors_and_reload() {
+        let dir = temp_dir();
+        let path = dir.join("broken.toml");
+        fs::write(&path, SOLARIZED.replace("#859900", "olive")).unwrap();
+        let error = ThemeRegistry::load(&dir).unwrap_err();
+        assert_eq!(error.path, path);
+        assert_eq!(error.message, "success: unknown color olive");
+
+        fs::write(&path, SOLARIZED).unwrap();
+        let mut registry = ThemeRegistry::load(&dir).unwrap();
+        assert_eq!(registry.reload_if_changed(), None);
+        fs::remove_file(&path).unwrap();
+        registry.checked -= RELOAD_INTERVAL;
+        assert_eq!(registry.reload_if_changed(), Some(Ok(())));
+        assert_eq!(registry.len(), 6);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_color_depth_detection_and_fallback() {
+        let env = |vars: &[(&str, &str)]| {
+            let vars: Vec<(String, String)> = vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            ColorDepth::from_env(move |name| {
+                vars.iter()
+                    .find(|(var, _)| var == name)
+                    .map(|(_, value)| value.clone())
+            })
+        };
+        assert_eq!(
+            env(&[("COLORTERM", "truecolor"), ("TERM", "xterm")]),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(env(&[("TERM", "screen-256color")]), ColorDepth::Palette256);
+        assert_eq!(env(&[("TERM", "xterm")]), ColorDepth::Palette16);
+        assert_eq!(env(&[]), ColorDepth::Palette16);
+        assert_eq!(
+            env(&[("NO_COLOR", "1"), ("COLORTERM", "24bit")]),
+            ColorDepth::Monochrome
+        );
+        assert_eq!(env(&[("NO_COLOR", "")]), ColorDepth::Palette16);
+
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(ColorDepth::TrueColor.fit(orange), orange);
+        assert_eq!(ColorDepth::Palette256.fit(orange), Color::Indexed(208));
+        assert_eq!(
+            ColorDepth::Palette256.fit(Color::Rgb(250, 250, 250)),
+            Color::Indexed(231)
+        );
+        assert_eq!(
+            ColorDepth::Palette256.fit(Color::Rgb(12, 20, 31)),
+            Color::Indexed(233)
+        );
+        assert_eq!(ColorDepth::Palette16.fit(orange), Color::Yellow);
+        assert_eq!(
+            ColorDepth::Palette16.fit(Color::Indexed(196)),
+            Color::LightRed
+        );
+        assert_eq!(ColorDepth::Palette16.fit(Color::Indexed(4)), Color::Blue);
+        assert_eq!(ColorDepth::Palette16.fit(Color::Cyan), Color::Cyan);
+        assert_eq!(ColorDepth::Monochrome.fit(orange), Color::Reset);
+
+        let registry = ThemeRegistry::default().with_color_depth(ColorDepth::Palette16);
+        let light = registry.get(4);
+        assert_eq!(light.name, "light");
+        assert_eq!(light.background, Color::White);
+        assert_eq!(light.text, Color::Black);
+        assert_eq!(registry.source(4), &Theme::light());
+        assert_eq!(
+            light.highlight(light.primary),
+            Style::default().fg(Color::White).bg(light.primary)
+        );
+        let registry = ThemeRegistry::default().with_color_depth(ColorDepth::Monochrome);
+        let quote = registry.get(0).quote_style();
+        assert_eq!(
+            quote,
+            Style::default()
+                .fg(Color::Reset)
+                .add_modifier(Modifier::ITALIC)
+        );
+        assert_eq!(
+            registry.get(0).highlight(Color::Green),
+            Style::default().add_modifier(Modifier::REVERSED)
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use crate::ai::aliyun::AliYunModelType;
+use crate::ai::deepseek::{create_system_message, create_user_message};
+use crate::ai::traffic::TrafficLog;
+use crate::ai::{Prompt, Providers, stream_reply};
+use crate::ai_models::AIModel;
+use crate::app::{Message, Sender};
+use crate::i18n::Language;
+use crate::storage::Conversation;
+
+pub const TITLE_MODEL_ENV_VAR: &str = "AICHAT_TITLE_MODEL";
+/// How much of each message the title model gets to see.
+const EXCERPT_MAX_CHARS: usize = 1000;
+
+/// The model that writes conversation titles, from the `title_model` setting or
+/// `AICHAT_TITLE_MODEL`; Qwen-Turbo when unset. `off` turns automatic titles off, and
+/// so does a model without a real API, whose title would only be simulated.
+pub fn parse_model(name: &str) -> Option<AIModel> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Some(AIModel::AliYun(AliYunModelType::QwenTurbo));
+    }
+    if matches!(name.to_lowercase().as_str(), "off" | "none") {
+        return None;
+    }
+    let model = AIModel::from_api_name(name);
+    model.is_real_api().then_some(model)
+}
+
+fn excerpt(text: &str) -> String {
+    text.chars().take(EXCERPT_MAX_CHARS).collect()
+}
+
+/// The request for a title of the first exchange in `messages`, or `None` until the
+/// first user message has a reply.
+pub fn prompt(messages: &[Message], language: Language) -> Option<Prompt> {
+    let mut exchange = messages.iter().skip_while(|msg| msg.sender != Sender::User);
+    let question = exchange.next()?;
+    let answer = exchange.find(|msg| matches!(msg.sender, Sender::AI(_)))?;
+    let language_name = match language {
+        Language::Chinese => "Chinese",
+        Language::English => "English",
+    };
+    Some(Prompt {
+        messages: vec![
+            create_system_message(&format!(
+                "Write a title of at most six words for the conversation below, in {}. \
+                 Reply with the title only.",
+                language_name
+            )),
+            create_user_message(&format!(
+                "User: {}\n\nAssistant: {}",
+                excerpt(&question.content),
+                excerpt(&answer.content)
+            )),
+        ],
+        temperature: Some(0.3),
+    })
+}
+
+/// The first line of the model's reply without Markdown, a `Title:` label, quotes or
+/// closing punctuation, shortened to fit the title bar.
+pub fn clean(reply: &str) -> Option<String> {
+    let line = reply.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line.trim_matches(|c| c == '#' || c == '*').trim();
+    let line = ["Title:", "title:", "标题：", "标题:"]
+        .iter()
+        .find_map(|label| line.strip_prefix(label))
+        .unwrap_or(line)
+        .trim();
+    let closing = ['.', '。', '!', '！'];
+    let line = line
+        .trim_end_matches(closing)
+        .trim_matches(|c| "\"'“”‘’「」《》`".contains(c))
+        .trim_end_matches(closing)
+        .trim();
+    (!line.is_empty()).then(|| Conversation::shorten_title(line))
+}
+
+/// Asks `model` for a title. `None` when the request fails, so the session keeps its
+/// fallback title.
+pub async fn request(
+    model: &AIModel,
+    prompt: &Prompt,
+    language: Language,
+    providers: &Providers,
+    traffic: &TrafficLog,
+) -> Option<String> {
+    let reply = stream_reply(
+        model,
+        prompt,
+        Some(language),
+        providers,
+        Some(traffic),
+        |_| {},
+        |_| {},
+    )
+    .await?;
+    clean(&reply.ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_model() {
+        assert_eq!(
+            parse_model(""),
+            Some(AIModel::AliYun(AliYunModelType::QwenTurbo))
+        );
+        assert_eq!(parse_model("deepseek-chat"), Some(AIModel::DeepSeek));
+        assert_eq!(
+            parse_model("qwenplus"),
+            Some(AIModel::AliYun(AliYunModelType::QwenPlus))
+        );
+        assert_eq!(parse_model("OFF"), None);
+        assert_eq!(parse_model("claude"), None);
+    }
+
+    #[test]
+    fn test_clean_strips_decoration() {
+        assert_eq!(
+            clean("\n**Title: \"Sorting a Vec in Rust.\"**\nextra").as_deref(),
+            Some("Sorting a Vec in Rust")
+        );
+        assert_eq!(clean("《异步任务调度》。").as_deref(), Some("异步任务调度"));
+        assert_eq!(clean("  \n\"\"").as_deref(), None);
+    }
+}
@@ -1,82 +1,21 @@
 use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, BorderType, Borders, Clear, Padding, Paragraph, Wrap},
+    widgets::{Block, BorderType, Borders, Clear, Padding, Paragraph, Tabs, Wrap},
 };
 
+use crate::ai::{DEFAULT_TEMPERATURE, Provider};
 use crate::i18n::Language;
+use crate::keystore::{KeyEntry, KeySource, mask};
+use crate::search::{DateFilter, SenderFilter, match_ranges};
+use crate::theme::Theme;
 use crate::{
     ai_models::AIModel,
     app::{App, AppState, InputMode, Sender},
 };
 
-pub struct Theme {
-    pub primary: Color,
-    pub secondary: Color,
-    pub accent: Color,
-    pub background: Color,
-    pub text: Color,
-    pub success: Color,
-    pub warning: Color,
-    pub error: Color,
-}
-
-impl Theme {
-    pub fn deep_blue() -> Self {
-        Theme {
-            primary: Color::Rgb(0, 119, 190),
-            secondary: Color::Rgb(0, 180, 216),
-            accent: Color::Rgb(144, 224, 239),
-            background: Color::Rgb(12, 20, 31),
-            text: Color::Rgb(230, 240, 255),
-            success: Color::Rgb(46, 204, 113),
-            warning: Color::Rgb(241, 196, 15),
-            error: Color::Rgb(231, 76, 60),
-        }
-    }
-
-    pub fn forest_green() -> Self {
-        Theme {
-            primary: Color::Rgb(46, 125, 50),
-            secondary: Color::Rgb(76, 175, 80),
-            accent: Color::Rgb(165, 214, 167),
-            background: Color::Rgb(24, 30, 24),
-            text: Color::Rgb(240, 255, 240),
-            success: Color::Rgb(56, 142, 60),
-            warning: Color::Rgb(255, 193, 7),
-            error: Color::Rgb(244, 67, 54),
-        }
-    }
-
-    pub fn sunset() -> Self {
-        Theme {
-            primary: Color::Rgb(233, 69, 96),
-            secondary: Color::Rgb(255, 119, 34),
-            accent: Color::Rgb(255, 190, 11),
-            background: Color::Rgb(29, 23, 40),
-            text: Color::Rgb(255, 240, 230),
-            success: Color::Rgb(46, 204, 113),
-            warning: Color::Rgb(241, 196, 15),
-            error: Color::Rgb(231, 76, 60),
-        }
-    }
-
-    pub fn neon() -> Self {
-        Theme {
-            primary: Color::Rgb(255, 0, 255),
-            secondary: Color::Rgb(0, 255, 255),
-            accent: Color::Rgb(255, 255, 0),
-            background: Color::Rgb(0, 0, 20),
-            text: Color::Rgb(255, 255, 255),
-            success: Color::Rgb(0, 255, 128),
-            warning: Color::Rgb(255, 128, 0),
-            error: Color::Rgb(255, 0, 128),
-        }
-    }
-}
-
 pub fn render(app: &mut App, frame: &mut Frame) {
     match app.app_state {
         AppState::Welcome => {
@@ -84,29 +23,26 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         }
         AppState::Chatting => {
             if app.show_help {
-                render_help_modal(app, frame, frame.size());
+                render_help_modal(app, frame, frame.area());
             } else {
                 render_chat_interface(app, frame);
             }
         }
         AppState::Help => {
-            render_help_modal(app, frame, frame.size());
+            render_help_modal(app, frame, frame.area());
         }
     }
+    if let Some(journal) = &app.recovery {
+        render_recovery_prompt(app, frame, frame.area(), journal.sessions.len());
+    }
     if let Some(notification) = &app.notification {
-        render_notification(app, frame, frame.size(), notification);
+        render_notification(app, frame, frame.area(), notification);
     }
 }
 
 fn render_welcome_page(app: &App, frame: &mut Frame) {
-    let theme = match app.theme_index {
-        0 => Theme::deep_blue(),
-        1 => Theme::forest_green(),
-        2 => Theme::sunset(),
-        3 => Theme::neon(),
-        _ => Theme::deep_blue(),
-    };
-    let area = frame.size();
+    let theme = app.theme();
+    let area = frame.area();
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -119,7 +55,7 @@ fn render_welcome_page(app: &App, frame: &mut Frame) {
         .borders(Borders::TOP | Borders::BOTTOM)
         .border_type(BorderType::Rounded)
         .border_style(Style::default().fg(theme.primary))
-        .style(Style::default().bg(theme.background));
+        .style(Style::default().fg(theme.text).bg(theme.background));
     let title_content = Paragraph::new(vec![
         Line::from(Span::styled(
             app.t("welcome_title"),
@@ -141,12 +77,12 @@ fn render_welcome_page(app: &App, frame: &mut Frame) {
         .borders(Borders::ALL)
         .border_type(BorderType::Double)
         .border_style(Style::default().fg(theme.accent))
-        .style(Style::default().bg(theme.background));
+        .style(Style::default().fg(theme.text).bg(theme.background));
     let features = vec![
         Line::from(""),
         Line::from(Span::styled(
             app.t("welcome_feature1"),
-            Style::default().fg(Color::Cyan),
+            Style::default().fg(theme.secondary),
         )),
         Line::from(Span::styled(
             "  ├─ DeepSeek, Aliyun Qwen, OpenAI",
@@ -159,28 +95,28 @@ fn render_welcome_page(app: &App, frame: &mut Frame) {
         Line::from(""),
         Line::from(Span::styled(
             app.t("welcome_feature2"),
-            Style::default().fg(Color::Magenta),
+            Style::default().fg(theme.primary),
         )),
         Line::from(Span::styled(
             "  ├─ Deep Blue Ocean",
-            Style::default().fg(Color::Blue),
+            Style::default().fg(app.themes.get(0).primary),
         )),
         Line::from(Span::styled(
             "  ├─ Forest Green",
-            Style::default().fg(Color::Green),
+            Style::default().fg(app.themes.get(1).primary),
         )),
         Line::from(Span::styled(
             "  ├─ Sunset Orange",
-            Style::default().fg(Color::Rgb(255, 165, 0)),
+            Style::default().fg(app.themes.get(2).primary),
         )),
         Line::from(Span::styled(
             "  ├─ Neon Cyber",
-            Style::default().fg(Color::Rgb(255, 0, 255)),
+            Style::default().fg(app.themes.get(3).primary),
         )),
         Line::from(""),
         Line::from(Span::styled(
             app.t("welcome_feature3"),
-            Style::default().fg(Color::Yellow),
+            Style::default().fg(theme.warning),
         )),
         Line::from(Span::styled(
             "  ├─ Press C for Chinese",
@@ -193,7 +129,7 @@ fn render_welcome_page(app: &App, frame: &mut Frame) {
         Line::from(""),
         Line::from(Span::styled(
             app.t("welcome_feature4"),
-            Style::default().fg(Color::Green),
+            Style::default().fg(theme.success),
         )),
         Line::from(Span::styled(
             "  ├─ Real-time message exchange",
@@ -206,7 +142,7 @@ fn render_welcome_page(app: &App, frame: &mut Frame) {
         Line::from(""),
         Line::from(Span::styled(
             app.t("welcome_feature5"),
-            Style::default().fg(Color::Red),
+            Style::default().fg(theme.error),
         )),
         Line::from(Span::styled(
             "  ├─ Arrow keys for navigation",
@@ -235,7 +171,7 @@ fn render_welcome_page(app: &App, frame: &mut Frame) {
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .border_style(Style::default().fg(theme.primary))
-        .style(Style::default().bg(theme.background));
+        .style(Style::default().fg(theme.text).bg(theme.background));
     let hint_content = Paragraph::new(vec![
         Line::from(Span::styled(
             app.t("welcome_start_hint"),
@@ -247,7 +183,7 @@ fn render_welcome_page(app: &App, frame: &mut Frame) {
         Line::from(Span::styled(
             app.t("welcome_press_enter"),
             Style::default()
-                .fg(Color::Green)
+                .fg(theme.success)
                 .add_modifier(Modifier::SLOW_BLINK)
                 .add_modifier(Modifier::BOLD),
         )),
@@ -258,31 +194,40 @@ fn render_welcome_page(app: &App, frame: &mut Frame) {
 }
 
 fn render_chat_interface(app: &mut App, frame: &mut Frame) {
-    let theme = match app.theme_index {
-        0 => Theme::deep_blue(),
-        1 => Theme::forest_green(),
-        2 => Theme::sunset(),
-        3 => Theme::neon(),
-        _ => Theme::deep_blue(),
-    };
+    let theme = app.theme();
     let main_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(4),
             Constraint::Length(3),
+            Constraint::Length(1),
             Constraint::Min(10),
             Constraint::Length(4),
         ])
-        .split(frame.size());
-    render_title_bar(app, frame, main_chunks[0], &theme);
-    render_model_selector(app, frame, main_chunks[1], &theme);
-    render_chat_area(app, frame, main_chunks[2], &theme);
-    render_input_area(app, frame, main_chunks[3], &theme);
+        .split(frame.area());
+    render_title_bar(app, frame, main_chunks[0], theme);
+    render_model_selector(app, frame, main_chunks[1], theme);
+    render_session_tabs(app, frame, main_chunks[2], theme);
+    if app.input_mode == InputMode::Searching {
+        render_search_results(app, frame, main_chunks[3], theme);
+    } else if matches!(app.input_mode, InputMode::Keys | InputMode::KeyEntry) {
+        render_keys(app, frame, main_chunks[3], theme);
+    } else if app.show_inspector {
+        let chat_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(main_chunks[3]);
+        render_chat_area(app, frame, chat_chunks[0], theme);
+        render_inspector(app, frame, chat_chunks[1], theme);
+    } else {
+        render_chat_area(app, frame, main_chunks[3], theme);
+    }
+    render_input_area(app, frame, main_chunks[4], theme);
 }
 
 fn render_title_bar(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
     let title = app.t("app_title");
-    let subtitle = app.t("app_subtitle");
+    let subtitle = app.title().unwrap_or_else(|| app.t("app_subtitle"));
     let title_block = Block::default()
         .borders(Borders::TOP | Borders::BOTTOM)
         .border_type(BorderType::Rounded)
@@ -304,6 +249,46 @@ fn render_title_bar(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
     frame.render_widget(title_content, area);
 }
 
+fn render_session_tabs(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
+    const TAB_TITLE_MAX_CHARS: usize = 18;
+    let titles: Vec<Line> = app
+        .sessions
+        .iter()
+        .enumerate()
+        .map(|(i, session)| {
+            let title = session.title().unwrap_or_else(|| app.t("session_untitled"));
+            let title = if title.chars().count() > TAB_TITLE_MAX_CHARS {
+                let short: String = title.chars().take(TAB_TITLE_MAX_CHARS - 1).collect();
+                format!("{}…", short)
+            } else {
+                title
+            };
+            let mut spans = vec![Span::raw(format!("{}:{}", i + 1, title))];
+            if session.has_pending() {
+                spans.push(Span::styled(" …", Style::default().fg(theme.secondary)));
+            } else if session.is_unread() && i != app.active_session {
+                spans.push(Span::styled(
+                    " ●",
+                    Style::default()
+                        .fg(theme.warning)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            Line::from(spans)
+        })
+        .collect();
+    let tabs = Tabs::new(titles)
+        .select(app.active_session)
+        .style(Style::default().fg(theme.secondary).bg(theme.background))
+        .highlight_style(
+            Style::default()
+                .fg(theme.primary)
+                .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+        )
+        .divider(Span::styled("│", Style::default().fg(theme.accent)));
+    frame.render_widget(tabs, area);
+}
+
 fn render_model_selector(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
     let horizontal_chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -318,42 +303,25 @@ fn render_model_selector(app: &App, frame: &mut Frame, area: Rect, theme: &Theme
     let language_area = horizontal_chunks[2];
     let max_visible = app.calculate_max_visible(models_area.width as usize);
     render_model_scrollable_selector(app, frame, models_area, theme, max_visible);
-    let themes = ["1", "2", "3", "4"];
-    let mut theme_spans = Vec::new();
-    theme_spans.push(Span::styled(
-        "Theme ",
-        Style::default()
-            .fg(theme.secondary)
-            .add_modifier(Modifier::BOLD),
-    ));
-    for (i, theme_num) in themes.iter().enumerate() {
-        let is_active = i == app.theme_index;
-        let style = if is_active {
-            Style::default()
-                .fg(theme.text)
-                .bg(theme.primary)
-                .add_modifier(Modifier::BOLD)
-        } else {
+    let theme_line = Line::from(vec![
+        Span::styled(
+            format!("{}/{} ", app.theme_index + 1, app.themes.len()),
             Style::default()
                 .fg(theme.secondary)
-                .add_modifier(Modifier::BOLD)
-        };
-        if i > 0 {
-            theme_spans.push(Span::styled(" ", Style::default()));
-        }
-        theme_spans.push(Span::styled(format!("{}", theme_num), style));
-    }
-    let theme_names = app.t("theme_names");
-    theme_spans.push(Span::styled(" ", Style::default()));
-    theme_spans.push(Span::styled(theme_names, Style::default().fg(theme.accent)));
-    let theme_line = Line::from(theme_spans);
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!(" {} ", theme.name),
+            theme.highlight(theme.primary).add_modifier(Modifier::BOLD),
+        ),
+    ]);
     let theme_block = Block::default()
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .border_style(Style::default().fg(theme.accent))
         .title(app.t("theme_selector_title"))
         .title_style(Style::default().fg(theme.primary))
-        .style(Style::default().bg(theme.background));
+        .style(Style::default().fg(theme.text).bg(theme.background));
     let theme_paragraph = Paragraph::new(theme_line)
         .alignment(ratatui::layout::Alignment::Center)
         .block(theme_block);
@@ -375,7 +343,7 @@ fn render_model_selector(app: &App, frame: &mut Frame, area: Rect, theme: &Theme
         .border_style(Style::default().fg(theme.accent))
         .title(app.t("language_selector"))
         .title_style(Style::default().fg(theme.primary))
-        .style(Style::default().bg(theme.background));
+        .style(Style::default().fg(theme.text).bg(theme.background));
     let language_paragraph = Paragraph::new(language_line)
         .alignment(ratatui::layout::Alignment::Center)
         .block(language_block);
@@ -390,15 +358,29 @@ fn render_model_scrollable_selector(
     max_visible: usize,
 ) {
     let total_models = app.ai_models.len();
-    let start_index = app.model_display_offset;
+    let start_index = app.session().model_display_offset;
     let end_index = (start_index + max_visible).min(total_models);
     let models_block = Block::default()
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .border_style(Style::default().fg(theme.accent))
         .title(app.t("model_selector_title"))
+        .title(
+            Line::from(match &app.session().persona {
+                Some(persona) => format!(
+                    " 🎭 {} · 🌡 {:.1} ",
+                    persona,
+                    app.session().temperature.unwrap_or(DEFAULT_TEMPERATURE)
+                ),
+                None => format!(
+                    " 🌡 {:.1} ",
+                    app.session().temperature.unwrap_or(DEFAULT_TEMPERATURE)
+                ),
+            })
+            .right_aligned(),
+        )
         .title_style(Style::default().fg(theme.primary))
-        .style(Style::default().bg(theme.background));
+        .style(Style::default().fg(theme.text).bg(theme.background));
     let mut model_spans = Vec::new();
     if start_index > 0 {
         model_spans.push(Span::styled(
@@ -410,8 +392,8 @@ fn render_model_scrollable_selector(
     }
     for i in start_index..end_index {
         let model = &app.ai_models[i];
-        let is_selected = i == app.selected_model_index;
-        let color = model.color();
+        let is_selected = i == app.session().selected_model_index;
+        let color = theme.paint(model.color());
         if i > start_index {
             model_spans.push(Span::styled("│", Style::default().fg(theme.accent)));
         }
@@ -430,10 +412,7 @@ fn render_model_scrollable_selector(
             model_name
         };
         let style = if is_selected {
-            Style::default()
-                .fg(Color::Black)
-                .bg(color)
-                .add_modifier(Modifier::BOLD)
+            theme.highlight(color).add_modifier(Modifier::BOLD)
         } else {
             Style::default().fg(color).add_modifier(Modifier::BOLD)
         };
@@ -459,7 +438,7 @@ fn render_chat_area(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .border_style(Style::default().fg(theme.primary))
-        .style(Style::default().bg(theme.background));
+        .style(Style::default().fg(theme.text).bg(theme.background));
     frame.render_widget(chat_block, area);
     let inner_area = Rect {
         x: area.x + 1,
@@ -471,7 +450,7 @@ fn render_chat_area(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
 }
 
 fn render_messages(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
-    let messages = app.messages.lock().unwrap();
+    let messages = app.session().messages.lock().unwrap();
     if messages.is_empty() {
         let empty_text = Paragraph::new(app.t("chat_empty"))
             .style(Style::default().fg(theme.secondary))
@@ -479,30 +458,99 @@ fn render_messages(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
         frame.render_widget(empty_text, area);
         return;
     }
+    let highlight = app.session().highlight.as_ref();
+    let match_style = theme.highlight(theme.warning).add_modifier(Modifier::BOLD);
     let mut lines = Vec::new();
-    for msg in messages.iter() {
+    for (index, msg) in messages.iter().enumerate() {
+        let terms = highlight
+            .filter(|highlight| highlight.message_index == index)
+            .map(|highlight| highlight.terms.as_slice());
         let prefix = match &msg.sender {
             Sender::User => "👤 ",
             Sender::AI(_) => "🤖 ",
             Sender::Thinking(_) => "🤔 ",
+            Sender::Error(_) => "⚠️ ",
+        };
+        let (prefix_style, body_style, gutter) = match &msg.sender {
+            Sender::Error(_) => (
+                Style::default()
+                    .fg(theme.error)
+                    .add_modifier(Modifier::BOLD),
+                theme.error_style(),
+                "  ┃ ",
+            ),
+            _ => (
+                Style::default().fg(theme.primary),
+                Style::default().fg(theme.text),
+                "  ",
+            ),
         };
         let timestamp = msg.timestamp.format("%H:%M").to_string();
-        lines.push(Line::from(vec![
+        let mut header = vec![
             Span::styled(
                 format!("[{}] ", timestamp),
                 Style::default().fg(theme.accent),
             ),
-            Span::styled(prefix, Style::default().fg(theme.primary)),
-        ]));
+            Span::styled(prefix, prefix_style),
+        ];
+        if let (Some(_), Sender::AI(model) | Sender::Error(model)) = (&msg.branches, &msg.sender) {
+            header.push(Span::styled(
+                model.name(app.language),
+                Style::default().fg(theme.paint(model.color())),
+            ));
+        }
+        if let Some(branches) = &msg.branches {
+            header.push(Span::styled(
+                format!(" ‹ {}/{} › ", branches.position(), branches.count()),
+                Style::default()
+                    .fg(theme.secondary)
+                    .add_modifier(Modifier::BOLD),
+            ));
+        }
+        if msg.pinned {
+            header.push(Span::styled(" 📌", Style::default().fg(theme.warning)));
+        }
+        if terms.is_some() {
+            header.push(Span::styled("◀", Style::default().fg(theme.warning)));
+        }
+        if app.input_mode == InputMode::Selecting && index == app.selected_message {
+            header.insert(
+                0,
+                Span::styled(
+                    "▶ ",
+                    Style::default()
+                        .fg(theme.accent)
+                        .add_modifier(Modifier::BOLD),
+                ),
+            );
+            for span in &mut header {
+                span.style = span.style.add_modifier(Modifier::REVERSED);
+            }
+        }
+        lines.push(Line::from(header));
+        let mut in_code = false;
         for line in msg.content.lines() {
-            lines.push(Line::from(Span::styled(
-                format!("  {}", line),
-                Style::default().fg(theme.text),
-            )));
+            let fence = line.trim_start().starts_with("```");
+            let style = if matches!(msg.sender, Sender::Error(_)) {
+                body_style
+            } else if in_code || fence {
+                theme.code_style()
+            } else if line.starts_with('>') {
+                theme.quote_style()
+            } else {
+                body_style
+            };
+            in_code ^= fence;
+            let mut spans = vec![Span::styled(gutter, body_style)];
+            match terms {
+                Some(terms) => spans.extend(highlighted_spans(line, terms, style, match_style)),
+                None => spans.push(Span::styled(line.to_string(), style)),
+            }
+            lines.push(Line::from(spans));
         }
         lines.push(Line::from(""));
     }
-    let scroll_offset = if app.auto_scroll {
+    let scroll_offset = if app.session().auto_scroll {
         let total_lines = lines.len();
         let viewport_height = area.height as usize;
         if total_lines > viewport_height {
@@ -511,7 +559,7 @@ fn render_messages(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
             0
         }
     } else {
-        let selected_index = app.ai_list_state.selected().unwrap_or(0);
+        let selected_index = app.session().ai_list_state.selected().unwrap_or(0);
         (selected_index * 3).min(lines.len().saturating_sub(1)) as u16
     };
     let paragraph = Paragraph::new(lines)
@@ -521,42 +569,442 @@ fn render_messages(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
     frame.render_widget(paragraph, area);
 }
 
+/// `text` split into spans, with every occurrence of `terms` in `match_style`.
+fn highlighted_spans(
+    text: &str,
+    terms: &[String],
+    style: Style,
+    match_style: Style,
+) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut last = 0;
+    for range in match_ranges(text, terms) {
+        if range.start > last {
+            spans.push(Span::styled(text[last..range.start].to_string(), style));
+        }
+        spans.push(Span::styled(text[range.clone()].to_string(), match_style));
+        last = range.end;
+    }
+    if last < text.len() {
+        spans.push(Span::styled(text[last..].to_string(), style));
+    }
+    spans
+}
+
+fn render_search_results(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
+    let search = &app.search;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme.accent))
+        .title(app.t("search_title"))
+        .title_style(Style::default().fg(theme.primary))
+        .title_bottom(app.t("search_hint"))
+        .style(Style::default().fg(theme.text).bg(theme.background));
+    let model = search
+        .filter
+        .model
+        .as_ref()
+        .map(|model| model.name(app.language))
+        .unwrap_or_else(|| app.t("search_any"));
+    let sender = match search.filter.sender {
+        SenderFilter::Any => app.t("search_any"),
+        SenderFilter::User => app.t("search_sender_user"),
+        SenderFilter::AI => app.t("search_sender_ai"),
+    };
+    let date = match search.filter.date {
+        DateFilter::Any => app.t("search_any"),
+        DateFilter::Today => app.t("search_date_today"),
+        DateFilter::Week => app.t("search_date_week"),
+        DateFilter::Month => app.t("search_date_month"),
+    };
+    let label = Style::default().fg(theme.secondary);
+    let value = Style::default()
+        .fg(theme.accent)
+        .add_modifier(Modifier::BOLD);
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled(format!("{} ", app.t("search_filter_model")), label),
+            Span::styled(model, value),
+            Span::styled(format!("  {} ", app.t("search_filter_sender")), label),
+            Span::styled(sender, value),
+            Span::styled(format!("  {} ", app.t("search_filter_date")), label),
+            Span::styled(date, value),
+            Span::styled(
+                format!("  · {} {}", search.results.len(), app.t("search_results")),
+                label,
+            ),
+        ]),
+        Line::from(""),
+    ];
+    if search.results.is_empty() {
+        let message = if search.query.trim().is_empty() {
+            app.t("search_prompt")
+        } else {
+            app.t("search_no_results")
+        };
+        lines.push(Line::from(Span::styled(message, label)));
+    }
+    let terms = crate::search::tokenize(&search.query);
+    let match_style = theme.highlight(theme.warning).add_modifier(Modifier::BOLD);
+    for (i, hit) in search.results.iter().enumerate() {
+        let selected = i == search.selected;
+        let marker = if selected { "▶ " } else { "  " };
+        let header_style = if selected {
+            theme.highlight(theme.accent).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.primary)
+        };
+        let speaker = match &hit.sender {
+            Sender::User => format!("👤 {}", app.t("search_sender_user")),
+            Sender::AI(model) | Sender::Thinking(model) | Sender::Error(model) => {
+                format!("🤖 {}", model.name(app.language))
+            }
+        };
+        lines.push(Line::from(Span::styled(
+            format!(
+                "{}[{}] {} · {}",
+                marker,
+                hit.timestamp.format("%Y-%m-%d %H:%M"),
+                speaker,
+                hit.title
+            ),
+            header_style,
+        )));
+        let mut snippet = vec![Span::raw("    ")];
+        snippet.extend(highlighted_spans(
+            &hit.snippet,
+            &terms,
+            Style::default().fg(theme.text),
+            match_style,
+        ));
+        lines.push(Line::from(snippet));
+    }
+    // Two lines per result below the two header lines; keep the selection in view.
+    let inner_height = area.height.saturating_sub(2) as usize;
+    let selected_bottom = 2 + search.selected * 2 + 2;
+    let scroll = selected_bottom.saturating_sub(inner_height) as u16;
+    let paragraph = Paragraph::new(lines).block(block).scroll((scroll, 0));
+    frame.render_widget(paragraph, area);
+}
+
+fn render_inspector(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme.accent))
+        .title(app.t("inspector_title"))
+        .title_style(Style::default().fg(theme.primary))
+        .title_bottom(app.t("inspector_hint"))
+        .style(Style::default().fg(theme.text).bg(theme.background));
+    let exchanges = app.traffic.snapshot();
+    if exchanges.is_empty() {
+        let empty = Paragraph::new(app.t("inspector_empty"))
+            .style(Style::default().fg(theme.secondary))
+            .alignment(ratatui::layout::Alignment::Center)
+            .block(block);
+        frame.render_widget(empty, area);
+        return;
+    }
+    let selected = app.inspector_selected.min(exchanges.len() - 1);
+    let mut lines = Vec::new();
+    for (i, exchange) in exchanges.iter().enumerate() {
+        let status = match (exchange.status, &exchange.error) {
+            (_, Some(_)) => "ERR".to_string(),
+            (Some(status), None) => status.to_string(),
+            (None, None) => "...".to_string(),
+        };
+        let duration = exchange
+            .duration_ms
+            .map(|ms| format!("{}ms", ms))
+            .unwrap_or_else(|| "...".to_string());
+        let style = if i == selected {
+            theme.highlight(theme.accent).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.text)
+        };
+        lines.push(Line::from(Span::styled(
+            format!(
+                "#{} {} {} {} {}",
+                exchange.id,
+                exchange.started_at.format("%H:%M:%S"),
+                exchange.provider,
+                status,
+                duration
+            ),
+            style,
+        )));
+    }
+    let exchange = &exchanges[selected];
+    let label = Style::default()
+        .fg(theme.secondary)
+        .add_modifier(Modifier::BOLD);
+    let body = Style::default().fg(theme.text);
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled("POST ", label),
+        Span::styled(exchange.endpoint.clone(), body),
+    ]));
+    for (name, value) in &exchange.request_headers {
+        lines.push(Line::from(Span::styled(
+            format!("{}: {}", name, value),
+            body,
+        )));
+    }
+    lines.push(Line::from(Span::styled(app.t("inspector_request"), label)));
+    for line in exchange.request_body.lines() {
+        lines.push(Line::from(Span::styled(line.to_string(), body)));
+    }
+    lines.push(Line::from(Span::styled(
+        format!(
+            "{} {}",
+            app.t("inspector_response"),
+            exchange
+                .status
+                .map(|status| status.to_string())
+                .unwrap_or_default()
+        ),
+        label,
+    )));
+    if let Some(error) = &exchange.error {
+        lines.push(Line::from(Span::styled(
+            error.clone(),
+            Style::default().fg(theme.error),
+        )));
+    }
+    if let Some(response_body) = &exchange.response_body {
+        for line in response_body.lines() {
+            lines.push(Line::from(Span::styled(line.to_string(), body)));
+        }
+    }
+    for sse in exchange.sse_lines.iter().filter(|sse| !sse.line.is_empty()) {
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("+{}ms ", sse.elapsed_ms),
+                Style::default().fg(theme.accent),
+            ),
+            Span::styled(sse.line.clone(), body),
+        ]));
+    }
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, area);
+}
+
+/// `text` as lines with `cursor` drawn at byte offset `at`. In place of the
+/// character there when `over` is set, before it otherwise.
+fn with_cursor<'a>(text: &'a str, at: usize, cursor: Style, over: bool) -> Vec<Line<'a>> {
+    let (before, after) = text.split_at(at);
+    let mut lines: Vec<Line> = before.split('\n').map(Line::from).collect();
+    let under = after.chars().next().filter(|c| over && *c != '\n');
+    let after = &after[under.map_or(0, char::len_utf8)..];
+    let symbol = match under {
+        Some(c) => c.to_string(),
+        None if over => " ".to_string(),
+        None => "█".to_string(),
+    };
+    let mut rest = after.split('\n');
+    if let Some(line) = lines.last_mut() {
+        line.spans.push(Span::styled(symbol, cursor));
+        if let Some(first) = rest.next() {
+            line.spans.push(Span::raw(first));
+        }
+    }
+    lines.extend(rest.map(Line::from));
+    lines
+}
+
+/// The profiles, then one row per provider with its key masked, the command that
+/// gives it, or the environment variable used instead.
+fn render_keys(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
+    let Some(store) = &app.key_store else {
+        return;
+    };
+    let screen = &app.keys_screen;
+    let mut profiles = vec![Span::styled(
+        app.t("keys_profiles"),
+        Style::default()
+            .fg(theme.secondary)
+            .add_modifier(Modifier::BOLD),
+    )];
+    for name in store.keyring.profiles.keys() {
+        let label = if *name == store.keyring.active {
+            format!(" ● {} ", name)
+        } else {
+            format!(" {} ", name)
+        };
+        let style = if *name == screen.profile {
+            theme.highlight(theme.primary).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.text)
+        };
+        profiles.push(Span::raw(" "));
+        profiles.push(Span::styled(label, style));
+    }
+    let mut lines = vec![Line::from(profiles), Line::from("")];
+    let profile = store.keyring.profiles.get(&screen.profile);
+    let in_use = screen.profile == store.keyring.active;
+    for (i, provider) in Provider::ALL.iter().enumerate() {
+        let selected = i == screen.selected;
+        let name_style = if selected {
+            theme.highlight(theme.accent).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.primary)
+        };
+        let (value, status) = match profile.and_then(|profile| profile.get(provider)) {
+            Some(KeySource::Stored(key)) => (
+                mask(key),
+                Some(
+                    provider
+                        .check_key(key)
+                        .map_err(|problem| problem.localized(&app.translations)),
+                ),
+            ),
+            Some(KeySource::Command(command)) => (
+                format!("$ {}", command),
+                in_use.then(|| match screen.errors.get(provider) {
+                    Some(e) => Err(e.clone()),
+                    None => Ok(()),
+                }),
+            ),
+            None => (
+                format!(
+                    "{} ${}",
+                    app.t("keys_from_env"),
+                    app.providers.get(*provider).api_key_env
+                ),
+                None,
+            ),
+        };
+        let mut spans = vec![
+            Span::raw(if selected { "▶ " } else { "  " }),
+            Span::styled(format!(" {:<10}", provider.name()), name_style),
+            Span::raw("  "),
+            Span::styled(value, Style::default().fg(theme.text)),
+        ];
+        match status {
+            Some(Ok(())) => spans.push(Span::styled("  ✓", Style::default().fg(theme.success))),
+            Some(Err(e)) => spans.push(Span::styled(
+                format!("  ✗ {}", e),
+                Style::default().fg(theme.error),
+            )),
+            None => {}
+        }
+        lines.push(Line::from(spans));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        app.t("keys_hint"),
+        Style::default().fg(theme.secondary),
+    )));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme.primary))
+        .title(app.t("keys_title"))
+        .title_style(
+            Style::default()
+                .fg(theme.primary)
+                .add_modifier(Modifier::BOLD),
+        )
+        .style(Style::default().fg(theme.text).bg(theme.background));
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, area);
+}
+
 fn render_input_area(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
     let input_block = Block::default()
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .border_style(match app.input_mode {
-            InputMode::Normal => Style::default().fg(theme.accent),
-            InputMode::Editing => Style::default().fg(Color::Yellow),
+            InputMode::Normal | InputMode::Selecting | InputMode::Keys => {
+                Style::default().fg(theme.accent)
+            }
+            InputMode::Editing
+            | InputMode::EditingNormal
+            | InputMode::Renaming
+            | InputMode::Searching
+            | InputMode::Command
+            | InputMode::KeyEntry => Style::default().fg(theme.warning),
         })
         .title(match app.input_mode {
             InputMode::Normal => app.t("input_hint_normal"),
+            InputMode::Editing if app.editing_message.is_some() => {
+                app.t("input_hint_editing_message")
+            }
             InputMode::Editing => app.t("input_hint_editing"),
+            InputMode::EditingNormal => app.t("input_hint_editing_normal"),
+            InputMode::Selecting => app.t("input_hint_selecting"),
+            InputMode::Renaming => app.t("input_hint_renaming"),
+            InputMode::Searching => app.t("input_hint_searching"),
+            InputMode::Command => app.t("input_hint_command"),
+            InputMode::Keys => app.t("input_hint_keys"),
+            InputMode::KeyEntry => match app.keys_screen.entry {
+                Some(KeyEntry::Command) => app.t("input_hint_key_entry_command"),
+                Some(KeyEntry::Profile) => app.t("input_hint_key_entry_profile"),
+                Some(KeyEntry::Passphrase) => app.t("input_hint_key_entry_passphrase"),
+                Some(KeyEntry::Key) | None => app.t("input_hint_key_entry_key"),
+            },
         })
-        .title_style(Style::default().fg(Color::Cyan))
-        .style(Style::default().bg(theme.background));
-    let display_text = if app.input.is_empty() {
-        match app.input_mode {
-            InputMode::Normal => "".to_string(),
-            InputMode::Editing => {
-                if app.cursor_blink_state {
-                    format!("{}█", app.input)
-                } else {
-                    format!("{} ", app.input)
-                }
-            }
+        .title_style(Style::default().fg(theme.secondary))
+        .style(Style::default().fg(theme.text).bg(theme.background));
+    let shown;
+    let text = match app.input_mode {
+        InputMode::Renaming => &app.rename_input,
+        InputMode::Searching => &app.search.query,
+        InputMode::Command => {
+            shown = format!(":{}", app.command_input);
+            &shown
         }
-    } else {
-        if app.cursor_blink_state && app.input_mode == InputMode::Editing {
-            format!("{}█", app.input)
-        } else {
-            format!("{} ", app.input)
+        InputMode::Keys => {
+            shown = String::new();
+            &shown
+        }
+        InputMode::KeyEntry => match app.keys_screen.entry {
+            Some(KeyEntry::Key | KeyEntry::Passphrase) => {
+                shown = "•".repeat(app.keys_screen.input.chars().count());
+                &shown
+            }
+            _ => &app.keys_screen.input,
+        },
+        _ => &app.input,
+    };
+    let at = match app.input_mode {
+        InputMode::Editing | InputMode::EditingNormal => app.cursor(),
+        _ => text.len(),
+    };
+    let display_text = match app.input_mode {
+        InputMode::EditingNormal => with_cursor(
+            text,
+            at,
+            Style::default().add_modifier(Modifier::REVERSED),
+            true,
+        ),
+        InputMode::Editing
+        | InputMode::Renaming
+        | InputMode::Searching
+        | InputMode::Command
+        | InputMode::KeyEntry
+            if app.cursor_blink_state =>
+        {
+            with_cursor(text, at, Style::default(), false)
         }
+        _ => text.split('\n').map(Line::from).collect(),
     };
     let input_style = match app.input_mode {
-        InputMode::Normal => Style::default().fg(theme.text),
-        InputMode::Editing => Style::default()
-            .fg(Color::Yellow)
+        InputMode::Normal | InputMode::Selecting | InputMode::Keys => {
+            Style::default().fg(theme.text)
+        }
+        InputMode::Editing
+        | InputMode::EditingNormal
+        | InputMode::Renaming
+        | InputMode::Searching
+        | InputMode::Command
+        | InputMode::KeyEntry => Style::default()
+            .fg(theme.warning)
             .add_modifier(Modifier::BOLD),
     };
     let input_paragraph = Paragraph::new(display_text)
@@ -567,7 +1015,7 @@ fn render_input_area(app: &App, frame: &mut Frame, area: Rect, theme: &Theme) {
 }
 
 fn render_help_modal(app: &App, frame: &mut Frame, area: Rect) {
-    let theme = Theme::deep_blue();
+    let theme = app.theme();
     let block = Block::default()
         .borders(Borders::ALL)
         .border_type(BorderType::Double)
@@ -575,10 +1023,10 @@ fn render_help_modal(app: &App, frame: &mut Frame, area: Rect) {
         .title(app.t("help_title"))
         .title_style(
             Style::default()
-                .fg(Color::Yellow)
+                .fg(theme.warning)
                 .add_modifier(Modifier::BOLD),
         )
-        .style(Style::default().bg(Color::Black));
+        .style(Style::default().fg(theme.text).bg(theme.background));
     let text = vec![
         Line::from(Span::styled(
             match app.language {
@@ -586,14 +1034,14 @@ fn render_help_modal(app: &App, frame: &mut Frame, area: Rect) {
                 Language::English => "AI Chat Terminal - User Guide",
             },
             Style::default()
-                .fg(Color::Cyan)
+                .fg(theme.secondary)
                 .add_modifier(Modifier::BOLD),
         )),
         Line::from(""),
         Line::from(Span::styled(
             app.t("help_nav_title"),
             Style::default()
-                .fg(Color::Green)
+                .fg(theme.success)
                 .add_modifier(Modifier::BOLD),
         )),
         Line::from(app.t("help_nav_line1")),
@@ -606,11 +1054,21 @@ fn render_help_modal(app: &App, frame: &mut Frame, area: Rect) {
         Line::from(app.t("help_nav_line8")),
         Line::from(app.t("help_nav_line9")),
         Line::from(app.t("help_nav_line10")),
+        Line::from(app.t("help_nav_line11")),
+        Line::from(app.t("help_nav_line12")),
+        Line::from(app.t("help_nav_line13")),
+        Line::from(app.t("help_nav_line14")),
+        Line::from(app.t("help_nav_line15")),
+        Line::from(app.t("help_nav_line16")),
+        Line::from(app.t("help_nav_line17")),
+        Line::from(app.t("help_nav_line18")),
+        Line::from(app.t("help_nav_line19")),
+        Line::from(app.t("help_nav_line20")),
         Line::from(""),
         Line::from(Span::styled(
             app.t("help_edit_title"),
             Style::default()
-                .fg(Color::Yellow)
+                .fg(theme.warning)
                 .add_modifier(Modifier::BOLD),
         )),
         Line::from(app.t("help_edit_line1")),
@@ -621,7 +1079,7 @@ fn render_help_modal(app: &App, frame: &mut Frame, area: Rect) {
         Line::from(Span::styled(
             app.t("help_theme_title"),
             Style::default()
-                .fg(Color::Magenta)
+                .fg(theme.primary)
                 .add_modifier(Modifier::BOLD),
         )),
         Line::from(app.t("help_theme_line1")),
@@ -632,7 +1090,7 @@ fn render_help_modal(app: &App, frame: &mut Frame, area: Rect) {
         Line::from(Span::styled(
             app.t("help_tips_title"),
             Style::default()
-                .fg(Color::Cyan)
+                .fg(theme.secondary)
                 .add_modifier(Modifier::BOLD),
         )),
         Line::from(app.t("help_tips_line1")),
@@ -643,7 +1101,7 @@ fn render_help_modal(app: &App, frame: &mut Frame, area: Rect) {
         Line::from(Span::styled(
             app.t("help_close_hint"),
             Style::default()
-                .fg(Color::Gray)
+                .fg(theme.secondary)
                 .add_modifier(Modifier::ITALIC),
         )),
     ];
@@ -654,18 +1112,12 @@ fn render_help_modal(app: &App, frame: &mut Frame, area: Rect) {
 }
 
 fn render_notification(app: &App, frame: &mut Frame, area: Rect, notification: &str) {
-    let theme = match app.theme_index {
-        0 => Theme::deep_blue(),
-        1 => Theme::forest_green(),
-        2 => Theme::sunset(),
-        3 => Theme::neon(),
-        _ => Theme::deep_blue(),
-    };
+    let theme = app.theme();
     let notification_text = vec![
         Line::from(Span::styled(
             app.t("notification_title"),
             Style::default()
-                .fg(Color::Yellow)
+                .fg(theme.warning)
                 .add_modifier(Modifier::BOLD),
         )),
         Line::from(""),
@@ -673,7 +1125,7 @@ fn render_notification(app: &App, frame: &mut Frame, area: Rect, notification: &
         Line::from(""),
         Line::from(Span::styled(
             app.t("notification_continue"),
-            Style::default().fg(Color::Gray),
+            Style::default().fg(theme.secondary),
         )),
     ];
     let block = Block::default()
@@ -681,7 +1133,7 @@ fn render_notification(app: &App, frame: &mut Frame, area: Rect, notification: &
         .border_type(BorderType::Rounded)
         .border_style(Style::default().fg(theme.primary))
         .title("🔔")
-        .style(Style::default().bg(theme.background));
+        .style(Style::default().fg(theme.text).bg(theme.background));
     let paragraph = Paragraph::new(notification_text)
         .block(block)
         .wrap(Wrap { trim: true });
@@ -690,6 +1142,36 @@ fn render_notification(app: &App, frame: &mut Frame, area: Rect, notification: &
     frame.render_widget(paragraph, notification_area);
 }
 
+fn render_recovery_prompt(app: &App, frame: &mut Frame, area: Rect, count: usize) {
+    let theme = app.theme();
+    let text = vec![
+        Line::from(Span::styled(
+            app.t("recovery_title"),
+            Style::default()
+                .fg(theme.warning)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(app.t("recovery_message")),
+        Line::from(format!("{}: {}", app.t("recovery_count"), count)),
+        Line::from(""),
+        Line::from(Span::styled(
+            app.t("recovery_hint"),
+            Style::default().fg(theme.secondary),
+        )),
+    ];
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme.warning))
+        .title("💾")
+        .style(Style::default().fg(theme.text).bg(theme.background));
+    let paragraph = Paragraph::new(text).block(block).wrap(Wrap { trim: true });
+    let prompt_area = centered_rect(60, 30, area);
+    frame.render_widget(Clear, prompt_area);
+    frame.render_widget(paragraph, prompt_area);
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -708,15 +1190,3 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         ])
         .split(popup_layout[1])[1]
 }
-
-fn darken_color(color: Color, factor: f32) -> Color {
-    match color {
-        Color::Rgb(r, g, b) => {
-            let r = (r as f32 * factor) as u8;
-            let g = (g as f32 * factor) as u8;
-            let b = (b as f32 * factor) as u8;
-            Color::Rgb(r, g, b)
-        }
-        _ => color,
-    }
-}